@@ -0,0 +1,246 @@
+use candles_cache::{
+    BidAskToHandle, BidOrAsk, CandleData, CandleModel, CandleType, CandlesInstrumentsCache,
+    GetCandleDateKey,
+};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use rust_extensions::date_time::{DateTimeAsMicroseconds, MICRO_SECONDS_IN_ONE_MINUTE};
+
+const INSTRUMENTS_AMOUNT: usize = 2_000;
+const RETENTION_CAP: usize = 300;
+const QUOTES_PER_ITERATION: usize = 10_000;
+
+/// Deterministic random walk of quotes across many instruments.
+struct QuotesGenerator {
+    instruments: Vec<String>,
+    prices: Vec<f64>,
+    time_stamp: DateTimeAsMicroseconds,
+    step_us: i64,
+    seed: u64,
+}
+
+impl QuotesGenerator {
+    fn new(instruments_amount: usize, start: DateTimeAsMicroseconds, step_us: i64) -> Self {
+        Self {
+            instruments: (0..instruments_amount)
+                .map(|index| format!("INSTR{:04}", index))
+                .collect(),
+            prices: (0..instruments_amount)
+                .map(|index| 1.0 + index as f64 * 0.01)
+                .collect(),
+            time_stamp: start,
+            step_us,
+            seed: 0x2545F4914F6CDD1D,
+        }
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+
+    fn next_quote(&mut self) -> (usize, f64, f64, DateTimeAsMicroseconds) {
+        let random = self.next_random();
+        let index = (random % self.instruments.len() as u64) as usize;
+
+        let change = ((random >> 32) % 21) as f64 - 10.0;
+        let price = &mut self.prices[index];
+        *price = (*price * (1.0 + change * 0.00001)).max(0.0001);
+
+        self.time_stamp =
+            DateTimeAsMicroseconds::new(self.time_stamp.unix_microseconds + self.step_us);
+
+        (index, *price, *price * 1.0002, self.time_stamp)
+    }
+}
+
+fn get_start() -> DateTimeAsMicroseconds {
+    DateTimeAsMicroseconds::parse_iso_string("2021-01-04T00:00:00.000000Z").unwrap()
+}
+
+fn generate_candles(start: DateTimeAsMicroseconds, amount: usize, price: f64) -> Vec<CandleModel> {
+    (0..amount)
+        .map(|index| {
+            let time_stamp = DateTimeAsMicroseconds::new(
+                start.unix_microseconds + index as i64 * MICRO_SECONDS_IN_ONE_MINUTE,
+            );
+
+            CandleModel {
+                date_key: time_stamp.into_candle_date_key(CandleType::Minute),
+                data: CandleData::new_from_price(price, 0.0),
+            }
+        })
+        .collect()
+}
+
+/// Cache with every instrument filled with minute candles up to `candles_amount`.
+fn create_filled_cache(
+    generator: &QuotesGenerator,
+    candles_amount: usize,
+) -> CandlesInstrumentsCache {
+    let mut cache = CandlesInstrumentsCache::new();
+    let start = get_start();
+
+    for (instrument, price) in generator.instruments.iter().zip(&generator.prices) {
+        for bid_or_ask in [BidOrAsk::Bid, BidOrAsk::Ask] {
+            cache.init_candles(
+                bid_or_ask,
+                instrument,
+                CandleType::Minute,
+                generate_candles(start, candles_amount, *price).into_iter(),
+                Some(RETENTION_CAP),
+            );
+        }
+    }
+
+    cache
+}
+
+fn handle_bid_ask(c: &mut Criterion) {
+    let mut generator = QuotesGenerator::new(INSTRUMENTS_AMOUNT, get_start(), 2_000);
+    let mut cache = create_filled_cache(&generator, RETENTION_CAP);
+
+    let mut group = c.benchmark_group("handle_bid_ask");
+    group.throughput(Throughput::Elements(QUOTES_PER_ITERATION as u64));
+
+    group.bench_function("2000_instruments", |b| {
+        b.iter(|| {
+            for _ in 0..QUOTES_PER_ITERATION {
+                let (index, bid, ask, time_stamp) = generator.next_quote();
+
+                black_box(cache.handle_bid_ask(
+                    &generator.instruments[index],
+                    bid,
+                    ask,
+                    time_stamp,
+                    RETENTION_CAP,
+                ));
+            }
+        })
+    });
+
+    let instruments = generator.instruments.clone();
+
+    group.bench_function("2000_instruments_batch", |b| {
+        b.iter_batched(
+            || {
+                (0..QUOTES_PER_ITERATION)
+                    .map(|_| generator.next_quote())
+                    .collect::<Vec<_>>()
+            },
+            |quotes| {
+                let quotes: Vec<BidAskToHandle> = quotes
+                    .iter()
+                    .map(|(index, bid, ask, time_stamp)| BidAskToHandle {
+                        instrument_id: &instruments[*index],
+                        bid: *bid,
+                        ask: *ask,
+                        volume: 0.0,
+                        time_stamp: *time_stamp,
+                    })
+                    .collect();
+
+                black_box(cache.handle_bid_ask_batch(&quotes, RETENTION_CAP));
+            },
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn queries(c: &mut Criterion) {
+    let mut generator = QuotesGenerator::new(INSTRUMENTS_AMOUNT, get_start(), 2_000);
+    let cache = create_filled_cache(&generator, RETENTION_CAP);
+
+    let start = get_start();
+    let last = DateTimeAsMicroseconds::new(
+        start.unix_microseconds + (RETENTION_CAP as i64 - 1) * MICRO_SECONDS_IN_ONE_MINUTE,
+    );
+    let from =
+        DateTimeAsMicroseconds::new(last.unix_microseconds - 60 * MICRO_SECONDS_IN_ONE_MINUTE)
+            .into_candle_date_key(CandleType::Minute);
+    let to = last.into_candle_date_key(CandleType::Minute);
+
+    c.bench_function("get_in_date_range/60_minutes", |b| {
+        b.iter(|| {
+            let index = generator.next_random() as usize % INSTRUMENTS_AMOUNT;
+
+            black_box(cache.get_in_date_range(
+                &generator.instruments[index],
+                from,
+                to,
+                CandleType::Minute,
+                BidOrAsk::Bid,
+            ));
+        })
+    });
+
+    c.bench_function("get_highest_and_below/100_candles", |b| {
+        b.iter(|| {
+            let index = generator.next_random() as usize % INSTRUMENTS_AMOUNT;
+
+            black_box(cache.get_highest_and_below(
+                BidOrAsk::Ask,
+                &generator.instruments[index],
+                CandleType::Minute,
+                to,
+                100,
+            ));
+        })
+    });
+}
+
+fn maintenance(c: &mut Criterion) {
+    let generator = QuotesGenerator::new(INSTRUMENTS_AMOUNT, get_start(), 2_000);
+
+    let mut group = c.benchmark_group("maintenance");
+    group.sample_size(10);
+
+    group.bench_function("gc_candles/2000_instruments", |b| {
+        b.iter_batched(
+            || create_filled_cache(&generator, RETENTION_CAP * 2),
+            |mut cache| {
+                cache.gc_candles(CandleType::Minute, RETENTION_CAP);
+                cache
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    let candles: Vec<Vec<CandleModel>> = generator
+        .prices
+        .iter()
+        .map(|price| generate_candles(get_start(), RETENTION_CAP, *price))
+        .collect();
+
+    group.throughput(Throughput::Elements(
+        (INSTRUMENTS_AMOUNT * RETENTION_CAP) as u64,
+    ));
+
+    group.bench_function("init_candles/2000_instruments", |b| {
+        b.iter_batched(
+            || (CandlesInstrumentsCache::new(), candles.clone()),
+            |(mut cache, candles)| {
+                for (instrument, candles) in generator.instruments.iter().zip(candles) {
+                    cache.init_candles(
+                        BidOrAsk::Bid,
+                        instrument,
+                        CandleType::Minute,
+                        candles.into_iter(),
+                        Some(RETENTION_CAP),
+                    );
+                }
+
+                cache
+            },
+            BatchSize::PerIteration,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, handle_bid_ask, queries, maintenance);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+use candles_cache::{CandleType, CandlesInstrumentsCache, GetCandleDateKey};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+const QUOTES_AMOUNT: u64 = 10_000;
+const MAX_CANDLES_AMOUNT: usize = 1_000;
+
+fn into_candle_date_key(c: &mut Criterion) {
+    let date_time =
+        DateTimeAsMicroseconds::parse_iso_string("2021-01-01T10:22:33.000000Z").unwrap();
+
+    c.bench_function("into_candle_date_key", |b| {
+        b.iter(|| {
+            for candle_type in CandleType::ALL_CANDLE_TYPES {
+                black_box(black_box(date_time).into_candle_date_key(candle_type));
+            }
+        })
+    });
+}
+
+fn handle_bid_ask(c: &mut Criterion) {
+    let start = DateTimeAsMicroseconds::parse_iso_string("2021-01-01T00:00:00.000000Z").unwrap();
+
+    let mut group = c.benchmark_group("handle_bid_ask");
+    group.throughput(Throughput::Elements(QUOTES_AMOUNT));
+
+    group.bench_function("one_instrument", |b| {
+        b.iter(|| {
+            let mut cache = CandlesInstrumentsCache::new();
+
+            for index in 0..QUOTES_AMOUNT {
+                let price = 1.1 + (index % 100) as f64 * 0.0001;
+                let time_stamp =
+                    DateTimeAsMicroseconds::new(start.unix_microseconds + index as i64 * 250_000);
+
+                black_box(cache.handle_bid_ask(
+                    "EURUSD",
+                    price,
+                    price + 0.0002,
+                    time_stamp,
+                    MAX_CANDLES_AMOUNT,
+                ));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, into_candle_date_key, handle_bid_ask);
+criterion_main!(benches);
@@ -0,0 +1,116 @@
+//! Replays a tick file through the cache and prints the resulting candles,
+//! or the difference against a reference candle dump.
+//!
+//! Usage: candles_replay <ticks.csv|ticks.bin> [--max-candles N] [--reference dump.csv] [--tolerance X]
+
+use std::{fs::File, io::BufReader, process::ExitCode};
+
+use candles_cache::{
+    diff_candle_dumps, dump_candles, read_candle_dump, read_ticks, replay_ticks, write_candle_dump,
+    CandleDiff, CandlesInstrumentsCache, TickFileFormat,
+};
+
+const DEFAULT_MAX_CANDLES_AMOUNT: usize = 100_000;
+
+struct Args {
+    ticks_file: String,
+    max_candles_amount: usize,
+    reference_file: Option<String>,
+    tolerance: f64,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut ticks_file = None;
+    let mut max_candles_amount = DEFAULT_MAX_CANDLES_AMOUNT;
+    let mut reference_file = None;
+    let mut tolerance = 1e-9;
+
+    let mut args = std::env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("{} requires a value", arg))
+        };
+
+        match arg.as_str() {
+            "--max-candles" => {
+                max_candles_amount = value()?
+                    .parse()
+                    .map_err(|_| "Invalid --max-candles".to_string())?
+            }
+            "--reference" => reference_file = Some(value()?),
+            "--tolerance" => {
+                tolerance = value()?
+                    .parse()
+                    .map_err(|_| "Invalid --tolerance".to_string())?
+            }
+            _ if ticks_file.is_none() && !arg.starts_with("--") => ticks_file = Some(arg),
+            _ => return Err(format!("Unknown argument {}", arg)),
+        }
+    }
+
+    Ok(Args {
+        ticks_file: ticks_file.ok_or_else(|| "Ticks file is not specified".to_string())?,
+        max_candles_amount,
+        reference_file,
+        tolerance,
+    })
+}
+
+fn open(path: &str) -> Result<BufReader<File>, String> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| format!("Can not open {}: {}", path, err))
+}
+
+fn run() -> Result<bool, String> {
+    let args = parse_args()?;
+
+    let ticks = read_ticks(
+        open(&args.ticks_file)?,
+        TickFileFormat::from_path(&args.ticks_file),
+    )?;
+
+    let mut cache = CandlesInstrumentsCache::new();
+    let handled = replay_ticks(&mut cache, ticks.into_iter(), args.max_candles_amount);
+    eprintln!("Replayed {} ticks", handled);
+
+    let actual = dump_candles(&cache);
+
+    let reference_file = match args.reference_file {
+        Some(reference_file) => reference_file,
+        None => {
+            write_candle_dump(std::io::stdout().lock(), actual.iter())?;
+            return Ok(true);
+        }
+    };
+
+    let expected = read_candle_dump(open(&reference_file)?)?;
+    let diff = diff_candle_dumps(&expected, &actual, args.tolerance);
+
+    for item in &diff {
+        match item {
+            CandleDiff::Missing(record) => println!("- {:?}", record),
+            CandleDiff::Unexpected(record) => println!("+ {:?}", record),
+            CandleDiff::Different { expected, actual } => {
+                println!("- {:?}\n+ {:?}", expected, actual)
+            }
+        }
+    }
+
+    eprintln!("{} differences", diff.len());
+
+    Ok(diff.is_empty())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::from(2)
+        }
+    }
+}
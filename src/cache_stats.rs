@@ -0,0 +1,93 @@
+use crate::{BidOrAsk, CandleDateKey, CandleType};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct IngestionCounters {
+    /// Ticks which got into the candles.
+    pub ticks_processed: u64,
+    /// Ticks rejected by the tick filter.
+    pub ticks_dropped: u64,
+    /// Candles removed to keep the retention cap.
+    pub gc_evictions: u64,
+}
+
+impl IngestionCounters {
+    pub fn merge(&mut self, other: &IngestionCounters) {
+        self.ticks_processed += other.ticks_processed;
+        self.ticks_dropped += other.ticks_dropped;
+        self.gc_evictions += other.gc_evictions;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CandleTypeStats {
+    pub candle_type: CandleType,
+    pub candles_amount: usize,
+    pub capacity: usize,
+    pub first_key: Option<CandleDateKey>,
+    pub last_key: Option<CandleDateKey>,
+    pub gc_evictions: u64,
+    /// Allocated candles and Heikin-Ashi storage, not only the used part of it.
+    pub estimated_bytes: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct InstrumentStats {
+    pub instrument_id: String,
+    pub bid_or_ask: BidOrAsk,
+    /// Ordered by candle type.
+    pub by_type: Vec<CandleTypeStats>,
+    /// Memory of the map of the candle types.
+    pub estimated_overhead_bytes: usize,
+}
+
+impl InstrumentStats {
+    pub fn get_candles_amount(&self) -> usize {
+        self.by_type.iter().map(|stats| stats.candles_amount).sum()
+    }
+
+    pub fn get_estimated_bytes(&self) -> usize {
+        self.by_type
+            .iter()
+            .map(|stats| stats.estimated_bytes)
+            .sum::<usize>()
+            + self.estimated_overhead_bytes
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CacheStats {
+    pub instruments_amount: usize,
+    pub candles_amount: usize,
+    /// Estimation of the memory taken by the candles, tick buffers, indicators and non time bars.
+    pub estimated_bytes: usize,
+    pub estimated_candles_bytes: usize,
+    pub estimated_tick_buffers_bytes: usize,
+    /// Values of the indicators. The state of the calculations is not counted.
+    pub estimated_indicators_bytes: usize,
+    pub estimated_non_time_bars_bytes: usize,
+    /// Ordered by instrument and side.
+    pub instruments: Vec<InstrumentStats>,
+    pub counters: IngestionCounters,
+}
+
+impl CacheStats {
+    pub fn merge(&mut self, other: CacheStats) {
+        self.instruments_amount += other.instruments_amount;
+        self.candles_amount += other.candles_amount;
+        self.estimated_bytes += other.estimated_bytes;
+        self.estimated_candles_bytes += other.estimated_candles_bytes;
+        self.estimated_tick_buffers_bytes += other.estimated_tick_buffers_bytes;
+        self.estimated_indicators_bytes += other.estimated_indicators_bytes;
+        self.estimated_non_time_bars_bytes += other.estimated_non_time_bars_bytes;
+        self.counters.merge(&other.counters);
+        self.instruments.extend(other.instruments);
+        self.sort_instruments();
+    }
+
+    pub(crate) fn sort_instruments(&mut self) {
+        self.instruments.sort_by(|a, b| {
+            (a.instrument_id.as_str(), !a.bid_or_ask.to_is_bid())
+                .cmp(&(b.instrument_id.as_str(), !b.bid_or_ask.to_is_bid()))
+        });
+    }
+}
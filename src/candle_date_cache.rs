@@ -1,10 +1,25 @@
 use rust_extensions::sorted_vec::*;
 
-use crate::{CandleData, CandleDateKey, CandleModel, CandleType};
+use crate::{
+    to_heikin_ashi, CandleData, CandleDateKey, CandleModel, CandleType, CandleTypeStats,
+    ClosedCandle, FinalizeCandlesSettings, HeikinAshiView, TradingCalendar,
+};
 
 pub struct CandleDateCache {
     pub candles: SortedVec<u64, CandleModel>,
     pub candle_type: CandleType,
+    /// All the periods up to this key (inclusive) are already reported as closed.
+    pub closed_up_to: Option<CandleDateKey>,
+    /// Candles up to this key (inclusive) may be missing since they were evicted or never loaded.
+    pub incomplete_up_to: Option<CandleDateKey>,
+    /// Amount of candles removed by [`Self::gc_candles`].
+    pub gc_evictions: u64,
+    /// Fixed-point mode: prices of every candle are rounded to this amount of digits.
+    pub price_digits: Option<u32>,
+    /// Heikin-Ashi data of every cached candle, at the same index.
+    pub(crate) heikin_ashi: Vec<CandleData>,
+    /// Heikin-Ashi data of the last evicted candle, so the series keeps its values after gc.
+    heikin_ashi_seed: Option<CandleModel>,
 }
 
 impl CandleDateCache {
@@ -12,6 +27,12 @@ impl CandleDateCache {
         Self {
             candles: SortedVec::new(),
             candle_type,
+            closed_up_to: None,
+            incomplete_up_to: None,
+            gc_evictions: 0,
+            price_digits: None,
+            heikin_ashi: Vec::new(),
+            heikin_ashi_seed: None,
         }
     }
 
@@ -21,8 +42,24 @@ impl CandleDateCache {
         }
     }
 
+    /// Switches fixed-point mode, rounding the cached candles.
+    pub fn set_price_digits(&mut self, price_digits: Option<u32>) {
+        self.price_digits = price_digits;
+
+        if let Some(digits) = price_digits {
+            self.update_all(|candle| candle.data.round_to_digits(digits));
+        }
+    }
+
     pub fn insert_or_update(&mut self, candle_to_load: CandleModel) {
-        let model: CandleModel = candle_to_load.into();
+        let mut model: CandleModel = candle_to_load.into();
+
+        if let Some(digits) = self.price_digits {
+            model.data.round_to_digits(digits);
+        }
+
+        let date_key = model.date_key;
+
         match self
             .candles
             .insert_or_update(model.get_candle_date_key().as_ref())
@@ -30,6 +67,8 @@ impl CandleDateCache {
             InsertOrUpdateEntry::Insert(entry) => entry.insert(model),
             InsertOrUpdateEntry::Update(entry) => entry.item.data = model.data,
         }
+
+        self.update_heikin_ashi_from(date_key);
     }
 
     pub fn get_in_date_range(&self, from: CandleDateKey, to: CandleDateKey) -> &[CandleModel] {
@@ -67,9 +106,9 @@ impl CandleDateCache {
             self.gc_candles(max_candles_amount);
         }
 
-        match self.candles.insert_or_update(date_key.as_ref()) {
+        let result = match self.candles.insert_or_update(date_key.as_ref()) {
             InsertOrUpdateEntry::Insert(entry) => {
-                let data = CandleData::new_from_price(price, 0.0);
+                let data = CandleData::new_from_price_with_digits(price, 0.0, self.price_digits);
                 let candle = CandleModel {
                     date_key,
                     data: data.clone(),
@@ -79,19 +118,216 @@ impl CandleDateCache {
 
                 entry.insert(candle.clone());
 
-                return result;
+                result
             }
 
             InsertOrUpdateEntry::Update(entry) => {
-                entry.item.data.update_from_price(price, 0.0);
-                return entry.item.data.clone();
+                entry
+                    .item
+                    .data
+                    .update_from_price_with_digits(price, 0.0, self.price_digits);
+                entry.item.data.clone()
             }
-        }
+        };
+
+        self.update_heikin_ashi_from(date_key);
+
+        result
+    }
+
+    pub fn remove(&mut self, date_key: CandleDateKey) -> Option<CandleModel> {
+        let index = self
+            .candles
+            .as_slice()
+            .binary_search_by_key(&date_key, |candle| candle.date_key)
+            .ok()?;
+
+        let removed = self.candles.remove_at(index);
+        self.update_heikin_ashi_from(date_key);
+
+        removed
     }
 
     pub fn gc_candles(&mut self, max_candles_amount: usize) {
         while self.candles.len() > max_candles_amount {
-            self.candles.remove_at(0);
+            if let Some(removed) = self.candles.remove_at(0) {
+                if !self.heikin_ashi.is_empty() {
+                    self.heikin_ashi_seed = Some(CandleModel {
+                        date_key: removed.date_key,
+                        data: self.heikin_ashi.remove(0),
+                    });
+                }
+
+                self.gc_evictions += 1;
+                self.mark_incomplete_up_to(removed.date_key);
+            }
+        }
+    }
+
+    /// Remembers that candles before the loaded one may be missing.
+    pub fn mark_loaded(&mut self, date_key: CandleDateKey) {
+        self.mark_incomplete_up_to(date_key.get_prev_period_date_key(self.candle_type));
+    }
+
+    fn mark_incomplete_up_to(&mut self, date_key: CandleDateKey) {
+        match self.incomplete_up_to {
+            Some(current) if current >= date_key => {}
+            _ => self.incomplete_up_to = Some(date_key),
+        }
+    }
+
+    /// Reports candles of the periods which are over by the time the period `current_key` is running.
+    /// Every period is reported once. The first call reports only the latest candle before `current_key`.
+    /// Empty candles are synthesized only for the periods the calendar is open.
+    pub fn finalize(
+        &mut self,
+        current_key: CandleDateKey,
+        settings: &FinalizeCandlesSettings,
+        calendar: Option<&TradingCalendar>,
+    ) -> Vec<ClosedCandle> {
+        let mut result = Vec::new();
+
+        let first_key = match self.closed_up_to {
+            Some(closed_up_to) => closed_up_to.get_next_period_date_key(self.candle_type),
+            None => match self.get_last_candle_below(current_key) {
+                Some(candle) => candle.date_key,
+                None => return result,
+            },
+        };
+
+        if first_key >= current_key {
+            return result;
+        }
+
+        if !settings.synthesize_empty_candles {
+            for candle in self.get_in_date_range(first_key, current_key) {
+                result.push(ClosedCandle {
+                    candle: candle.clone(),
+                    synthesized: false,
+                });
+            }
+
+            self.closed_up_to = Some(current_key.get_prev_period_date_key(self.candle_type));
+            return result;
+        }
+
+        let mut prev_close = self
+            .get_last_candle_below(first_key)
+            .map(|candle| candle.data.close);
+
+        let mut date_key = first_key;
+        let mut periods = 0;
+
+        while date_key < current_key && periods < settings.max_periods_per_series {
+            match self.get_candle(date_key) {
+                Some(candle) => {
+                    prev_close = Some(candle.data.close);
+                    result.push(ClosedCandle {
+                        candle,
+                        synthesized: false,
+                    });
+                }
+                None => {
+                    let in_session = match calendar {
+                        Some(calendar) => calendar.is_in_session(self.candle_type, date_key),
+                        None => true,
+                    };
+
+                    if let Some(prev_close) = prev_close.filter(|_| in_session) {
+                        let candle = CandleModel {
+                            date_key,
+                            data: CandleData::new_from_price(prev_close, 0.0),
+                        };
+
+                        self.insert_or_update(candle.clone());
+
+                        result.push(ClosedCandle {
+                            candle,
+                            synthesized: true,
+                        });
+                    }
+                }
+            }
+
+            self.closed_up_to = Some(date_key);
+            date_key = date_key.get_next_period_date_key(self.candle_type);
+            periods += 1;
+        }
+
+        self.gc_candles(settings.max_candles_amount);
+
+        result
+    }
+
+    fn get_last_candle_below(&self, date_key: CandleDateKey) -> Option<&CandleModel> {
+        let candles = self.candles.as_slice();
+        let index = candles.partition_point(|candle| candle.date_key < date_key);
+
+        if index == 0 {
+            return None;
+        }
+
+        candles.get(index - 1)
+    }
+
+    /// Applies the update to every cached candle. The key of the candle must stay the same.
+    pub fn update_all(&mut self, mut update: impl FnMut(&mut CandleModel)) {
+        let keys: Vec<u64> = self
+            .candles
+            .as_slice()
+            .iter()
+            .map(|candle| candle.date_key.get_value())
+            .collect();
+
+        for key in keys {
+            if let InsertOrUpdateEntry::Update(entry) = self.candles.insert_or_update(&key) {
+                update(entry.item);
+            }
+        }
+
+        self.heikin_ashi.clear();
+        self.update_heikin_ashi_from(CandleDateKey::new(0));
+    }
+
+    /// Applies the update to the Heikin-Ashi data of the last evicted candle. Must be followed
+    /// by [`Self::update_all`] so the cached candles are recalculated from the new seed.
+    pub fn update_heikin_ashi_seed(&mut self, update: impl FnOnce(&mut CandleModel)) {
+        if let Some(seed) = self.heikin_ashi_seed.as_mut() {
+            update(seed);
+        }
+    }
+
+    /// Recalculates Heikin-Ashi data of the candles starting from the key. Every later candle
+    /// depends on the previous one, so for the live candle it is a single step.
+    fn update_heikin_ashi_from(&mut self, date_key: CandleDateKey) {
+        let candles = self.candles.as_slice();
+        let index = candles
+            .partition_point(|candle| candle.date_key < date_key)
+            .min(self.heikin_ashi.len());
+
+        self.heikin_ashi.truncate(index);
+
+        for candle in &candles[index..] {
+            let seed = self.heikin_ashi_seed.as_ref().map(|seed| &seed.data);
+            let prev = self.heikin_ashi.last().or(seed);
+            let data = to_heikin_ashi(prev, &candle.data);
+            self.heikin_ashi.push(data);
+        }
+    }
+
+    pub fn get_stats(&self) -> CandleTypeStats {
+        let candles = self.candles.as_slice();
+
+        CandleTypeStats {
+            candle_type: self.candle_type,
+            candles_amount: candles.len(),
+            capacity: self.candles.capacity(),
+            first_key: candles.first().map(|candle| candle.date_key),
+            last_key: candles.last().map(|candle| candle.date_key),
+            gc_evictions: self.gc_evictions,
+            estimated_bytes: std::mem::size_of::<Self>()
+                + self.candles.capacity() * std::mem::size_of::<CandleModel>()
+                + self.heikin_ashi.capacity() * std::mem::size_of::<CandleData>(),
         }
     }
 
@@ -99,6 +335,10 @@ impl CandleDateCache {
         self.candles.first()
     }
 
+    pub fn heikin_ashi(&self) -> HeikinAshiView<'_> {
+        HeikinAshiView::new(self)
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &CandleModel> {
         self.candles.as_slice().iter()
     }
@@ -0,0 +1,218 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex, MutexGuard, Weak},
+    time::Duration,
+};
+
+use crate::{BidOrAsk, CandleModel, CandleToPersist, CandleType, InstrumentId};
+
+#[derive(Debug, Clone)]
+pub struct CandleUpdate {
+    pub instrument_id: InstrumentId,
+    pub bid_or_ask: BidOrAsk,
+    pub candle_type: CandleType,
+    pub candle: CandleModel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandleSubscriptionError {
+    /// Subscriber was too slow and the given amount of the oldest updates was dropped.
+    /// Receiving continues from the oldest update still buffered.
+    Lagged(u64),
+    /// The cache was dropped and all the buffered updates are received.
+    Closed,
+}
+
+struct SubscriptionQueue {
+    updates: VecDeque<CandleUpdate>,
+    lagged: u64,
+    closed: bool,
+}
+
+struct SubscriptionInner {
+    queue: Mutex<SubscriptionQueue>,
+    notify: Condvar,
+    buffer_size: usize,
+}
+
+impl SubscriptionInner {
+    fn lock(&self) -> MutexGuard<'_, SubscriptionQueue> {
+        self.queue.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn push(&self, update: CandleUpdate) {
+        let mut queue = self.lock();
+
+        if queue.updates.len() >= self.buffer_size {
+            queue.updates.pop_front();
+            queue.lagged += 1;
+        }
+
+        queue.updates.push_back(update);
+        self.notify.notify_one();
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.notify.notify_all();
+    }
+}
+
+/// Receiving side of a subscription created by [`crate::CandlesInstrumentsCache::subscribe`].
+/// Dropping it unsubscribes.
+pub struct CandleSubscription {
+    inner: Arc<SubscriptionInner>,
+}
+
+impl CandleSubscription {
+    fn receive(
+        queue: &mut SubscriptionQueue,
+    ) -> Result<Option<CandleUpdate>, CandleSubscriptionError> {
+        if queue.lagged > 0 {
+            let lagged = queue.lagged;
+            queue.lagged = 0;
+            return Err(CandleSubscriptionError::Lagged(lagged));
+        }
+
+        if let Some(update) = queue.updates.pop_front() {
+            return Ok(Some(update));
+        }
+
+        if queue.closed {
+            return Err(CandleSubscriptionError::Closed);
+        }
+
+        Ok(None)
+    }
+
+    /// Returns `Ok(None)` if there is no update buffered at the moment.
+    pub fn try_recv(&self) -> Result<Option<CandleUpdate>, CandleSubscriptionError> {
+        let mut queue = self.inner.lock();
+        Self::receive(&mut queue)
+    }
+
+    /// Waits for an update up to the timeout. Returns `Ok(None)` if the timeout elapsed.
+    pub fn recv_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<Option<CandleUpdate>, CandleSubscriptionError> {
+        let queue = self.inner.lock();
+
+        let (mut queue, _) = self
+            .inner
+            .notify
+            .wait_timeout_while(queue, timeout, |queue| {
+                queue.updates.is_empty() && queue.lagged == 0 && !queue.closed
+            })
+            .unwrap_or_else(|err| err.into_inner());
+
+        Self::receive(&mut queue)
+    }
+
+    pub fn get_buffered_amount(&self) -> usize {
+        self.inner.lock().updates.len()
+    }
+}
+
+/// Subscriptions by (instrument, bid/ask, candle type).
+pub struct CandleSubscribers {
+    subscriptions: HashMap<(InstrumentId, bool, u8), Vec<Weak<SubscriptionInner>>>,
+}
+
+impl CandleSubscribers {
+    pub fn new() -> Self {
+        Self {
+            subscriptions: HashMap::new(),
+        }
+    }
+
+    pub fn subscribe(
+        &mut self,
+        instrument_id: InstrumentId,
+        bid_or_ask: BidOrAsk,
+        candle_type: CandleType,
+        buffer_size: usize,
+    ) -> CandleSubscription {
+        let inner = Arc::new(SubscriptionInner {
+            queue: Mutex::new(SubscriptionQueue {
+                updates: VecDeque::new(),
+                lagged: 0,
+                closed: false,
+            }),
+            notify: Condvar::new(),
+            buffer_size: buffer_size.max(1),
+        });
+
+        self.subscriptions
+            .entry((instrument_id, bid_or_ask.to_is_bid(), candle_type.to_u8()))
+            .or_default()
+            .push(Arc::downgrade(&inner));
+
+        CandleSubscription { inner }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    /// Sends updates to the subscribers and forgets subscriptions which were dropped.
+    pub fn publish(
+        &mut self,
+        instrument_id: InstrumentId,
+        bid_or_ask: BidOrAsk,
+        candles: &[CandleToPersist],
+    ) {
+        if self.subscriptions.is_empty() {
+            return;
+        }
+
+        for candle in candles {
+            let key = (
+                instrument_id,
+                bid_or_ask.to_is_bid(),
+                candle.candle_type.to_u8(),
+            );
+
+            let subscriptions = match self.subscriptions.get_mut(&key) {
+                Some(subscriptions) => subscriptions,
+                None => continue,
+            };
+
+            subscriptions.retain(|subscription| match subscription.upgrade() {
+                Some(subscription) => {
+                    subscription.push(CandleUpdate {
+                        instrument_id,
+                        bid_or_ask,
+                        candle_type: candle.candle_type,
+                        candle: CandleModel {
+                            date_key: candle.date_key,
+                            data: candle.data,
+                        },
+                    });
+                    true
+                }
+                None => false,
+            });
+
+            if subscriptions.is_empty() {
+                self.subscriptions.remove(&key);
+            }
+        }
+    }
+}
+
+impl Default for CandleSubscribers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for CandleSubscribers {
+    fn drop(&mut self) {
+        for subscription in self.subscriptions.values().flatten() {
+            if let Some(subscription) = subscription.upgrade() {
+                subscription.close();
+            }
+        }
+    }
+}
@@ -1,19 +1,43 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use rust_extensions::date_time::DateTimeAsMicroseconds;
 
 use crate::{
-    CandleDateCache, CandleDateKey, CandleModel, CandleToPersist, CandleType, GetCandleDateKey,
+    CandleData, CandleDateCache, CandleDateKey, CandleModel, CandleToPersist, CandleType,
+    CandleTypeStats, ClosedCandle, FinalizeCandlesSettings, GetCandleDateKey, PriceAdjustment,
+    TradingCalendar,
 };
 
+#[derive(Debug, Clone, Copy)]
+pub struct CandleToDelete {
+    pub date_key: CandleDateKey,
+    pub candle_type: CandleType,
+}
+
 pub struct CandlesCacheByType {
     pub candles: HashMap<u8, CandleDateCache>,
+    /// Fixed-point mode: prices of every candle are rounded to this amount of digits.
+    pub price_digits: Option<u32>,
 }
 
 impl CandlesCacheByType {
     pub fn new() -> Self {
+        Self::with_price_digits(None)
+    }
+
+    pub fn with_price_digits(price_digits: Option<u32>) -> Self {
         Self {
             candles: HashMap::new(),
+            price_digits,
+        }
+    }
+
+    /// Switches fixed-point mode for every candle type, rounding the cached candles.
+    pub fn set_price_digits(&mut self, price_digits: Option<u32>) {
+        self.price_digits = price_digits;
+
+        for cache in self.candles.values_mut() {
+            cache.set_price_digits(price_digits);
         }
     }
 
@@ -28,16 +52,23 @@ impl CandlesCacheByType {
     ) -> &mut CandleDateCache {
         let candle_type_as_u8 = candle_type.to_u8();
         if !self.candles.contains_key(&candle_type_as_u8) {
-            let result = CandleDateCache::new(candle_type);
+            let mut result = CandleDateCache::new(candle_type);
+            result.price_digits = self.price_digits;
             self.candles.insert(candle_type.to_u8(), result);
         }
 
         self.candles.get_mut(&candle_type.to_u8()).unwrap()
     }
 
+    /// Inserts a candle loaded from outside. Candles before it are considered possibly missing.
     pub fn insert_or_update(&mut self, candle_type: CandleType, candle: CandleModel) {
-        self.get_or_create_by_candle_type_mut(candle_type)
-            .insert_or_update(candle)
+        let cache = self.get_or_create_by_candle_type_mut(candle_type);
+        cache.mark_loaded(candle.date_key);
+        cache.insert_or_update(candle)
+    }
+
+    pub fn get_by_type(&self, candle_type: CandleType) -> Option<&CandleDateCache> {
+        self.candles.get(&candle_type.to_u8())
     }
 
     pub fn get_first_candle(&self, candle_type: CandleType) -> Option<&CandleModel> {
@@ -62,6 +93,18 @@ impl CandlesCacheByType {
         price: f64,
         price_date: DateTimeAsMicroseconds,
         max_candles_amount: usize,
+    ) -> Vec<CandleToPersist> {
+        self.handle_new_price_with_adjustments(price, price_date, max_candles_amount, &[])
+    }
+
+    /// Same as [`Self::handle_new_price`] for a quote arriving after the price adjustments.
+    /// The adjustments which affect a candle are applied to the price, oldest first.
+    pub fn handle_new_price_with_adjustments(
+        &mut self,
+        price: f64,
+        price_date: DateTimeAsMicroseconds,
+        max_candles_amount: usize,
+        adjustments: &[PriceAdjustment],
     ) -> Vec<CandleToPersist> {
         let mut result = Vec::new();
 
@@ -69,6 +112,9 @@ impl CandlesCacheByType {
             let cache_data = self.get_or_create_by_candle_type_mut(candle_type);
 
             let date_key = price_date.into_candle_date_key(candle_type);
+            let price = adjustments.iter().fold(price, |price, adjustment| {
+                adjustment.apply_to_price(candle_type, date_key, price)
+            });
 
             let new_candle_data =
                 cache_data.handle_price(price, date_key, Some(max_candles_amount));
@@ -82,6 +128,61 @@ impl CandlesCacheByType {
         return result;
     }
 
+    /// Handles prices in the order they are given and returns only the latest state of every touched candle.
+    pub fn handle_new_prices(
+        &mut self,
+        prices: impl Iterator<Item = (f64, DateTimeAsMicroseconds)>,
+        max_candles_amount: usize,
+    ) -> Vec<CandleToPersist> {
+        self.handle_new_prices_with_adjustments(prices, max_candles_amount, &[])
+    }
+
+    /// Same as [`Self::handle_new_prices`] for quotes arriving after the price adjustments.
+    pub fn handle_new_prices_with_adjustments(
+        &mut self,
+        prices: impl Iterator<Item = (f64, DateTimeAsMicroseconds)>,
+        max_candles_amount: usize,
+        adjustments: &[PriceAdjustment],
+    ) -> Vec<CandleToPersist> {
+        let mut result = BTreeMap::new();
+
+        for (price, price_date) in prices {
+            for candle in self.handle_new_price_with_adjustments(
+                price,
+                price_date,
+                max_candles_amount,
+                adjustments,
+            ) {
+                result.insert(
+                    (candle.candle_type.to_u8(), candle.date_key.get_value()),
+                    candle,
+                );
+            }
+        }
+
+        result.into_values().collect()
+    }
+
+    pub fn finalize(
+        &mut self,
+        now: DateTimeAsMicroseconds,
+        settings: &FinalizeCandlesSettings,
+        calendar: Option<&TradingCalendar>,
+    ) -> Vec<(CandleType, ClosedCandle)> {
+        let mut result = Vec::new();
+
+        for cache in self.candles.values_mut() {
+            let candle_type = cache.candle_type;
+            let current_key = now.into_candle_date_key(candle_type);
+
+            for closed in cache.finalize(current_key, settings, calendar) {
+                result.push((candle_type, closed));
+            }
+        }
+
+        result
+    }
+
     pub fn get_in_date_range(
         &self,
         from: CandleDateKey,
@@ -132,6 +233,174 @@ impl CandlesCacheByType {
         None
     }
 
+    pub fn update_all(&mut self, mut update: impl FnMut(CandleType, &mut CandleModel)) {
+        for cache in self.candles.values_mut() {
+            let candle_type = cache.candle_type;
+            cache.update_all(|candle| update(candle_type, candle));
+        }
+    }
+
+    pub fn update_heikin_ashi_seeds(
+        &mut self,
+        mut update: impl FnMut(CandleType, &mut CandleModel),
+    ) {
+        for cache in self.candles.values_mut() {
+            let candle_type = cache.candle_type;
+            cache.update_heikin_ashi_seed(|seed| update(candle_type, seed));
+        }
+    }
+
+    /// Checks that every cached candle containing `time_stamp` above the minute one can be recomputed,
+    /// i.e. no candle of the type below within its period was evicted or left unloaded.
+    pub fn check_can_recompute(&self, time_stamp: DateTimeAsMicroseconds) -> Result<(), String> {
+        for window in CandleType::ALL_CANDLE_TYPES.windows(2) {
+            let (lower, candle_type) = (window[0], window[1]);
+            let date_key = time_stamp.into_candle_date_key(candle_type);
+
+            let exists = self
+                .get_by_type(candle_type)
+                .and_then(|cache| cache.get_candle(date_key))
+                .is_some();
+
+            if !exists {
+                continue;
+            }
+
+            let covered = match self.get_by_type(lower) {
+                Some(cache) => match cache.incomplete_up_to {
+                    Some(incomplete_up_to) => incomplete_up_to < date_key,
+                    None => true,
+                },
+                None => false,
+            };
+
+            if !covered {
+                return Err(format!(
+                    "{:?} candles do not cover the {:?} candle {}",
+                    lower,
+                    candle_type,
+                    date_key.get_value()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the minute candle containing `time_stamp` with the given data (`None` removes it)
+    /// and recomputes the candles of the other types containing it from the type below.
+    /// Returns changed and removed candles.
+    pub fn recompute(
+        &mut self,
+        time_stamp: DateTimeAsMicroseconds,
+        minute: Option<CandleData>,
+    ) -> (Vec<CandleToPersist>, Vec<CandleToDelete>) {
+        self.recompute_with_adjustments(time_stamp, minute, &[])
+    }
+
+    /// Same as [`Self::recompute`] for an instrument with recorded price adjustments.
+    /// The minute must already be adjusted. A lower candle adjusted while the candle above it is not
+    /// is reverted before the aggregation, so every candle keeps the prices of its own type.
+    pub fn recompute_with_adjustments(
+        &mut self,
+        time_stamp: DateTimeAsMicroseconds,
+        minute: Option<CandleData>,
+        adjustments: &[PriceAdjustment],
+    ) -> (Vec<CandleToPersist>, Vec<CandleToDelete>) {
+        let mut to_persist = Vec::new();
+        let mut to_delete = Vec::new();
+
+        let mut data = minute;
+
+        for (index, candle_type) in CandleType::ALL_CANDLE_TYPES.iter().enumerate() {
+            let date_key = time_stamp.into_candle_date_key(*candle_type);
+
+            if index > 0 {
+                let lower = CandleType::ALL_CANDLE_TYPES[index - 1];
+                let next_key = date_key.get_next_period_date_key(*candle_type);
+
+                data = self
+                    .get_in_date_range(date_key, next_key, lower)
+                    .and_then(|candles| {
+                        let candles: Vec<CandleData> = candles
+                            .iter()
+                            .map(|candle| {
+                                let mut data = candle.data;
+
+                                for adjustment in adjustments.iter().rev() {
+                                    if adjustment.affects(lower, candle.date_key)
+                                        && !adjustment.affects(*candle_type, date_key)
+                                    {
+                                        adjustment.kind.revert_candle(&mut data);
+                                    }
+                                }
+
+                                data
+                            })
+                            .collect();
+
+                        aggregate(candles.iter())
+                    });
+            }
+
+            if let (Some(data), Some(digits)) = (data.as_mut(), self.price_digits) {
+                data.round_to_digits(digits);
+            }
+
+            match data {
+                Some(data) => {
+                    self.get_or_create_by_candle_type_mut(*candle_type)
+                        .insert_or_update(CandleModel { date_key, data });
+                    to_persist.push(CandleToPersist {
+                        date_key,
+                        candle_type: *candle_type,
+                        data,
+                    });
+                }
+                None => {
+                    let removed = self
+                        .candles
+                        .get_mut(&candle_type.to_u8())
+                        .and_then(|cache| cache.remove(date_key));
+
+                    if removed.is_some() {
+                        to_delete.push(CandleToDelete {
+                            date_key,
+                            candle_type: *candle_type,
+                        });
+                    }
+                }
+            }
+        }
+
+        (to_persist, to_delete)
+    }
+
+    /// Ordered by candle type.
+    pub fn get_stats(&self) -> Vec<CandleTypeStats> {
+        let mut result: Vec<CandleTypeStats> = self
+            .candles
+            .values()
+            .map(|cache| cache.get_stats())
+            .collect();
+        result.sort_by_key(|stats| stats.candle_type.to_u8());
+        result
+    }
+
+    /// Candles evicted by gc from the caches of every candle type which are still there.
+    pub fn get_gc_evictions(&self) -> u64 {
+        self.candles.values().map(|cache| cache.gc_evictions).sum()
+    }
+
+    /// Memory of the map of the candle types which is not counted by the stats of the candle types.
+    pub fn get_estimated_overhead_bytes(&self) -> usize {
+        let capacity = self.candles.capacity();
+        let entry_size = std::mem::size_of::<(u8, CandleDateCache)>();
+
+        std::mem::size_of::<Self>() + capacity * (entry_size + 1)
+            - self.candles.len() * std::mem::size_of::<CandleDateCache>()
+    }
+
     pub fn clean_by_type(&mut self, candle_type: CandleType) {
         self.candles.remove(&candle_type.to_u8());
     }
@@ -142,3 +411,167 @@ impl CandlesCacheByType {
         }
     }
 }
+
+/// Aggregates candles ordered by date into one candle.
+fn aggregate<'s>(candles: impl Iterator<Item = &'s CandleData>) -> Option<CandleData> {
+    let mut result: Option<CandleData> = None;
+
+    for candle in candles {
+        match result.as_mut() {
+            Some(result) => {
+                result.close = candle.close;
+                result.high = result.high.max(candle.high);
+                result.low = result.low.min(candle.low);
+                result.volume += candle.volume;
+            }
+            None => result = Some(*candle),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{CandleData, CandleModel, CandleType, GetCandleDateKey};
+
+    use super::CandlesCacheByType;
+
+    const START_MICROSECONDS: i64 = 1_577_836_800_000_000; // 2020-01-01
+
+    /// Quotes with increasing time stamps spanning from seconds to days between them.
+    fn quotes_stream() -> impl Strategy<Value = Vec<(f64, DateTimeAsMicroseconds)>> {
+        prop::collection::vec((0.5f64..2.0, 0i64..2 * 24 * 60 * 60 * 1_000_000), 1..300).prop_map(
+            |items| {
+                let mut time_stamp = START_MICROSECONDS;
+
+                items
+                    .into_iter()
+                    .map(|(price, delta)| {
+                        time_stamp += delta;
+                        (price, DateTimeAsMicroseconds::new(time_stamp))
+                    })
+                    .collect()
+            },
+        )
+    }
+
+    fn handle_quotes(quotes: &[(f64, DateTimeAsMicroseconds)]) -> CandlesCacheByType {
+        let mut cache = CandlesCacheByType::new();
+
+        for (price, time_stamp) in quotes {
+            cache.handle_new_price(*price, *time_stamp, usize::MAX);
+        }
+
+        cache
+    }
+
+    #[test]
+    fn test_price_digits_are_applied() {
+        let mut cache = CandlesCacheByType::with_price_digits(Some(2));
+        let time_stamp = DateTimeAsMicroseconds::new(START_MICROSECONDS);
+
+        cache.handle_new_price(1.23456, time_stamp, 100);
+        cache.handle_new_price(1.2049, time_stamp, 100);
+
+        let date_key = time_stamp.into_candle_date_key(CandleType::Hour);
+        let candle = cache.get_candle(date_key, CandleType::Hour).unwrap();
+
+        assert_eq!(1.23, candle.data.open);
+        assert_eq!(1.2, candle.data.low);
+
+        let minute_key = time_stamp.into_candle_date_key(CandleType::Minute);
+        cache.insert_or_update(
+            CandleType::Minute,
+            CandleModel {
+                date_key: minute_key,
+                data: CandleData::new_from_price(1.11111, 0.0),
+            },
+        );
+
+        assert_eq!(
+            1.11,
+            cache
+                .get_candle(minute_key, CandleType::Minute)
+                .unwrap()
+                .data
+                .close
+        );
+
+        let (to_persist, _) =
+            cache.recompute(time_stamp, Some(CandleData::new_from_price(1.33333, 0.0)));
+        assert!(to_persist.iter().all(|candle| candle.data.high == 1.33));
+
+        cache.set_price_digits(Some(1));
+        assert_eq!(
+            1.3,
+            cache
+                .get_candle(date_key, CandleType::Hour)
+                .unwrap()
+                .data
+                .close
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn candles_are_valid(quotes in quotes_stream()) {
+            let cache = handle_quotes(&quotes);
+
+            for candle_type in CandleType::ALL_CANDLE_TYPES {
+                for candle in cache.iter_by_type(candle_type).unwrap() {
+                    let data = &candle.data;
+
+                    prop_assert!(data.high >= data.open.max(data.close));
+                    prop_assert!(data.low <= data.open.min(data.close));
+                    prop_assert!(candle_type.verify_date_key(candle.date_key).is_ok());
+                }
+            }
+
+            for (_, time_stamp) in &quotes {
+                for candle_type in CandleType::ALL_CANDLE_TYPES {
+                    let date_key = time_stamp.into_candle_date_key(candle_type);
+                    prop_assert!(cache.get_candle(date_key, candle_type).is_some());
+                }
+            }
+        }
+
+        #[test]
+        fn coarser_candles_match_finer_ones(quotes in quotes_stream()) {
+            let cache = handle_quotes(&quotes);
+
+            for window in CandleType::ALL_CANDLE_TYPES.windows(2) {
+                let (lower, candle_type) = (window[0], window[1]);
+
+                let mut lower_amount = 0;
+
+                for candle in cache.iter_by_type(candle_type).unwrap() {
+                    let next_key = candle.date_key.get_next_period_date_key(candle_type);
+                    let lower_candles = cache.get_in_date_range(candle.date_key, next_key, lower).unwrap();
+
+                    prop_assert!(!lower_candles.is_empty());
+                    lower_amount += lower_candles.len();
+
+                    let first = &lower_candles[0].data;
+                    let last = &lower_candles[lower_candles.len() - 1].data;
+
+                    prop_assert_eq!(candle.data.open, first.open);
+                    prop_assert_eq!(candle.data.close, last.close);
+                    prop_assert_eq!(
+                        candle.data.high,
+                        lower_candles.iter().map(|c| c.data.high).fold(f64::MIN, f64::max)
+                    );
+                    prop_assert_eq!(
+                        candle.data.low,
+                        lower_candles.iter().map(|c| c.data.low).fold(f64::MAX, f64::min)
+                    );
+                }
+
+                prop_assert_eq!(lower_amount, cache.iter_by_type(lower).unwrap().count());
+            }
+        }
+    }
+}
@@ -1,8 +1,21 @@
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
 
 use rust_extensions::date_time::DateTimeAsMicroseconds;
 
-use crate::{BidOrAsk, CandleData, CandleDateKey, CandleModel, CandleType, CandlesCacheByType};
+use crate::{
+    round_price, BidOrAsk, BufferedTick, CacheStats, CandleData, CandleDateKey, CandleModel,
+    CandleSubscribers, CandleSubscription, CandleToDelete, CandleType, CandleTypeStats,
+    CandlesCacheByType, ClosedCandleEvent, FinalizeCandlesSettings, GetCandleDateKey, IndicatorId,
+    IndicatorPoint, IndicatorSettings, IndicatorsRegistry, IngestionCounters, InstrumentId,
+    InstrumentMarkup, InstrumentRef, InstrumentStats, InstrumentsInterner, InstrumentsMap,
+    MarkupProfiles, NonTimeBar, NonTimeBarSettings, NonTimeBarToPersist, NonTimeBarsCache,
+    NonTimeBarsId, NonTimeBarsRegistry, PriceAdjustment, PriceAdjustmentKind, PriceAdjustments,
+    RejectedTick, SyntheticFormula, SyntheticInstruments, TickBuffer, TickFilter,
+    TickFilterSettings, TickToFilter, TradingCalendar,
+};
 
 #[derive(Debug, Clone)]
 pub struct CandleToPersist {
@@ -11,14 +24,63 @@ pub struct CandleToPersist {
     pub data: CandleData,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct BidAskToHandle<'s> {
+    pub instrument_id: &'s str,
+    pub bid: f64,
+    pub ask: f64,
+    /// Traded volume of the quote. Only non time bars use it.
+    pub volume: f64,
+    pub time_stamp: DateTimeAsMicroseconds,
+}
+
 pub struct HandleBidAskChanges {
     pub bids_to_persist: Vec<CandleToPersist>,
     pub asks_to_persist: Vec<CandleToPersist>,
+    pub non_time_bars_to_persist: Vec<NonTimeBarToPersist>,
+    /// Changes of the synthetic instruments recalculated because of the quote.
+    pub synthetics: Vec<SyntheticInstrumentChanges>,
+    /// Ticks rejected by the tick filter, including quarantined ticks rejected by this quote.
+    pub rejected_ticks: Vec<RejectedTick>,
+}
+
+#[derive(Default)]
+pub struct TickCorrectionChanges {
+    pub bids_to_persist: Vec<CandleToPersist>,
+    pub asks_to_persist: Vec<CandleToPersist>,
+    pub bids_to_delete: Vec<CandleToDelete>,
+    pub asks_to_delete: Vec<CandleToDelete>,
+}
+
+#[derive(Default)]
+pub struct AdjustPricesChanges {
+    pub bids_to_persist: Vec<CandleToPersist>,
+    pub asks_to_persist: Vec<CandleToPersist>,
+    pub non_time_bars_to_persist: Vec<NonTimeBarToPersist>,
+}
+
+pub struct SyntheticInstrumentChanges {
+    pub instrument_id: InstrumentId,
+    pub changes: HandleBidAskChanges,
 }
 
+/// Instruments are resolved to [`InstrumentId`] once at the boundary and all the series are stored by id.
+/// Every method accepting an instrument takes either its name or its id.
 pub struct CandlesInstrumentsCache {
-    pub bid_candles: BTreeMap<String, CandlesCacheByType>,
-    pub ask_candles: BTreeMap<String, CandlesCacheByType>,
+    pub instruments: InstrumentsInterner,
+    pub bid_candles: InstrumentsMap<CandlesCacheByType>,
+    pub ask_candles: InstrumentsMap<CandlesCacheByType>,
+    pub price_digits: InstrumentsMap<u32>,
+    pub subscribers: CandleSubscribers,
+    pub indicators: IndicatorsRegistry,
+    pub non_time_bars: NonTimeBarsRegistry,
+    pub synthetics: SyntheticInstruments,
+    pub markups: MarkupProfiles,
+    pub tick_filter: TickFilter,
+    pub tick_buffers: InstrumentsMap<TickBuffer>,
+    pub price_adjustments: PriceAdjustments,
+    pub trading_calendars: InstrumentsMap<TradingCalendar>,
+    pub counters: IngestionCounters,
 }
 
 pub struct CleanIntervalParameters {
@@ -29,293 +91,2516 @@ pub struct CleanIntervalParameters {
 impl CandlesInstrumentsCache {
     pub fn new() -> Self {
         Self {
-            bid_candles: BTreeMap::new(),
-            ask_candles: BTreeMap::new(),
+            instruments: InstrumentsInterner::new(),
+            bid_candles: InstrumentsMap::new(),
+            ask_candles: InstrumentsMap::new(),
+            price_digits: InstrumentsMap::new(),
+            subscribers: CandleSubscribers::new(),
+            indicators: IndicatorsRegistry::new(),
+            non_time_bars: NonTimeBarsRegistry::new(),
+            synthetics: SyntheticInstruments::new(),
+            markups: MarkupProfiles::new(),
+            tick_filter: TickFilter::new(),
+            tick_buffers: InstrumentsMap::new(),
+            price_adjustments: PriceAdjustments::new(),
+            trading_calendars: InstrumentsMap::new(),
+            counters: IngestionCounters::default(),
         }
     }
 
-    pub async fn handle_bid_ask(
-        &mut self,
-        instrument_id: &str,
-        bid: f64,
-        ask: f64,
-        time_stamp: DateTimeAsMicroseconds,
-        max_candles_amount: usize,
-    ) -> HandleBidAskChanges {
-        if !self.bid_candles.contains_key(instrument_id) {
-            self.bid_candles
-                .insert(instrument_id.to_string(), CandlesCacheByType::new());
-        }
+    pub fn get_instrument_id(&self, instrument: &str) -> Option<InstrumentId> {
+        self.instruments.get_id(instrument)
+    }
 
-        let bids_to_persist = self
-            .bid_candles
-            .get_mut(instrument_id)
-            .unwrap()
-            .handle_new_price(bid, time_stamp, max_candles_amount);
+    /// Registers the instrument if it is met for the first time.
+    pub fn get_or_add_instrument_id(&mut self, instrument: &str) -> InstrumentId {
+        self.instruments.get_or_add(instrument)
+    }
 
-        if !self.ask_candles.contains_key(instrument_id) {
-            self.ask_candles
-                .insert(instrument_id.to_string(), CandlesCacheByType::new());
-        }
+    pub fn get_instrument_name(&self, instrument_id: InstrumentId) -> Option<&str> {
+        self.instruments.get_name(instrument_id)
+    }
 
-        let asks_to_persist = self
-            .ask_candles
-            .get_mut(instrument_id)
-            .unwrap()
-            .handle_new_price(ask, time_stamp, max_candles_amount);
+    /// Switches the instrument to fixed-point mode: every incoming price and every loaded candle is rounded
+    /// to the given amount of digits before it gets into any candle, so all candle types aggregate
+    /// exactly the same prices the trading engine sees. Already cached candles are rounded as well.
+    pub fn set_price_digits<'s>(&mut self, instrument: impl Into<InstrumentRef<'s>>, digits: u32) {
+        let instrument_id = self.instruments.resolve_or_add(instrument);
+        self.price_digits.insert(instrument_id, digits);
 
-        HandleBidAskChanges {
-            bids_to_persist,
-            asks_to_persist,
+        for cache in [&mut self.bid_candles, &mut self.ask_candles] {
+            if let Some(cache) = cache.get_mut(instrument_id) {
+                cache.set_price_digits(Some(digits));
+            }
         }
+
+        self.rebuild_indicators(instrument_id, BidOrAsk::Bid);
+        self.rebuild_indicators(instrument_id, BidOrAsk::Ask);
     }
 
-    fn get_candles_cache(&self, bid_or_ask: BidOrAsk) -> &BTreeMap<String, CandlesCacheByType> {
-        match bid_or_ask {
-            BidOrAsk::Bid => &self.bid_candles,
-            BidOrAsk::Ask => &self.ask_candles,
+    pub fn get_price_digits<'s>(&self, instrument: impl Into<InstrumentRef<'s>>) -> Option<u32> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        self.price_digits.get(instrument_id).copied()
+    }
+
+    fn normalize_price(&self, instrument_id: InstrumentId, price: f64) -> f64 {
+        match self.price_digits.get(instrument_id) {
+            Some(digits) => round_price(price, *digits),
+            None => price,
         }
     }
 
-    fn get_candles_cache_mut(
+    /// Ticks of the instrument are checked by the filter before they reach the candles.
+    /// `None` disables filtering. Synthetic instruments are calculated from the accepted ticks only.
+    pub fn set_tick_filter<'s>(
         &mut self,
-        bid_or_ask: BidOrAsk,
-    ) -> &mut BTreeMap<String, CandlesCacheByType> {
-        match bid_or_ask {
-            BidOrAsk::Bid => &mut self.bid_candles,
-            BidOrAsk::Ask => &mut self.ask_candles,
+        instrument: impl Into<InstrumentRef<'s>>,
+        settings: Option<TickFilterSettings>,
+    ) {
+        let instrument_id = self.instruments.resolve_or_add(instrument);
+        self.tick_filter.set_settings(instrument_id, settings);
+    }
+
+    /// Suspicious ticks waiting to be either confirmed or rejected by the following ticks.
+    pub fn get_quarantined_ticks<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Vec<TickToFilter> {
+        match self.instruments.resolve(instrument) {
+            Some(instrument_id) => self.tick_filter.get_quarantined(instrument_id),
+            None => vec![],
         }
     }
 
-    pub fn init_candles(
+    /// Every candle update produced by the ingestion of the instrument for the given side and candle type
+    /// is sent to the subscription, as well as candles loaded by [`Self::init_candles`]
+    /// and [`Self::bulk_insert_or_update`]. Batch ingestion sends only the coalesced updates.
+    /// If the subscriber does not keep up, the oldest updates above `buffer_size` are dropped
+    /// and reported as [`crate::CandleSubscriptionError::Lagged`].
+    pub fn subscribe<'s>(
         &mut self,
+        instrument: impl Into<InstrumentRef<'s>>,
         bid_or_ask: BidOrAsk,
-        instrument: &str,
         candle_type: CandleType,
-        candles_to_init: impl Iterator<Item = CandleModel>,
-        pre_allocate_memory: Option<usize>,
-    ) {
-        let candles = self.get_candles_cache_mut(bid_or_ask);
+        buffer_size: usize,
+    ) -> CandleSubscription {
+        let instrument_id = self.instruments.resolve_or_add(instrument);
+        self.subscribers
+            .subscribe(instrument_id, bid_or_ask, candle_type, buffer_size)
+    }
 
-        for candle_to_init in candles_to_init {
-            match candles.get_mut(instrument) {
-                Some(candles) => {
-                    if let Some(pre_allocate_memory) = pre_allocate_memory {
-                        candles.pre_allocate_memory_if_needed(candle_type, pre_allocate_memory);
-                    }
+    /// Nothing here waits for I/O, so ingestion is synchronous and can be called from any context.
+    /// Persisting or broadcasting the returned changes is up to the caller.
+    pub fn handle_bid_ask<'s>(
+        &mut self,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        bid: f64,
+        ask: f64,
+        time_stamp: DateTimeAsMicroseconds,
+        max_candles_amount: usize,
+    ) -> HandleBidAskChanges {
+        self.handle_bid_ask_with_volume(
+            instrument_id,
+            bid,
+            ask,
+            0.0,
+            time_stamp,
+            max_candles_amount,
+        )
+    }
 
-                    candles.insert_or_update(candle_type, candle_to_init);
-                }
-                None => {
-                    let mut candles_cache = CandlesCacheByType::new();
-                    if let Some(pre_allocate_memory) = pre_allocate_memory {
-                        candles_cache
-                            .pre_allocate_memory_if_needed(candle_type, pre_allocate_memory);
-                    }
-                    candles_cache.insert_or_update(candle_type, candle_to_init);
-                    candles.insert(instrument.to_string(), candles_cache);
+    /// Same as [`Self::handle_bid_ask`]. The volume feeds the volume based non time bars.
+    pub fn handle_bid_ask_with_volume<'s>(
+        &mut self,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        bid: f64,
+        ask: f64,
+        volume: f64,
+        time_stamp: DateTimeAsMicroseconds,
+        max_candles_amount: usize,
+    ) -> HandleBidAskChanges {
+        let instrument_id = self.instruments.resolve_or_add(instrument_id);
+        let bid = self.normalize_price(instrument_id, bid);
+        let ask = self.normalize_price(instrument_id, ask);
+
+        let filtered = self
+            .tick_filter
+            .check(instrument_id, (bid, ask, volume, time_stamp));
+
+        self.counters.ticks_processed += filtered.accepted.len() as u64;
+        self.counters.ticks_dropped += filtered.rejected.len() as u64;
+
+        let mut changes = self.handle_quotes(instrument_id, &filtered.accepted, max_candles_amount);
+        changes.rejected_ticks = filtered.rejected;
+
+        if self.synthetics.is_empty() {
+            return changes;
+        }
+
+        for (bid, ask, _, time_stamp) in filtered.accepted {
+            let dependents = self
+                .synthetics
+                .update_quote(instrument_id, bid, ask)
+                .to_vec();
+
+            for synthetic_id in dependents {
+                if let Some((bid, ask)) = self.synthetics.calculate(synthetic_id) {
+                    let bid = self.normalize_price(synthetic_id, bid);
+                    let ask = self.normalize_price(synthetic_id, ask);
+
+                    changes.synthetics.push(SyntheticInstrumentChanges {
+                        instrument_id: synthetic_id,
+                        changes: self.handle_quotes(
+                            synthetic_id,
+                            &[(bid, ask, 0.0, time_stamp)],
+                            max_candles_amount,
+                        ),
+                    });
                 }
-            };
+            }
         }
+
+        changes
     }
 
-    pub fn pre_allocate_memory(
+    /// Handles a batch of quotes. Quotes of the same instrument are handled in the order they are given,
+    /// every instrument is looked up once per batch and changes are coalesced to the latest state
+    /// of every touched candle.
+    /// Changes of a synthetic instrument are reported with the component whose quote came last in the batch.
+    pub fn handle_bid_ask_batch<'s>(
         &mut self,
-        bid_or_ask: BidOrAsk,
-        instrument_id: &str,
-        candle_type: CandleType,
-        amount: usize,
-    ) {
-        let candles = self.get_candles_cache_mut(bid_or_ask);
+        quotes: &[BidAskToHandle<'s>],
+        max_candles_amount: usize,
+    ) -> HashMap<&'s str, HandleBidAskChanges> {
+        let mut result = HashMap::new();
+        let mut accepted_quotes = Vec::new();
 
-        match candles.get_mut(instrument_id) {
-            Some(candles) => {
-                candles.pre_allocate_memory_if_needed(candle_type, amount);
-            }
-            None => {
-                let mut candles_cache = CandlesCacheByType::new();
-                candles_cache.pre_allocate_memory_if_needed(candle_type, amount);
-                candles.insert(instrument_id.to_string(), candles_cache);
+        for (instrument_id, group) in group_by_instrument(&mut self.instruments, quotes) {
+            let name = group[0].1.instrument_id;
+
+            let mut accepted: Vec<TickToFilter> = Vec::with_capacity(group.len());
+            let mut rejected_ticks = Vec::new();
+
+            for (index, quote) in group {
+                let tick = (
+                    self.normalize_price(instrument_id, quote.bid),
+                    self.normalize_price(instrument_id, quote.ask),
+                    quote.volume,
+                    quote.time_stamp,
+                );
+
+                let filtered = self.tick_filter.check(instrument_id, tick);
+
+                self.counters.ticks_processed += filtered.accepted.len() as u64;
+                self.counters.ticks_dropped += filtered.rejected.len() as u64;
+
+                for tick in &filtered.accepted {
+                    accepted_quotes.push((index, instrument_id, name, *tick));
+                }
+
+                accepted.extend(filtered.accepted);
+                rejected_ticks.extend(filtered.rejected);
             }
-        };
-    }
 
-    pub fn get_instruments(&self) -> HashSet<String> {
-        let mut result = HashSet::new();
-        let candles = self.get_candles_cache(BidOrAsk::Bid);
+            let mut changes = self.handle_quotes(instrument_id, &accepted, max_candles_amount);
+            changes.rejected_ticks = rejected_ticks;
+            result.insert(name, changes);
+        }
+
+        if self.synthetics.is_empty() {
+            return result;
+        }
+
+        accepted_quotes.sort_by_key(|(index, _, _, _)| *index);
+
+        let mut synthetic_quotes: Vec<(InstrumentId, &'s str, Vec<_>)> = Vec::new();
+
+        for (_, instrument_id, name, (bid, ask, _, time_stamp)) in accepted_quotes {
+            let dependents = self
+                .synthetics
+                .update_quote(instrument_id, bid, ask)
+                .to_vec();
 
-        for instrument in candles.keys() {
-            result.insert(instrument.to_string());
+            for synthetic_id in dependents {
+                let (bid, ask) = match self.synthetics.calculate(synthetic_id) {
+                    Some(bid_ask) => bid_ask,
+                    None => continue,
+                };
+
+                let synthetic_quote = (
+                    self.normalize_price(synthetic_id, bid),
+                    self.normalize_price(synthetic_id, ask),
+                    0.0,
+                    time_stamp,
+                );
+
+                match synthetic_quotes
+                    .iter_mut()
+                    .find(|(id, _, _)| *id == synthetic_id)
+                {
+                    Some((_, trigger, quotes)) => {
+                        *trigger = name;
+                        quotes.push(synthetic_quote);
+                    }
+                    None => synthetic_quotes.push((synthetic_id, name, vec![synthetic_quote])),
+                }
+            }
         }
 
-        let candles = self.get_candles_cache(BidOrAsk::Ask);
+        for (synthetic_id, trigger, quotes) in synthetic_quotes {
+            let changes = SyntheticInstrumentChanges {
+                instrument_id: synthetic_id,
+                changes: self.handle_quotes(synthetic_id, &quotes, max_candles_amount),
+            };
 
-        for instrument in candles.keys() {
-            if !result.contains(instrument) {
-                result.insert(instrument.to_string());
+            if let Some(trigger_changes) = result.get_mut(trigger) {
+                trigger_changes.synthetics.push(changes);
             }
         }
 
         result
     }
 
-    pub fn clean_by_type(
+    /// Applies already normalized quotes of the instrument to candles, indicators and non time bars.
+    /// Quotes older than a recorded price adjustment are adjusted the same way as the candles were.
+    fn handle_quotes(
         &mut self,
-        bid_or_ask: BidOrAsk,
-        instrument_id: &str,
-        candle_type: CandleType,
-    ) {
-        let candles = self.get_candles_cache_mut(bid_or_ask);
-        if let Some(candles) = candles.get_mut(instrument_id) {
-            candles.clean_by_type(candle_type);
+        instrument_id: InstrumentId,
+        quotes: &[(f64, f64, f64, DateTimeAsMicroseconds)],
+        max_candles_amount: usize,
+    ) -> HandleBidAskChanges {
+        let adjustments = self.price_adjustments.get(instrument_id).to_vec();
+        let adjusted_quotes: Vec<(f64, f64, f64, DateTimeAsMicroseconds)>;
+
+        let tick_quotes = if adjustments.is_empty() {
+            quotes
+        } else {
+            adjusted_quotes = quotes
+                .iter()
+                .map(|(bid, ask, volume, time_stamp)| {
+                    let (bid, ask) =
+                        adjustments
+                            .iter()
+                            .fold((*bid, *ask), |(bid, ask), adjustment| {
+                                (
+                                    adjustment.apply_to_tick_price(*time_stamp, bid),
+                                    adjustment.apply_to_tick_price(*time_stamp, ask),
+                                )
+                            });
+                    (bid, ask, *volume, *time_stamp)
+                })
+                .collect();
+            &adjusted_quotes
+        };
+
+        if let Some(tick_buffer) = self.tick_buffers.get_mut(instrument_id) {
+            for (bid, ask, volume, time_stamp) in tick_quotes {
+                tick_buffer.push(BufferedTick {
+                    bid: *bid,
+                    ask: *ask,
+                    volume: *volume,
+                    time_stamp: *time_stamp,
+                });
+            }
+        }
+
+        let bids = self.get_or_create_candles_by_type_mut(BidOrAsk::Bid, instrument_id);
+        let gc_evictions = bids.get_gc_evictions();
+        let bids_to_persist = bids.handle_new_prices_with_adjustments(
+            quotes
+                .iter()
+                .map(|(bid, _, _, time_stamp)| (*bid, *time_stamp)),
+            max_candles_amount,
+            &adjustments,
+        );
+        self.counters.gc_evictions += bids.get_gc_evictions() - gc_evictions;
+
+        let asks = self.get_or_create_candles_by_type_mut(BidOrAsk::Ask, instrument_id);
+        let gc_evictions = asks.get_gc_evictions();
+        let asks_to_persist = asks.handle_new_prices_with_adjustments(
+            quotes
+                .iter()
+                .map(|(_, ask, _, time_stamp)| (*ask, *time_stamp)),
+            max_candles_amount,
+            &adjustments,
+        );
+        self.counters.gc_evictions += asks.get_gc_evictions() - gc_evictions;
+
+        self.handle_changes(instrument_id, BidOrAsk::Bid, &bids_to_persist);
+        self.handle_changes(instrument_id, BidOrAsk::Ask, &asks_to_persist);
+
+        let non_time_bars_to_persist = self
+            .non_time_bars
+            .handle_quotes(instrument_id, tick_quotes.iter().copied());
+
+        HandleBidAskChanges {
+            bids_to_persist,
+            asks_to_persist,
+            non_time_bars_to_persist,
+            synthetics: Vec::new(),
+            rejected_ticks: Vec::new(),
         }
     }
 
-    pub fn bulk_insert_or_update(
+    /// Keeps the ticks of the instrument for the last `horizon` so they can be corrected
+    /// by [`Self::remove_tick`] and [`Self::replace_tick`]. Only ticks handled after this call are kept.
+    pub fn enable_tick_buffer<'s>(
         &mut self,
-        bid_or_ask: BidOrAsk,
-        instrument: &str,
-        candle_type: CandleType,
-        candles_to_init: impl Iterator<Item = CandleModel>,
+        instrument: impl Into<InstrumentRef<'s>>,
+        horizon: Duration,
     ) {
-        let candles = self.get_candles_cache_mut(bid_or_ask);
+        let instrument_id = self.instruments.resolve_or_add(instrument);
 
-        for candle_to_init in candles_to_init {
-            match candles.get_mut(instrument) {
-                Some(candles) => {
-                    candles.insert_or_update(candle_type, candle_to_init);
-                }
-                None => {
-                    let mut candles_cache = CandlesCacheByType::new();
-                    candles_cache.insert_or_update(candle_type, candle_to_init);
-                    candles.insert(instrument.to_string(), candles_cache);
-                }
-            };
+        if !self.tick_buffers.contains(instrument_id) {
+            self.tick_buffers
+                .insert(instrument_id, TickBuffer::new(horizon.as_micros() as i64));
         }
     }
 
-    pub fn get_candle(
-        &self,
-        instrument: &str,
-        date_key: CandleDateKey,
-        candle_type: CandleType,
-        bid_or_ask: BidOrAsk,
-    ) -> Option<CandleModel> {
-        let cache_by_type = self.get_candles_cache(bid_or_ask).get(instrument);
-
-        if cache_by_type.is_none() {
-            println!("No cache for instrument {}", instrument);
+    pub fn disable_tick_buffer<'s>(&mut self, instrument: impl Into<InstrumentRef<'s>>) -> bool {
+        match self.instruments.resolve(instrument) {
+            Some(instrument_id) => self.tick_buffers.remove(instrument_id).is_some(),
+            None => false,
         }
-
-        let result = cache_by_type.unwrap();
-
-        result.get_candle(date_key, candle_type)
     }
 
-    pub fn get_in_date_range(
+    pub fn get_tick_buffer<'s>(
         &self,
-        instrument: &str,
-        from: CandleDateKey,
-        to: CandleDateKey,
-        candle_type: CandleType,
-        bid_or_ask: BidOrAsk,
-    ) -> Option<&[CandleModel]> {
-        let cache_by_instrument = self.get_candles_cache(bid_or_ask).get(instrument)?;
-        cache_by_instrument.get_in_date_range(from, to, candle_type)
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Option<&TickBuffer> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        self.tick_buffers.get(instrument_id)
     }
 
-    pub fn get_highest_and_below(
-        &self,
-        bid_or_ask: BidOrAsk,
-        instrument: &str,
-        candle_type: CandleType,
-        highest: CandleDateKey,
-        amount: usize,
-    ) -> Option<&[CandleModel]> {
-        let cache_by_instrument = self.get_candles_cache(bid_or_ask).get(instrument)?;
-        cache_by_instrument.get_highest_and_below(candle_type, highest, amount)
+    /// Removes the buffered tick with the time stamp and recomputes the candles containing it.
+    /// Non time bars and synthetic instruments are not corrected.
+    pub fn remove_tick<'s>(
+        &mut self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        time_stamp: DateTimeAsMicroseconds,
+    ) -> Result<TickCorrectionChanges, String> {
+        self.correct_tick(instrument, time_stamp, None)
     }
 
-    pub fn get_all_from_cache(
-        &self,
-        bid_or_ask: BidOrAsk,
-    ) -> HashMap<String, Vec<(CandleType, Vec<CandleModel>)>> {
-        let cache_by_type = self.get_candles_cache(bid_or_ask);
+    /// Replaces bid and ask of the buffered tick with the time stamp and recomputes the candles containing it.
+    /// The recorded price adjustments are applied to the new prices as to a late quote.
+    pub fn replace_tick<'s>(
+        &mut self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        time_stamp: DateTimeAsMicroseconds,
+        bid: f64,
+        ask: f64,
+    ) -> Result<TickCorrectionChanges, String> {
+        self.correct_tick(instrument, time_stamp, Some((bid, ask)))
+    }
 
-        let mut result = HashMap::new();
+    fn correct_tick<'s>(
+        &mut self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        time_stamp: DateTimeAsMicroseconds,
+        replace_with: Option<(f64, f64)>,
+    ) -> Result<TickCorrectionChanges, String> {
+        let instrument_id = match self.instruments.resolve(instrument) {
+            Some(instrument_id) => instrument_id,
+            None => return Err("Unknown instrument".to_string()),
+        };
+
+        let minute_from: DateTimeAsMicroseconds =
+            time_stamp.into_candle_date_key(CandleType::Minute).into();
+        let mut minute_to = minute_from;
+        minute_to.add_minutes(1);
+
+        let tick_buffer = match self.tick_buffers.get(instrument_id) {
+            Some(tick_buffer) => tick_buffer,
+            None => return Err("Tick buffer is not enabled for the instrument".to_string()),
+        };
 
-        for (instrument, cache) in cache_by_type {
-            let from_cache = cache.get_all_from_cache();
-            result.insert(instrument.to_string(), from_cache);
+        let covered = match tick_buffer.get_covered_from() {
+            Some(covered_from) => covered_from.unix_microseconds <= minute_from.unix_microseconds,
+            None => false,
+        };
+
+        if !covered {
+            return Err(format!(
+                "Ticks of the minute {} are not buffered",
+                minute_from.to_rfc3339()
+            ));
         }
 
-        return result;
-    }
+        let index = match tick_buffer.find(time_stamp) {
+            Some(index) => index,
+            None => return Err(format!("Tick {} is not found", time_stamp.to_rfc3339())),
+        };
 
-    pub fn iter_all_by_instrument(
-        &self,
-        bid_or_ask: BidOrAsk,
-        instrument_id: &str,
-        candle_type: CandleType,
-    ) -> Option<impl Iterator<Item = &CandleModel>> {
-        let cache_by_type = self.get_candles_cache(bid_or_ask);
-        let instruments = cache_by_type.get(instrument_id)?;
-        instruments.iter_by_type(candle_type)
-    }
+        for bid_or_ask in [BidOrAsk::Bid, BidOrAsk::Ask] {
+            if let Some(cache) = self.get_candles_cache(bid_or_ask).get(instrument_id) {
+                cache.check_can_recompute(time_stamp)?;
+            }
+        }
 
-    pub fn iter_all(&self, bid_or_ask: BidOrAsk) -> impl Iterator<Item = &CandleModel> {
-        let cache_by_type = self.get_candles_cache(bid_or_ask);
-        cache_by_type.values().flat_map(|itm| itm.iter_all())
-    }
+        let adjustments = self.price_adjustments.get(instrument_id).to_vec();
 
-    pub fn get_all_by_instrument(
-        &self,
-        bid_or_ask: BidOrAsk,
-        instrument_id: &str,
-    ) -> Option<Vec<(CandleType, Vec<CandleModel>)>> {
-        let cache_by_type = self.get_candles_cache(bid_or_ask);
+        let replace_with = replace_with.map(|(bid, ask)| {
+            let bid = self.normalize_price(instrument_id, bid);
+            let ask = self.normalize_price(instrument_id, ask);
 
-        if let Some(by_instrument) = cache_by_type.get(instrument_id) {
-            return Some(by_instrument.get_all_from_cache());
+            adjustments
+                .iter()
+                .fold((bid, ask), |(bid, ask), adjustment| {
+                    (
+                        adjustment.apply_to_tick_price(time_stamp, bid),
+                        adjustment.apply_to_tick_price(time_stamp, ask),
+                    )
+                })
+        });
+
+        let tick_buffer = self.tick_buffers.get_mut(instrument_id).unwrap();
+
+        match replace_with {
+            Some((bid, ask)) => {
+                let tick = tick_buffer.get_mut(index).unwrap();
+                tick.bid = bid;
+                tick.ask = ask;
+            }
+            None => {
+                tick_buffer.remove(index);
+            }
         }
 
-        return None;
+        let mut result = TickCorrectionChanges::default();
+
+        for bid_or_ask in [BidOrAsk::Bid, BidOrAsk::Ask] {
+            let minute = self.tick_buffers.get(instrument_id).unwrap().build_candle(
+                bid_or_ask,
+                minute_from,
+                minute_to,
+            );
+
+            let (to_persist, to_delete) = self
+                .get_or_create_candles_by_type_mut(bid_or_ask, instrument_id)
+                .recompute_with_adjustments(time_stamp, minute, &adjustments);
+
+            self.subscribers
+                .publish(instrument_id, bid_or_ask, &to_persist);
+            self.rebuild_indicators(instrument_id, bid_or_ask);
+
+            match bid_or_ask {
+                BidOrAsk::Bid => {
+                    result.bids_to_persist = to_persist;
+                    result.bids_to_delete = to_delete;
+                }
+                BidOrAsk::Ask => {
+                    result.asks_to_persist = to_persist;
+                    result.asks_to_delete = to_delete;
+                }
+            }
+        }
+
+        Ok(result)
     }
 
-    pub fn gc_candles_by_instrument(
+    /// Applies the adjustment to every cached candle of the instrument whose period ends at or before `before`,
+    /// e.g. on a split or a contract roll. Buffered ticks and non time bars before `before` are adjusted as well.
+    /// The adjusted candles are sent to the subscribers.
+    /// The adjustment is recorded, so unadjusted candles can still be retrieved, and quotes and loaded candles
+    /// older than `before` which arrive later are adjusted the same way.
+    pub fn adjust_prices<'s>(
         &mut self,
-        instrument: &str,
-        candle_type: CandleType,
-        max_candles_amount: usize,
-    ) {
-        if let Some(cache) = self.bid_candles.get_mut(instrument) {
-            cache.gc_by_type(candle_type, max_candles_amount);
+        instrument: impl Into<InstrumentRef<'s>>,
+        before: CandleDateKey,
+        kind: PriceAdjustmentKind,
+        applied_at: DateTimeAsMicroseconds,
+    ) -> Result<AdjustPricesChanges, String> {
+        kind.validate()?;
+
+        let instrument_id = match self.instruments.resolve(instrument) {
+            Some(instrument_id) => instrument_id,
+            None => return Err("Unknown instrument".to_string()),
+        };
+
+        let adjustment = PriceAdjustment {
+            before,
+            kind,
+            applied_at,
+        };
+
+        let digits = self.price_digits.get(instrument_id).copied();
+        let mut result = AdjustPricesChanges::default();
+
+        for bid_or_ask in [BidOrAsk::Bid, BidOrAsk::Ask] {
+            let mut changes = Vec::new();
+
+            if let Some(cache) = self
+                .get_candles_cache_mut(bid_or_ask)
+                .get_mut(instrument_id)
+            {
+                // Evicted candles are older than the cached ones, so the seed is adjusted
+                // whenever the candle it was built from is
+                cache.update_heikin_ashi_seeds(|candle_type, seed| {
+                    adjustment.apply(candle_type, seed);
+                });
+                cache.update_all(|candle_type, candle| {
+                    if adjustment.apply(candle_type, candle) {
+                        if let Some(digits) = digits {
+                            candle.data.round_to_digits(digits);
+                        }
+
+                        changes.push(CandleToPersist {
+                            date_key: candle.date_key,
+                            candle_type,
+                            data: candle.data,
+                        });
+                    }
+                });
+            }
+
+            self.subscribers
+                .publish(instrument_id, bid_or_ask, &changes);
+            self.rebuild_indicators(instrument_id, bid_or_ask);
+
+            match bid_or_ask {
+                BidOrAsk::Bid => result.bids_to_persist = changes,
+                BidOrAsk::Ask => result.asks_to_persist = changes,
+            }
         }
 
-        if let Some(cache) = self.ask_candles.get_mut(instrument) {
-            cache.gc_by_type(candle_type, max_candles_amount);
+        let before: DateTimeAsMicroseconds = before.into();
+
+        if let Some(tick_buffer) = self.tick_buffers.get_mut(instrument_id) {
+            tick_buffer.update_before(before, |tick| {
+                tick.bid = kind.apply(tick.bid);
+                tick.ask = kind.apply(tick.ask);
+            });
         }
+
+        result.non_time_bars_to_persist =
+            self.non_time_bars
+                .adjust_prices(instrument_id, before, &kind);
+
+        self.price_adjustments.add(instrument_id, adjustment);
+
+        Ok(result)
     }
 
-    pub fn get_first_candle(
+    pub fn get_price_adjustments<'s>(
         &self,
-        bid_or_ask: BidOrAsk,
-        instrument: &str,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> &[PriceAdjustment] {
+        match self.instruments.resolve(instrument) {
+            Some(instrument_id) => self.price_adjustments.get(instrument_id),
+            None => &[],
+        }
+    }
+
+    /// Same as [`Self::get_candle`] with the recorded price adjustments reverted.
+    pub fn get_unadjusted_candle<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        date_key: CandleDateKey,
         candle_type: CandleType,
-    ) -> Option<&CandleModel> {
-        let candles_cache = self.get_candles_cache(bid_or_ask);
+        bid_or_ask: BidOrAsk,
+    ) -> Option<CandleModel> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        let candle = self
+            .get_candles_by_type(bid_or_ask, instrument_id)?
+            .get_by_type(candle_type)?
+            .get_candle(date_key)?;
 
-        let by_instrument = candles_cache.get(instrument)?;
-        by_instrument.get_first_candle(candle_type)
+        Some(
+            self.price_adjustments
+                .unadjust(instrument_id, candle_type, &candle),
+        )
     }
 
-    pub fn gc_candles(&mut self, candle_type: CandleType, max_candles_amount: usize) {
-        for cache in self.bid_candles.values_mut() {
-            cache.gc_by_type(candle_type, max_candles_amount);
-        }
+    /// Same as [`Self::get_in_date_range`] with the recorded price adjustments reverted.
+    pub fn get_unadjusted_in_date_range<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        from: CandleDateKey,
+        to: CandleDateKey,
+        candle_type: CandleType,
+        bid_or_ask: BidOrAsk,
+    ) -> Option<Vec<CandleModel>> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        let candles = self.get_in_date_range(instrument_id, from, to, candle_type, bid_or_ask)?;
 
-        for cache in self.ask_candles.values_mut() {
+        Some(
+            candles
+                .iter()
+                .map(|candle| {
+                    self.price_adjustments
+                        .unadjust(instrument_id, candle_type, candle)
+                })
+                .collect(),
+        )
+    }
+
+    /// `None` makes the instrument open all the time.
+    pub fn set_trading_calendar<'s>(
+        &mut self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        calendar: Option<TradingCalendar>,
+    ) {
+        let instrument_id = self.instruments.resolve_or_add(instrument);
+
+        match calendar {
+            Some(calendar) => {
+                self.trading_calendars.insert(instrument_id, calendar);
+            }
+            None => {
+                self.trading_calendars.remove(instrument_id);
+            }
+        }
+    }
+
+    pub fn get_trading_calendar<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Option<&TradingCalendar> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        self.trading_calendars.get(instrument_id)
+    }
+
+    /// Keys of the periods within `from..to` when the instrument is trading.
+    pub fn get_in_session_periods<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        from: CandleDateKey,
+        to: CandleDateKey,
+        candle_type: CandleType,
+    ) -> Vec<CandleDateKey> {
+        match self.get_trading_calendar(instrument) {
+            Some(calendar) => calendar.get_in_session_periods(from, to, candle_type),
+            None => TradingCalendar::default().get_in_session_periods(from, to, candle_type),
+        }
+    }
+
+    /// Same as [`Self::get_in_date_range`] without the candles of the periods the instrument is closed,
+    /// e.g. built from quotes arriving on a weekend.
+    pub fn get_in_session_date_range<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        from: CandleDateKey,
+        to: CandleDateKey,
+        candle_type: CandleType,
+        bid_or_ask: BidOrAsk,
+    ) -> Option<Vec<CandleModel>> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        let candles = self.get_in_date_range(instrument_id, from, to, candle_type, bid_or_ask)?;
+
+        match self.trading_calendars.get(instrument_id) {
+            Some(calendar) => Some(
+                candles
+                    .iter()
+                    .filter(|candle| calendar.is_in_session(candle_type, candle.date_key))
+                    .cloned()
+                    .collect(),
+            ),
+            None => Some(candles.to_vec()),
+        }
+    }
+
+    /// Defines an instrument whose bid/ask is calculated by the formula every time a component is quoted.
+    /// Candles, indicators and non time bars of the synthetic instrument work as for any other instrument.
+    /// The synthetic instrument is quoted once every component got a quote.
+    pub fn add_synthetic_instrument(
+        &mut self,
+        instrument_id: &str,
+        formula: SyntheticFormula,
+    ) -> Result<InstrumentId, String> {
+        let instrument_id = self.instruments.get_or_add(instrument_id);
+        self.synthetics
+            .add(&mut self.instruments, instrument_id, formula)?;
+        Ok(instrument_id)
+    }
+
+    /// Stops recalculating the synthetic instrument. Its cached candles are kept.
+    pub fn remove_synthetic_instrument<'s>(
+        &mut self,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Option<SyntheticFormula> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        self.synthetics.remove(instrument_id)
+    }
+
+    pub fn get_synthetic_formula<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Option<&SyntheticFormula> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        self.synthetics.get_formula(instrument_id)
+    }
+
+    /// Clock driven step which reports candles of all the series whose period is over by `now`,
+    /// even if no quote arrived after the period ended. Should be called periodically, e.g. every second.
+    /// Empty candles are not synthesized for the periods the trading calendar of the instrument is closed.
+    pub fn finalize_candles(
+        &mut self,
+        now: DateTimeAsMicroseconds,
+        settings: &FinalizeCandlesSettings,
+    ) -> Vec<ClosedCandleEvent> {
+        let mut result = Vec::new();
+
+        for bid_or_ask in [BidOrAsk::Bid, BidOrAsk::Ask] {
+            let caches = match bid_or_ask {
+                BidOrAsk::Bid => &mut self.bid_candles,
+                BidOrAsk::Ask => &mut self.ask_candles,
+            };
+
+            for (instrument_id, cache) in caches.iter_mut() {
+                let calendar = self.trading_calendars.get(instrument_id);
+                let gc_evictions = cache.get_gc_evictions();
+                let closed_candles = cache.finalize(now, settings, calendar);
+                self.counters.gc_evictions += cache.get_gc_evictions() - gc_evictions;
+
+                for (candle_type, closed) in closed_candles {
+                    result.push(ClosedCandleEvent {
+                        instrument_id,
+                        bid_or_ask,
+                        candle_type,
+                        candle: closed.candle,
+                        synthesized: closed.synthesized,
+                    });
+                }
+            }
+        }
+
+        for event in result.iter().filter(|event| event.synthesized) {
+            let change = CandleToPersist {
+                date_key: event.candle.date_key,
+                candle_type: event.candle_type,
+                data: event.candle.data,
+            };
+
+            self.handle_changes(event.instrument_id, event.bid_or_ask, &[change]);
+        }
+
+        result
+    }
+
+    /// Sends candle changes to the subscribers and the attached indicators.
+    fn handle_changes(
+        &mut self,
+        instrument_id: InstrumentId,
+        bid_or_ask: BidOrAsk,
+        changes: &[CandleToPersist],
+    ) {
+        self.subscribers.publish(instrument_id, bid_or_ask, changes);
+
+        let source = match bid_or_ask {
+            BidOrAsk::Bid => self.bid_candles.get(instrument_id),
+            BidOrAsk::Ask => self.ask_candles.get(instrument_id),
+        };
+
+        self.indicators
+            .handle_changes(instrument_id, bid_or_ask, changes, source);
+    }
+
+    fn rebuild_indicators(&mut self, instrument_id: InstrumentId, bid_or_ask: BidOrAsk) {
+        let source = match bid_or_ask {
+            BidOrAsk::Bid => self.bid_candles.get(instrument_id),
+            BidOrAsk::Ask => self.ask_candles.get(instrument_id),
+        };
+
+        self.indicators.rebuild(instrument_id, bid_or_ask, source);
+    }
+
+    /// Attaches an indicator to the series. Values are calculated from the cached candles right away
+    /// and then kept up to date by the ingestion. At most `max_values` latest values are kept.
+    pub fn attach_indicator<'s>(
+        &mut self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        bid_or_ask: BidOrAsk,
+        candle_type: CandleType,
+        settings: IndicatorSettings,
+        max_values: usize,
+    ) -> IndicatorId {
+        let instrument_id = self.instruments.resolve_or_add(instrument);
+
+        let source = match bid_or_ask {
+            BidOrAsk::Bid => self.bid_candles.get(instrument_id),
+            BidOrAsk::Ask => self.ask_candles.get(instrument_id),
+        };
+
+        self.indicators.attach(
+            instrument_id,
+            bid_or_ask,
+            candle_type,
+            settings,
+            max_values,
+            source,
+        )
+    }
+
+    pub fn detach_indicator(&mut self, indicator_id: IndicatorId) -> bool {
+        self.indicators.detach(indicator_id)
+    }
+
+    pub fn get_indicator_values(
+        &self,
+        indicator_id: IndicatorId,
+        from: CandleDateKey,
+        to: CandleDateKey,
+    ) -> Option<Vec<IndicatorPoint>> {
+        self.indicators.get_in_date_range(indicator_id, from, to)
+    }
+
+    pub fn get_indicator_last_value(&self, indicator_id: IndicatorId) -> Option<IndicatorPoint> {
+        self.indicators.get_last_value(indicator_id)
+    }
+
+    /// Adds a series of Renko or range bars built from the quotes of the instrument.
+    /// Bars are built only from the quotes arriving after the series is added.
+    /// Returns an error if the size of the bars is not valid.
+    pub fn add_non_time_bars<'s>(
+        &mut self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        settings: NonTimeBarSettings,
+    ) -> Result<NonTimeBarsId, String> {
+        settings.bar_type.validate()?;
+
+        let instrument_id = self.instruments.resolve_or_add(instrument);
+        self.non_time_bars.add(instrument_id, settings)
+    }
+
+    pub fn remove_non_time_bars(&mut self, non_time_bars_id: NonTimeBarsId) -> bool {
+        self.non_time_bars.remove(non_time_bars_id)
+    }
+
+    pub fn get_non_time_bars(&self, non_time_bars_id: NonTimeBarsId) -> Option<&NonTimeBarsCache> {
+        self.non_time_bars.get(non_time_bars_id)
+    }
+
+    pub fn get_non_time_bars_in_range(
+        &self,
+        non_time_bars_id: NonTimeBarsId,
+        from: u64,
+        to: u64,
+    ) -> Vec<NonTimeBar> {
+        match self.non_time_bars.get(non_time_bars_id) {
+            Some(cache) => cache.get_in_range(from, to).to_vec(),
+            None => vec![],
+        }
+    }
+
+    pub fn get_non_time_bars_highest_and_below(
+        &self,
+        non_time_bars_id: NonTimeBarsId,
+        highest: u64,
+        amount: usize,
+    ) -> Vec<NonTimeBar> {
+        match self.non_time_bars.get(non_time_bars_id) {
+            Some(cache) => cache.get_highest_and_below(highest, amount).to_vec(),
+            None => vec![],
+        }
+    }
+
+    fn get_candles_cache(&self, bid_or_ask: BidOrAsk) -> &InstrumentsMap<CandlesCacheByType> {
+        match bid_or_ask {
+            BidOrAsk::Bid => &self.bid_candles,
+            BidOrAsk::Ask => &self.ask_candles,
+        }
+    }
+
+    fn get_candles_cache_mut(
+        &mut self,
+        bid_or_ask: BidOrAsk,
+    ) -> &mut InstrumentsMap<CandlesCacheByType> {
+        match bid_or_ask {
+            BidOrAsk::Bid => &mut self.bid_candles,
+            BidOrAsk::Ask => &mut self.ask_candles,
+        }
+    }
+
+    pub fn get_candles_by_type<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Option<&CandlesCacheByType> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        self.get_candles_cache(bid_or_ask).get(instrument_id)
+    }
+
+    fn get_or_create_candles_by_type_mut<'s>(
+        &mut self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> &mut CandlesCacheByType {
+        let instrument_id = self.instruments.resolve_or_add(instrument);
+        let price_digits = self.price_digits.get(instrument_id).copied();
+        self.get_candles_cache_mut(bid_or_ask)
+            .get_or_insert_with(instrument_id, || {
+                CandlesCacheByType::with_price_digits(price_digits)
+            })
+    }
+
+    pub fn init_candles<'s>(
+        &mut self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        candles_to_init: impl Iterator<Item = CandleModel>,
+        pre_allocate_memory: Option<usize>,
+    ) {
+        let instrument_id = self.instruments.resolve_or_add(instrument);
+
+        if let Some(pre_allocate_memory) = pre_allocate_memory {
+            self.get_or_create_candles_by_type_mut(bid_or_ask, instrument_id)
+                .pre_allocate_memory_if_needed(candle_type, pre_allocate_memory);
+        }
+
+        self.upsert_candles(instrument_id, bid_or_ask, candle_type, candles_to_init);
+    }
+
+    /// Inserts loaded candles, sends them to the subscribers as they are stored and rebuilds indicators.
+    /// Loaded candles are expected in the prices before the recorded price adjustments, so the adjustments
+    /// which affect a candle are applied to it.
+    fn upsert_candles(
+        &mut self,
+        instrument_id: InstrumentId,
+        bid_or_ask: BidOrAsk,
+        candle_type: CandleType,
+        candles_to_init: impl Iterator<Item = CandleModel>,
+    ) {
+        let publish = !self.subscribers.is_empty();
+        let adjustments = self.price_adjustments.get(instrument_id).to_vec();
+        let candles = self.get_or_create_candles_by_type_mut(bid_or_ask, instrument_id);
+        let mut to_publish = Vec::new();
+
+        for mut candle_to_init in candles_to_init {
+            for adjustment in &adjustments {
+                adjustment.apply(candle_type, &mut candle_to_init);
+            }
+
+            let date_key = candle_to_init.date_key;
+            candles.insert_or_update(candle_type, candle_to_init);
+
+            if publish {
+                if let Some(candle) = candles
+                    .get_by_type(candle_type)
+                    .and_then(|cache| cache.get_candle(date_key))
+                {
+                    to_publish.push(CandleToPersist {
+                        date_key,
+                        candle_type,
+                        data: candle.data,
+                    });
+                }
+            }
+        }
+
+        self.subscribers
+            .publish(instrument_id, bid_or_ask, &to_publish);
+        self.rebuild_indicators(instrument_id, bid_or_ask);
+    }
+
+    pub fn pre_allocate_memory<'s>(
+        &mut self,
+        bid_or_ask: BidOrAsk,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        amount: usize,
+    ) {
+        self.get_or_create_candles_by_type_mut(bid_or_ask, instrument_id)
+            .pre_allocate_memory_if_needed(candle_type, amount);
+    }
+
+    /// Names of the instruments which have candles. Prefer [`Self::iter_instruments`] on hot paths
+    /// since it does not clone names.
+    pub fn get_instruments(&self) -> HashSet<String> {
+        self.iter_instruments()
+            .map(|(_, name)| name.to_string())
+            .collect()
+    }
+
+    /// Sizes of the candles caches of every instrument and side plus the ingestion counters.
+    pub fn get_stats(&self) -> CacheStats {
+        let mut result = CacheStats {
+            estimated_tick_buffers_bytes: self
+                .tick_buffers
+                .values()
+                .map(|tick_buffer| tick_buffer.get_estimated_bytes())
+                .sum(),
+            estimated_indicators_bytes: self.indicators.get_estimated_bytes(),
+            estimated_non_time_bars_bytes: self.non_time_bars.get_estimated_bytes(),
+            counters: self.counters,
+            ..Default::default()
+        };
+
+        for (instrument_id, name) in self.iter_instruments() {
+            result.instruments_amount += 1;
+
+            for (bid_or_ask, candles) in [
+                (BidOrAsk::Bid, &self.bid_candles),
+                (BidOrAsk::Ask, &self.ask_candles),
+            ] {
+                if let Some(candles) = candles.get(instrument_id) {
+                    let stats = InstrumentStats {
+                        instrument_id: name.to_string(),
+                        bid_or_ask,
+                        by_type: candles.get_stats(),
+                        estimated_overhead_bytes: candles.get_estimated_overhead_bytes(),
+                    };
+
+                    result.candles_amount += stats.get_candles_amount();
+                    result.estimated_candles_bytes += stats.get_estimated_bytes();
+                    result.instruments.push(stats);
+                }
+            }
+        }
+
+        result.estimated_bytes = result.estimated_candles_bytes
+            + result.estimated_tick_buffers_bytes
+            + result.estimated_indicators_bytes
+            + result.estimated_non_time_bars_bytes;
+
+        result.sort_instruments();
+
+        result
+    }
+
+    pub fn get_instrument_stats<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Option<Vec<CandleTypeStats>> {
+        let candles = self.get_candles_by_type(bid_or_ask, instrument)?;
+        Some(candles.get_stats())
+    }
+
+    pub fn iter_instruments(&self) -> impl Iterator<Item = (InstrumentId, &str)> {
+        self.instruments.iter().filter(|(instrument_id, _)| {
+            self.bid_candles.contains(*instrument_id) || self.ask_candles.contains(*instrument_id)
+        })
+    }
+
+    pub fn clean_by_type<'s>(
+        &mut self,
+        bid_or_ask: BidOrAsk,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+    ) {
+        let instrument_id = match self.instruments.resolve(instrument_id) {
+            Some(instrument_id) => instrument_id,
+            None => return,
+        };
+
+        if let Some(candles) = self
+            .get_candles_cache_mut(bid_or_ask)
+            .get_mut(instrument_id)
+        {
+            candles.clean_by_type(candle_type);
+        }
+
+        self.rebuild_indicators(instrument_id, bid_or_ask);
+    }
+
+    pub fn bulk_insert_or_update<'s>(
+        &mut self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        candles_to_init: impl Iterator<Item = CandleModel>,
+    ) {
+        let instrument_id = self.instruments.resolve_or_add(instrument);
+        self.upsert_candles(instrument_id, bid_or_ask, candle_type, candles_to_init);
+    }
+
+    pub fn get_candle<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        date_key: CandleDateKey,
+        candle_type: CandleType,
+        bid_or_ask: BidOrAsk,
+    ) -> Option<CandleModel> {
+        self.get_candles_by_type(bid_or_ask, instrument)?
+            .get_candle(date_key, candle_type)
+    }
+
+    pub fn get_in_date_range<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        from: CandleDateKey,
+        to: CandleDateKey,
+        candle_type: CandleType,
+        bid_or_ask: BidOrAsk,
+    ) -> Option<&[CandleModel]> {
+        let cache_by_instrument = self.get_candles_by_type(bid_or_ask, instrument)?;
+        cache_by_instrument.get_in_date_range(from, to, candle_type)
+    }
+
+    pub fn get_highest_and_below<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        highest: CandleDateKey,
+        amount: usize,
+    ) -> Option<&[CandleModel]> {
+        let cache_by_instrument = self.get_candles_by_type(bid_or_ask, instrument)?;
+        cache_by_instrument.get_highest_and_below(candle_type, highest, amount)
+    }
+
+    /// Same as [`Self::get_in_date_range`] but returns Heikin-Ashi candles derived from the series.
+    pub fn get_heikin_ashi_in_date_range<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        from: CandleDateKey,
+        to: CandleDateKey,
+        candle_type: CandleType,
+        bid_or_ask: BidOrAsk,
+    ) -> Option<Vec<CandleModel>> {
+        let cache_by_instrument = self.get_candles_by_type(bid_or_ask, instrument)?;
+        let candles = cache_by_instrument.get_by_type(candle_type)?;
+        Some(candles.heikin_ashi().get_in_date_range(from, to))
+    }
+
+    pub fn get_heikin_ashi_highest_and_below<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        highest: CandleDateKey,
+        amount: usize,
+    ) -> Option<Vec<CandleModel>> {
+        let cache_by_instrument = self.get_candles_by_type(bid_or_ask, instrument)?;
+        let candles = cache_by_instrument.get_by_type(candle_type)?;
+        Some(candles.heikin_ashi().get_highest_and_below(highest, amount))
+    }
+
+    /// Sets the markup of the instrument for the trading group. Stored candles are not changed,
+    /// the markup is applied by the `*_with_markup` queries.
+    pub fn set_markup<'s>(
+        &mut self,
+        group: &str,
+        instrument: impl Into<InstrumentRef<'s>>,
+        markup: InstrumentMarkup,
+    ) {
+        let instrument_id = self.instruments.resolve_or_add(instrument);
+        self.markups.set(group, instrument_id, markup);
+    }
+
+    pub fn get_markup<'s>(
+        &self,
+        group: &str,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Option<InstrumentMarkup> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        self.markups.get(group, instrument_id)
+    }
+
+    pub fn remove_markup<'s>(
+        &mut self,
+        group: &str,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Option<InstrumentMarkup> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        self.markups.remove(group, instrument_id)
+    }
+
+    pub fn remove_markup_group(&mut self, group: &str) -> bool {
+        self.markups.remove_group(group)
+    }
+
+    fn apply_markup(
+        &self,
+        group: &str,
+        instrument_id: InstrumentId,
+        bid_or_ask: BidOrAsk,
+        candles: &[CandleModel],
+    ) -> Vec<CandleModel> {
+        match self.markups.get(group, instrument_id) {
+            Some(markup) => {
+                let digits = self.price_digits.get(instrument_id).copied();
+                candles
+                    .iter()
+                    .map(|candle| markup.apply_to_candle(candle, bid_or_ask, digits))
+                    .collect()
+            }
+            None => candles.to_vec(),
+        }
+    }
+
+    /// Same as [`Self::get_in_date_range`] with the markup of the trading group applied.
+    /// Candles are returned as they are if the group has no markup for the instrument.
+    pub fn get_in_date_range_with_markup<'s>(
+        &self,
+        group: &str,
+        instrument: impl Into<InstrumentRef<'s>>,
+        from: CandleDateKey,
+        to: CandleDateKey,
+        candle_type: CandleType,
+        bid_or_ask: BidOrAsk,
+    ) -> Option<Vec<CandleModel>> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        let candles = self.get_in_date_range(instrument_id, from, to, candle_type, bid_or_ask)?;
+        Some(self.apply_markup(group, instrument_id, bid_or_ask, candles))
+    }
+
+    pub fn get_highest_and_below_with_markup<'s>(
+        &self,
+        group: &str,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        highest: CandleDateKey,
+        amount: usize,
+    ) -> Option<Vec<CandleModel>> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        let candles =
+            self.get_highest_and_below(bid_or_ask, instrument_id, candle_type, highest, amount)?;
+        Some(self.apply_markup(group, instrument_id, bid_or_ask, candles))
+    }
+
+    pub fn get_candle_with_markup<'s>(
+        &self,
+        group: &str,
+        instrument: impl Into<InstrumentRef<'s>>,
+        date_key: CandleDateKey,
+        candle_type: CandleType,
+        bid_or_ask: BidOrAsk,
+    ) -> Option<CandleModel> {
+        let instrument_id = self.instruments.resolve(instrument)?;
+        let candle = self
+            .get_candles_by_type(bid_or_ask, instrument_id)?
+            .get_by_type(candle_type)?
+            .get_candle(date_key)?;
+
+        self.apply_markup(group, instrument_id, bid_or_ask, &[candle])
+            .pop()
+    }
+
+    pub fn get_all_from_cache(
+        &self,
+        bid_or_ask: BidOrAsk,
+    ) -> HashMap<String, Vec<(CandleType, Vec<CandleModel>)>> {
+        let cache_by_type = self.get_candles_cache(bid_or_ask);
+
+        let mut result = HashMap::new();
+
+        for (instrument_id, cache) in cache_by_type.iter() {
+            if let Some(instrument) = self.instruments.get_name(instrument_id) {
+                result.insert(instrument.to_string(), cache.get_all_from_cache());
+            }
+        }
+
+        return result;
+    }
+
+    pub fn iter_all_by_instrument<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+    ) -> Option<impl Iterator<Item = &CandleModel>> {
+        let instruments = self.get_candles_by_type(bid_or_ask, instrument_id)?;
+        instruments.iter_by_type(candle_type)
+    }
+
+    pub fn iter_all(&self, bid_or_ask: BidOrAsk) -> impl Iterator<Item = &CandleModel> {
+        let cache_by_type = self.get_candles_cache(bid_or_ask);
+        cache_by_type.values().flat_map(|itm| itm.iter_all())
+    }
+
+    pub fn get_all_by_instrument<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+    ) -> Option<Vec<(CandleType, Vec<CandleModel>)>> {
+        if let Some(by_instrument) = self.get_candles_by_type(bid_or_ask, instrument_id) {
+            return Some(by_instrument.get_all_from_cache());
+        }
+
+        return None;
+    }
+
+    pub fn gc_candles_by_instrument<'s>(
+        &mut self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        max_candles_amount: usize,
+    ) {
+        let instrument_id = match self.instruments.resolve(instrument) {
+            Some(instrument_id) => instrument_id,
+            None => return,
+        };
+
+        for candles in [&mut self.bid_candles, &mut self.ask_candles] {
+            if let Some(cache) = candles.get_mut(instrument_id) {
+                let gc_evictions = cache.get_gc_evictions();
+                cache.gc_by_type(candle_type, max_candles_amount);
+                self.counters.gc_evictions += cache.get_gc_evictions() - gc_evictions;
+            }
+        }
+    }
+
+    pub fn get_first_candle<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+    ) -> Option<&CandleModel> {
+        let by_instrument = self.get_candles_by_type(bid_or_ask, instrument)?;
+        by_instrument.get_first_candle(candle_type)
+    }
+
+    pub fn gc_candles(&mut self, candle_type: CandleType, max_candles_amount: usize) {
+        for cache in self
+            .bid_candles
+            .values_mut()
+            .chain(self.ask_candles.values_mut())
+        {
+            let gc_evictions = cache.get_gc_evictions();
             cache.gc_by_type(candle_type, max_candles_amount);
+            self.counters.gc_evictions += cache.get_gc_evictions() - gc_evictions;
+        }
+    }
+}
+
+/// Resolves instruments of the quotes and splits quotes into groups by instrument
+/// keeping the order of quotes inside every group. Every quote comes with its index in the batch.
+fn group_by_instrument<'q, 's>(
+    instruments: &mut InstrumentsInterner,
+    quotes: &'q [BidAskToHandle<'s>],
+) -> Vec<(InstrumentId, Vec<(usize, &'q BidAskToHandle<'s>)>)> {
+    let mut resolved: Vec<(InstrumentId, usize, &BidAskToHandle)> =
+        Vec::with_capacity(quotes.len());
+    let mut last_resolved: Option<(&str, InstrumentId)> = None;
+
+    for (index, quote) in quotes.iter().enumerate() {
+        let instrument_id = match last_resolved {
+            Some((name, instrument_id)) if name == quote.instrument_id => instrument_id,
+            _ => instruments.get_or_add(quote.instrument_id),
+        };
+
+        last_resolved = Some((quote.instrument_id, instrument_id));
+        resolved.push((instrument_id, index, quote));
+    }
+
+    resolved.sort_by_key(|(instrument_id, _, _)| *instrument_id);
+
+    let mut result: Vec<(InstrumentId, Vec<(usize, &BidAskToHandle)>)> = Vec::new();
+
+    for (instrument_id, index, quote) in resolved {
+        match result.last_mut() {
+            Some((group_id, group)) if *group_id == instrument_id => group.push((index, quote)),
+            _ => result.push((instrument_id, vec![(index, quote)])),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{
+        BidAskToHandle, BidOrAsk, CandleData, CandleDateKey, CandleModel, CandleSubscriptionError,
+        CandleType, DeviationReference, FinalizeCandlesSettings, GetCandleDateKey,
+        IndicatorSettings, IndicatorValue, IngestionCounters, InstrumentMarkup, Markup,
+        NonTimeBarSettings, NonTimeBarType, PriceAdjustmentKind, SyntheticFormula, SyntheticLeg,
+        TickFilterSettings, TickRejectReason, TradingCalendar, WeekTime, WeeklySession,
+    };
+
+    use super::CandlesInstrumentsCache;
+
+    #[test]
+    fn test_handle_bid_ask() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+
+        let changes = cache.handle_bid_ask("EURUSD", 1.1, 1.2, now, 100);
+
+        assert_eq!(4, changes.bids_to_persist.len());
+        assert_eq!(4, changes.asks_to_persist.len());
+
+        let candle = cache
+            .get_candle(
+                "EURUSD",
+                CandleDateKey::new(202101011000),
+                CandleType::Hour,
+                BidOrAsk::Ask,
+            )
+            .unwrap();
+
+        assert_eq!(1.2, candle.data.close);
+    }
+
+    #[test]
+    fn test_handle_bid_ask_batch() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+        let next_minute = DateTimeAsMicroseconds::from_str("2021-01-01T10:23:01").unwrap();
+
+        let quotes = [
+            BidAskToHandle {
+                instrument_id: "EURUSD",
+                bid: 1.1,
+                ask: 1.2,
+                volume: 0.0,
+                time_stamp: now,
+            },
+            BidAskToHandle {
+                instrument_id: "USDJPY",
+                bid: 110.1,
+                ask: 110.2,
+                volume: 0.0,
+                time_stamp: now,
+            },
+            BidAskToHandle {
+                instrument_id: "EURUSD",
+                bid: 1.3,
+                ask: 1.4,
+                volume: 0.0,
+                time_stamp: now,
+            },
+            BidAskToHandle {
+                instrument_id: "EURUSD",
+                bid: 1.0,
+                ask: 1.1,
+                volume: 0.0,
+                time_stamp: next_minute,
+            },
+        ];
+
+        let changes = cache.handle_bid_ask_batch(&quotes, 100);
+
+        assert_eq!(2, changes.len());
+
+        let eurusd = changes.get("EURUSD").unwrap();
+
+        // Two minute candles, one candle for every other candle type
+        assert_eq!(5, eurusd.bids_to_persist.len());
+
+        let hour = eurusd
+            .bids_to_persist
+            .iter()
+            .find(|itm| itm.candle_type.to_u8() == CandleType::Hour.to_u8())
+            .unwrap();
+
+        assert_eq!(1.1, hour.data.open);
+        assert_eq!(1.3, hour.data.high);
+        assert_eq!(1.0, hour.data.low);
+        assert_eq!(1.0, hour.data.close);
+
+        assert_eq!(4, changes.get("USDJPY").unwrap().asks_to_persist.len());
+
+        let candle = cache
+            .get_candle(
+                "EURUSD",
+                CandleDateKey::new(202101011022),
+                CandleType::Minute,
+                BidOrAsk::Ask,
+            )
+            .unwrap();
+
+        assert_eq!(1.4, candle.data.close);
+    }
+
+    #[test]
+    fn test_instrument_id_and_name_are_interchangeable() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+
+        let eurusd = cache.get_or_add_instrument_id("EURUSD");
+        cache.handle_bid_ask(eurusd, 1.1, 1.2, now, 100);
+        cache.handle_bid_ask("EURUSD", 1.3, 1.4, now, 100);
+
+        assert_eq!(Some(eurusd), cache.get_instrument_id("EURUSD"));
+        assert_eq!(1, cache.get_instruments().len());
+
+        let by_name = cache
+            .get_candle(
+                "EURUSD",
+                CandleDateKey::new(202101011022),
+                CandleType::Minute,
+                BidOrAsk::Bid,
+            )
+            .unwrap();
+
+        let by_id = cache
+            .get_candle(
+                eurusd,
+                CandleDateKey::new(202101011022),
+                CandleType::Minute,
+                BidOrAsk::Bid,
+            )
+            .unwrap();
+
+        assert_eq!(1.3, by_name.data.close);
+        assert_eq!(by_name.data.open, by_id.data.open);
+        assert_eq!(by_name.data.close, by_id.data.close);
+    }
+
+    #[test]
+    fn test_fixed_point_prices() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+
+        cache.handle_bid_ask("EURUSD", 1.123456, 1.123467, now, 100);
+        cache.set_price_digits("EURUSD", 5);
+        cache.handle_bid_ask("EURUSD", 1.000004, 1.1 + 0.2, now, 100);
+
+        for candle_type in CandleType::ALL_CANDLE_TYPES {
+            let date_key = now.into_candle_date_key(candle_type);
+
+            let bid = cache
+                .get_candle("EURUSD", date_key, candle_type, BidOrAsk::Bid)
+                .unwrap();
+
+            assert_eq!(1.12346, bid.data.open);
+            assert_eq!(1.12346, bid.data.high);
+            assert_eq!(1.0, bid.data.low);
+            assert_eq!(1.0, bid.data.close);
+
+            let ask = cache
+                .get_candle("EURUSD", date_key, candle_type, BidOrAsk::Ask)
+                .unwrap();
+
+            assert_eq!(1.3, ask.data.close);
+            assert_eq!(130000, ask.data.to_fixed_point(5).close.value);
+        }
+    }
+
+    #[test]
+    fn test_subscription() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let subscription = cache.subscribe("EURUSD", BidOrAsk::Ask, CandleType::Minute, 2);
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+
+        cache.handle_bid_ask("EURUSD", 1.1, 1.2, now, 100);
+        cache.handle_bid_ask("USDJPY", 110.1, 110.2, now, 100);
+        cache.handle_bid_ask("EURUSD", 1.3, 1.4, now, 100);
+        cache.handle_bid_ask("EURUSD", 1.5, 1.6, now, 100);
+
+        assert_eq!(
+            Err(CandleSubscriptionError::Lagged(1)),
+            subscription.try_recv().map(|_| ())
+        );
+
+        let update = subscription.try_recv().unwrap().unwrap();
+        assert_eq!(1.4, update.candle.data.close);
+        assert_eq!(202101011022, update.candle.date_key.get_value());
+
+        let update = subscription.try_recv().unwrap().unwrap();
+        assert_eq!(1.6, update.candle.data.close);
+
+        assert!(subscription.try_recv().unwrap().is_none());
+
+        drop(cache);
+
+        assert_eq!(
+            Err(CandleSubscriptionError::Closed),
+            subscription.try_recv().map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_subscription_receives_loaded_candles() {
+        let mut cache = CandlesInstrumentsCache::new();
+        cache.set_price_digits("EURUSD", 2);
+
+        let subscription = cache.subscribe("EURUSD", BidOrAsk::Bid, CandleType::Hour, 10);
+
+        cache.init_candles(
+            BidOrAsk::Bid,
+            "EURUSD",
+            CandleType::Hour,
+            [CandleModel {
+                date_key: CandleDateKey::new(202101011000),
+                data: CandleData::new_from_price(1.23456, 0.0),
+            }]
+            .into_iter(),
+            None,
+        );
+
+        cache.bulk_insert_or_update(
+            BidOrAsk::Bid,
+            "EURUSD",
+            CandleType::Hour,
+            [CandleModel {
+                date_key: CandleDateKey::new(202101010900),
+                data: CandleData::new_from_price(1.1, 0.0),
+            }]
+            .into_iter(),
+        );
+
+        cache.bulk_insert_or_update(
+            BidOrAsk::Bid,
+            "EURUSD",
+            CandleType::Minute,
+            [CandleModel {
+                date_key: CandleDateKey::new(202101010901),
+                data: CandleData::new_from_price(1.1, 0.0),
+            }]
+            .into_iter(),
+        );
+
+        let update = subscription.try_recv().unwrap().unwrap();
+        assert_eq!(202101011000, update.candle.date_key.get_value());
+        assert_eq!(1.23, update.candle.data.close);
+
+        let update = subscription.try_recv().unwrap().unwrap();
+        assert_eq!(202101010900, update.candle.date_key.get_value());
+
+        assert!(subscription.try_recv().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_finalize_candles() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let settings = FinalizeCandlesSettings {
+            synthesize_empty_candles: true,
+            max_periods_per_series: 100,
+            max_candles_amount: 100,
+        };
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+        cache.handle_bid_ask("EURUSD", 1.1, 1.2, now, 100);
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:59").unwrap();
+        assert!(cache.finalize_candles(now, &settings).is_empty());
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:25:01").unwrap();
+        let events = cache.finalize_candles(now, &settings);
+
+        let minutes: Vec<(u64, bool)> = events
+            .iter()
+            .filter(|itm| itm.bid_or_ask.to_is_bid())
+            .filter(|itm| itm.candle_type.to_u8() == CandleType::Minute.to_u8())
+            .map(|itm| (itm.candle.date_key.get_value(), itm.synthesized))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (202101011022, false),
+                (202101011023, true),
+                (202101011024, true)
+            ],
+            minutes
+        );
+
+        assert_eq!(6, events.len());
+        assert!(cache.finalize_candles(now, &settings).is_empty());
+
+        let synthesized = cache
+            .get_candle(
+                "EURUSD",
+                CandleDateKey::new(202101011024),
+                CandleType::Minute,
+                BidOrAsk::Ask,
+            )
+            .unwrap();
+
+        assert_eq!(1.2, synthesized.data.open);
+        assert_eq!(1.2, synthesized.data.close);
+    }
+
+    #[test]
+    fn test_indicator_follows_live_candle() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let closes = [1.0, 2.0, 3.0];
+
+        for (minute, close) in closes.iter().enumerate() {
+            let time_stamp =
+                DateTimeAsMicroseconds::from_str(&format!("2021-01-01T10:0{}:00", minute)).unwrap();
+            cache.handle_bid_ask("EURUSD", *close, *close, time_stamp, 100);
+        }
+
+        let sma = cache.attach_indicator(
+            "EURUSD",
+            BidOrAsk::Bid,
+            CandleType::Minute,
+            IndicatorSettings::Sma { period: 2 },
+            100,
+        );
+
+        let last = cache.get_indicator_last_value(sma).unwrap();
+        assert_eq!(202101011002, last.date_key.get_value());
+        assert_eq!(IndicatorValue::Single(2.5), last.value);
+
+        let time_stamp = DateTimeAsMicroseconds::from_str("2021-01-01T10:02:30").unwrap();
+        cache.handle_bid_ask("EURUSD", 5.0, 5.0, time_stamp, 100);
+
+        let last = cache.get_indicator_last_value(sma).unwrap();
+        assert_eq!(202101011002, last.date_key.get_value());
+        assert_eq!(IndicatorValue::Single(3.5), last.value);
+
+        let time_stamp = DateTimeAsMicroseconds::from_str("2021-01-01T10:03:00").unwrap();
+        cache.handle_bid_ask("EURUSD", 7.0, 7.0, time_stamp, 100);
+
+        let values = cache
+            .get_indicator_values(
+                sma,
+                CandleDateKey::new(202101011000),
+                CandleDateKey::new(202101011100),
+            )
+            .unwrap();
+
+        let values: Vec<(u64, IndicatorValue)> = values
+            .iter()
+            .map(|itm| (itm.date_key.get_value(), itm.value))
+            .collect();
+
+        assert_eq!(
+            vec![
+                (202101011001, IndicatorValue::Single(1.5)),
+                (202101011002, IndicatorValue::Single(3.5)),
+                (202101011003, IndicatorValue::Single(6.0)),
+            ],
+            values
+        );
+    }
+
+    #[test]
+    fn test_non_time_bars() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let renko = cache
+            .add_non_time_bars(
+                "EURUSD",
+                NonTimeBarSettings {
+                    bar_type: NonTimeBarType::Renko { brick_size: 0.001 },
+                    bid_or_ask: BidOrAsk::Bid,
+                    max_bars_amount: 100,
+                },
+            )
+            .unwrap();
+
+        let range = cache
+            .add_non_time_bars(
+                "EURUSD",
+                NonTimeBarSettings {
+                    bar_type: NonTimeBarType::Range { range: 0.001 },
+                    bid_or_ask: BidOrAsk::Ask,
+                    max_bars_amount: 100,
+                },
+            )
+            .unwrap();
+
+        assert!(cache
+            .add_non_time_bars(
+                "EURUSD",
+                NonTimeBarSettings {
+                    bar_type: NonTimeBarType::Renko { brick_size: 0.0 },
+                    bid_or_ask: BidOrAsk::Bid,
+                    max_bars_amount: 100,
+                },
+            )
+            .is_err());
+
+        let time_stamp = DateTimeAsMicroseconds::from_str("2021-01-01T10:00:00").unwrap();
+
+        let changes = cache.handle_bid_ask("EURUSD", 1.1000, 1.1002, time_stamp, 100);
+        assert_eq!(1, changes.non_time_bars_to_persist.len());
+        assert_eq!(range, changes.non_time_bars_to_persist[0].non_time_bars_id);
+
+        let quotes = vec![
+            BidAskToHandle {
+                instrument_id: "EURUSD",
+                bid: 1.1006,
+                ask: 1.1008,
+                volume: 0.0,
+                time_stamp,
+            },
+            BidAskToHandle {
+                instrument_id: "EURUSD",
+                bid: 1.1021,
+                ask: 1.1023,
+                volume: 0.0,
+                time_stamp,
+            },
+        ];
+
+        let changes = cache.handle_bid_ask_batch(&quotes, 100);
+        let changes = &changes.get("EURUSD").unwrap().non_time_bars_to_persist;
+
+        let renko_bars: Vec<u64> = changes
+            .iter()
+            .filter(|itm| itm.non_time_bars_id == renko)
+            .map(|itm| itm.bar.sequence)
+            .collect();
+        assert_eq!(vec![0, 1], renko_bars);
+
+        // The range bar updated by the first quote is reported once
+        let range_bars: Vec<u64> = changes
+            .iter()
+            .filter(|itm| itm.non_time_bars_id == range)
+            .map(|itm| itm.bar.sequence)
+            .collect();
+        assert_eq!(vec![0, 1], range_bars);
+
+        assert_eq!(2, cache.get_non_time_bars_in_range(renko, 0, 10).len());
+        assert_eq!(
+            1,
+            cache
+                .get_non_time_bars_highest_and_below(range, 0, 10)
+                .len()
+        );
+
+        assert!(cache.remove_non_time_bars(renko));
+        assert!(cache.get_non_time_bars(renko).is_none());
+    }
+
+    #[test]
+    fn test_synthetic_instrument() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let eurjpy = cache
+            .add_synthetic_instrument(
+                "EURJPY",
+                SyntheticFormula::Product(vec![
+                    SyntheticLeg {
+                        instrument_id: "EURUSD".to_string(),
+                        invert: false,
+                    },
+                    SyntheticLeg {
+                        instrument_id: "USDJPY".to_string(),
+                        invert: false,
+                    },
+                ]),
+            )
+            .unwrap();
+
+        cache.set_price_digits(eurjpy, 3);
+
+        let time_stamp = DateTimeAsMicroseconds::from_str("2021-01-01T10:00:00").unwrap();
+
+        let changes = cache.handle_bid_ask("EURUSD", 1.1, 1.2, time_stamp, 100);
+        assert!(changes.synthetics.is_empty());
+
+        let changes = cache.handle_bid_ask("USDJPY", 110.0, 110.5, time_stamp, 100);
+        assert_eq!(1, changes.synthetics.len());
+        assert_eq!(eurjpy, changes.synthetics[0].instrument_id);
+
+        let candle = cache
+            .get_candle(
+                "EURJPY",
+                time_stamp.into_candle_date_key(CandleType::Minute),
+                CandleType::Minute,
+                BidOrAsk::Ask,
+            )
+            .unwrap();
+        assert_eq!(132.6, candle.data.close);
+
+        let quotes = vec![
+            BidAskToHandle {
+                instrument_id: "EURUSD",
+                bid: 1.0,
+                ask: 1.0,
+                volume: 0.0,
+                time_stamp,
+            },
+            BidAskToHandle {
+                instrument_id: "USDJPY",
+                bid: 100.0,
+                ask: 100.0,
+                volume: 0.0,
+                time_stamp,
+            },
+        ];
+
+        let changes = cache.handle_bid_ask_batch(&quotes, 100);
+        assert!(changes.get("EURUSD").unwrap().synthetics.is_empty());
+        assert_eq!(1, changes.get("USDJPY").unwrap().synthetics.len());
+
+        let candle = cache
+            .get_candle(
+                eurjpy,
+                time_stamp.into_candle_date_key(CandleType::Minute),
+                CandleType::Minute,
+                BidOrAsk::Bid,
+            )
+            .unwrap();
+        assert_eq!(100.0, candle.data.close);
+        assert_eq!(100.0, candle.data.low);
+        assert_eq!(121.0, candle.data.high);
+
+        assert!(cache.remove_synthetic_instrument("EURJPY").is_some());
+        let changes = cache.handle_bid_ask("USDJPY", 110.0, 110.5, time_stamp, 100);
+        assert!(changes.synthetics.is_empty());
+    }
+
+    #[test]
+    fn test_markup() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let time_stamp = DateTimeAsMicroseconds::from_str("2021-01-01T10:00:00").unwrap();
+        let date_key = time_stamp.into_candle_date_key(CandleType::Minute);
+
+        cache.set_price_digits("EURUSD", 5);
+        cache.handle_bid_ask("EURUSD", 1.1, 1.1002, time_stamp, 100);
+
+        cache.set_markup(
+            "vip",
+            "EURUSD",
+            InstrumentMarkup {
+                bid: Markup::Absolute(0.0001),
+                ask: Markup::Absolute(0.0003),
+            },
+        );
+
+        let candles = cache
+            .get_in_date_range_with_markup(
+                "vip",
+                "EURUSD",
+                date_key,
+                CandleDateKey::new(date_key.get_value() + 1),
+                CandleType::Minute,
+                BidOrAsk::Ask,
+            )
+            .unwrap();
+        assert_eq!(1.1005, candles[0].data.close);
+
+        let candle = cache
+            .get_candle_with_markup("vip", "EURUSD", date_key, CandleType::Minute, BidOrAsk::Bid)
+            .unwrap();
+        assert_eq!(1.0999, candle.data.close);
+
+        // Unknown group gets candles without markup
+        let candle = cache
+            .get_candle_with_markup("std", "EURUSD", date_key, CandleType::Minute, BidOrAsk::Bid)
+            .unwrap();
+        assert_eq!(1.1, candle.data.close);
+
+        assert!(cache.remove_markup_group("vip"));
+        assert!(cache.get_markup("vip", "EURUSD").is_none());
+    }
+
+    #[test]
+    fn test_tick_filter() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        cache.set_tick_filter(
+            "EURUSD",
+            Some(TickFilterSettings {
+                max_deviation_percent: Some(5.0),
+                deviation_reference: DeviationReference::LastPrice,
+                reject_bid_above_ask: true,
+                quarantine_confirm_ticks: None,
+            }),
+        );
+
+        let time_stamp = DateTimeAsMicroseconds::from_str("2021-01-01T10:00:00").unwrap();
+        let date_key = time_stamp.into_candle_date_key(CandleType::Minute);
+
+        cache.handle_bid_ask("EURUSD", 1.1, 1.1002, time_stamp, 100);
+
+        let changes = cache.handle_bid_ask("EURUSD", 11.0, 11.0002, time_stamp, 100);
+        assert!(changes.bids_to_persist.is_empty());
+        assert!(matches!(
+            changes.rejected_ticks[0].reason,
+            TickRejectReason::Deviation { .. }
+        ));
+
+        let changes = cache.handle_bid_ask("EURUSD", f64::INFINITY, 1.1, time_stamp, 100);
+        assert_eq!(
+            TickRejectReason::NonFinite,
+            changes.rejected_ticks[0].reason
+        );
+
+        let candle = cache
+            .get_candle("EURUSD", date_key, CandleType::Minute, BidOrAsk::Bid)
+            .unwrap();
+        assert_eq!(1.1, candle.data.high);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        cache.set_tick_filter(
+            "EURUSD",
+            Some(TickFilterSettings {
+                max_deviation_percent: Some(5.0),
+                deviation_reference: DeviationReference::LastPrice,
+                reject_bid_above_ask: true,
+                quarantine_confirm_ticks: None,
+            }),
+        );
+
+        let start = DateTimeAsMicroseconds::from_str("2021-01-01T10:00:00").unwrap();
+
+        for minute in 0..4 {
+            let time_stamp =
+                DateTimeAsMicroseconds::new(start.unix_microseconds + minute * 60_000_000);
+            cache.handle_bid_ask("EURUSD", 1.1, 1.1002, time_stamp, 2);
+        }
+
+        cache.handle_bid_ask("EURUSD", 11.0, 11.0002, start, 2);
+        cache.handle_bid_ask("GBPUSD", 1.3, 1.3002, start, 2);
+
+        let stats = cache.get_stats();
+
+        assert_eq!(2, stats.instruments_amount);
+        assert_eq!(
+            IngestionCounters {
+                ticks_processed: 5,
+                ticks_dropped: 1,
+                gc_evictions: 2,
+            },
+            stats.counters
+        );
+
+        assert_eq!(4, stats.instruments.len());
+        assert_eq!("EURUSD", stats.instruments[0].instrument_id);
+        assert!(stats.instruments[0].bid_or_ask.to_is_bid());
+        assert_eq!(6, stats.instruments[0].get_candles_amount());
+        assert_eq!(12 + 8, stats.candles_amount);
+        assert!(stats.estimated_bytes > 0);
+
+        let minutes = &cache.get_instrument_stats(BidOrAsk::Ask, "EURUSD").unwrap()[0];
+
+        assert_eq!(3, minutes.candles_amount);
+        assert!(minutes.capacity >= minutes.candles_amount);
+        assert_eq!(Some(CandleDateKey::new(202101011001)), minutes.first_key);
+        assert_eq!(Some(CandleDateKey::new(202101011003)), minutes.last_key);
+
+        cache.enable_tick_buffer("EURUSD", std::time::Duration::from_secs(3600));
+        // A late tick evicts one more minute candle of both sides
+        cache.handle_bid_ask("EURUSD", 1.1, 1.1002, start, 2);
+
+        let stats = cache.get_stats();
+        assert_eq!(4, stats.counters.gc_evictions);
+        assert!(stats.estimated_tick_buffers_bytes > 0);
+        assert_eq!(
+            stats.estimated_bytes,
+            stats.estimated_candles_bytes
+                + stats.estimated_tick_buffers_bytes
+                + stats.estimated_indicators_bytes
+                + stats.estimated_non_time_bars_bytes
+        );
+
+        // Evictions stay counted after the evicting cache is removed
+        cache.clean_by_type(BidOrAsk::Bid, "EURUSD", CandleType::Minute);
+        assert_eq!(4, cache.get_stats().counters.gc_evictions);
+
+        // Hour candle of every instrument and side
+        cache.gc_candles(CandleType::Hour, 0);
+        assert_eq!(4 + 4, cache.get_stats().counters.gc_evictions);
+    }
+
+    #[test]
+    fn test_late_tick_does_not_extend_buffered_range() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let time_stamp = |value: &str| DateTimeAsMicroseconds::from_str(value).unwrap();
+
+        cache.handle_bid_ask("EURUSD", 1.0, 1.0, time_stamp("2021-01-01T10:00:10"), 100);
+        cache.enable_tick_buffer("EURUSD", std::time::Duration::from_secs(3600));
+        cache.handle_bid_ask("EURUSD", 1.1, 1.1, time_stamp("2021-01-01T10:00:30"), 100);
+
+        // Older than the horizon, so it is evicted right away
+        cache.handle_bid_ask("EURUSD", 0.9, 0.9, time_stamp("2021-01-01T08:00:00"), 100);
+
+        assert!(cache
+            .replace_tick("EURUSD", time_stamp("2021-01-01T10:00:30"), 1.2, 1.2)
+            .is_err());
+        assert!(cache
+            .remove_tick("EURUSD", time_stamp("2021-01-01T10:00:30"))
+            .is_err());
+
+        let minute = cache
+            .get_candle(
+                "EURUSD",
+                CandleDateKey::new(202101011000),
+                CandleType::Minute,
+                BidOrAsk::Bid,
+            )
+            .unwrap();
+        assert_eq!(1.0, minute.data.open);
+        assert_eq!(1.1, minute.data.close);
+    }
+
+    #[test]
+    fn test_tick_correction() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let time_stamp = |value: &str| DateTimeAsMicroseconds::from_str(value).unwrap();
+
+        cache.handle_bid_ask("EURUSD", 1.0, 1.0, time_stamp("2021-01-01T09:59:00"), 100);
+        assert!(cache
+            .remove_tick("EURUSD", time_stamp("2021-01-01T09:59:00"))
+            .is_err());
+
+        cache.enable_tick_buffer("EURUSD", std::time::Duration::from_secs(3600));
+
+        cache.handle_bid_ask("EURUSD", 1.1, 1.1, time_stamp("2021-01-01T10:00:00"), 100);
+        cache.handle_bid_ask("EURUSD", 9.9, 9.9, time_stamp("2021-01-01T10:00:30"), 100);
+        cache.handle_bid_ask("EURUSD", 1.2, 1.2, time_stamp("2021-01-01T10:00:40"), 100);
+        cache.handle_bid_ask("EURUSD", 1.3, 1.3, time_stamp("2021-01-01T10:01:10"), 100);
+
+        // The day candle started before the buffer, but minute and hour candles cover it
+        let changes = cache
+            .remove_tick("EURUSD", time_stamp("2021-01-01T10:00:30"))
+            .unwrap();
+        assert_eq!(4, changes.bids_to_persist.len());
+        assert!(changes.bids_to_delete.is_empty());
+
+        let get_bid = |cache: &CandlesInstrumentsCache, candle_type: CandleType| {
+            cache.get_candle(
+                "EURUSD",
+                time_stamp("2021-01-01T10:00:00").into_candle_date_key(candle_type),
+                candle_type,
+                BidOrAsk::Bid,
+            )
+        };
+
+        assert_eq!(1.2, get_bid(&cache, CandleType::Minute).unwrap().data.high);
+        assert_eq!(1.3, get_bid(&cache, CandleType::Hour).unwrap().data.high);
+        assert_eq!(1.3, get_bid(&cache, CandleType::Day).unwrap().data.high);
+        assert_eq!(1.0, get_bid(&cache, CandleType::Month).unwrap().data.open);
+
+        cache
+            .replace_tick("EURUSD", time_stamp("2021-01-01T10:00:00"), 1.15, 1.15)
+            .unwrap();
+        assert_eq!(1.15, get_bid(&cache, CandleType::Minute).unwrap().data.open);
+
+        cache
+            .remove_tick("EURUSD", time_stamp("2021-01-01T10:00:00"))
+            .unwrap();
+        let changes = cache
+            .remove_tick("EURUSD", time_stamp("2021-01-01T10:00:40"))
+            .unwrap();
+        assert_eq!(1, changes.asks_to_delete.len());
+        assert!(get_bid(&cache, CandleType::Minute).is_none());
+        assert_eq!(1.3, get_bid(&cache, CandleType::Hour).unwrap().data.low);
+        assert_eq!(1.0, get_bid(&cache, CandleType::Day).unwrap().data.low);
+
+        assert!(cache
+            .remove_tick("EURUSD", time_stamp("2021-01-01T10:00:40"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_tick_correction_after_adjustment() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let time_stamp = |value: &str| DateTimeAsMicroseconds::from_str(value).unwrap();
+
+        cache.enable_tick_buffer("AAPL", std::time::Duration::from_secs(86400));
+
+        cache.handle_bid_ask("AAPL", 200.0, 200.0, time_stamp("2021-01-13T10:00:00"), 100);
+        cache.handle_bid_ask("AAPL", 210.0, 210.0, time_stamp("2021-01-13T10:00:30"), 100);
+        cache.handle_bid_ask("AAPL", 220.0, 220.0, time_stamp("2021-01-13T10:01:10"), 100);
+        cache.handle_bid_ask("AAPL", 230.0, 230.0, time_stamp("2021-01-13T12:00:00"), 100);
+
+        cache
+            .adjust_prices(
+                "AAPL",
+                CandleDateKey::new(202101131100),
+                PriceAdjustmentKind::Multiply(0.5),
+                time_stamp("2021-01-13T12:30:00"),
+            )
+            .unwrap();
+
+        // The replacement comes in the prices before the split
+        cache
+            .replace_tick("AAPL", time_stamp("2021-01-13T10:00:30"), 240.0, 240.0)
+            .unwrap();
+
+        let get_bid = |cache: &CandlesInstrumentsCache, candle_type: CandleType| {
+            cache
+                .get_candle(
+                    "AAPL",
+                    time_stamp("2021-01-13T10:00:00").into_candle_date_key(candle_type),
+                    candle_type,
+                    BidOrAsk::Bid,
+                )
+                .unwrap()
+                .data
+        };
+
+        assert_eq!(120.0, get_bid(&cache, CandleType::Minute).close);
+        assert_eq!(120.0, get_bid(&cache, CandleType::Hour).high);
+        assert_eq!(100.0, get_bid(&cache, CandleType::Hour).low);
+        // The day of the split is not adjusted
+        assert_eq!(240.0, get_bid(&cache, CandleType::Day).high);
+        assert_eq!(200.0, get_bid(&cache, CandleType::Day).low);
+        assert_eq!(230.0, get_bid(&cache, CandleType::Day).close);
+    }
+
+    #[test]
+    fn test_adjust_prices() {
+        let mut cache = CandlesInstrumentsCache::new();
+        let applied_at = DateTimeAsMicroseconds::from_str("2021-01-15T09:00:00").unwrap();
+
+        let candle = |date_key: u64, price: f64| CandleModel {
+            date_key: CandleDateKey::new(date_key),
+            data: CandleData::new_from_price(price, 0.0),
+        };
+
+        cache.init_candles(
+            BidOrAsk::Bid,
+            "AAPL",
+            CandleType::Day,
+            vec![candle(202101130000, 200.0), candle(202101140000, 210.0)].into_iter(),
+            None,
+        );
+
+        cache.init_candles(
+            BidOrAsk::Bid,
+            "AAPL",
+            CandleType::Month,
+            vec![candle(202012000000, 190.0), candle(202101000000, 200.0)].into_iter(),
+            None,
+        );
+
+        assert!(cache
+            .adjust_prices(
+                "AAPL",
+                CandleDateKey::new(202101140000),
+                PriceAdjustmentKind::Multiply(-1.0),
+                applied_at,
+            )
+            .is_err());
+
+        let changes = cache
+            .adjust_prices(
+                "AAPL",
+                CandleDateKey::new(202101140000),
+                PriceAdjustmentKind::Multiply(0.5),
+                applied_at,
+            )
+            .unwrap();
+        assert_eq!(2, changes.bids_to_persist.len());
+
+        cache
+            .adjust_prices(
+                "AAPL",
+                CandleDateKey::new(202101150000),
+                PriceAdjustmentKind::Add(1.0),
+                applied_at,
+            )
+            .unwrap();
+
+        let get = |cache: &CandlesInstrumentsCache, date_key: u64, candle_type: CandleType| {
+            cache
+                .get_candle(
+                    "AAPL",
+                    CandleDateKey::new(date_key),
+                    candle_type,
+                    BidOrAsk::Bid,
+                )
+                .unwrap()
+                .data
+                .close
+        };
+
+        assert_eq!(101.0, get(&cache, 202101130000, CandleType::Day));
+        assert_eq!(211.0, get(&cache, 202101140000, CandleType::Day));
+        assert_eq!(96.0, get(&cache, 202012000000, CandleType::Month));
+        // The month of the split is not adjusted
+        assert_eq!(200.0, get(&cache, 202101000000, CandleType::Month));
+
+        let unadjusted = cache
+            .get_unadjusted_in_date_range(
+                "AAPL",
+                CandleDateKey::new(202101130000),
+                CandleDateKey::new(202101150000),
+                CandleType::Day,
+                BidOrAsk::Bid,
+            )
+            .unwrap();
+        assert_eq!(200.0, unadjusted[0].data.close);
+        assert_eq!(210.0, unadjusted[1].data.close);
+
+        assert_eq!(2, cache.get_price_adjustments("AAPL").len());
+        assert_eq!(
+            applied_at.unix_microseconds,
+            cache.get_price_adjustments("AAPL")[0]
+                .applied_at
+                .unix_microseconds
+        );
+    }
+
+    #[test]
+    fn test_adjust_prices_after_gc_keeps_heikin_ashi() {
+        let mut cache = CandlesInstrumentsCache::new();
+        let mut expected = CandlesInstrumentsCache::new();
+
+        for minute in 0..6 {
+            for (second, price) in [(0, 100.0), (30, 103.0)] {
+                let price = price + minute as f64 * 3.0;
+                let time_stamp = DateTimeAsMicroseconds::from_str(&format!(
+                    "2021-01-13T10:{:02}:{:02}",
+                    minute, second
+                ))
+                .unwrap();
+
+                cache.handle_bid_ask("AAPL", price, price, time_stamp, 3);
+                expected.handle_bid_ask("AAPL", price * 0.5, price * 0.5, time_stamp, 3);
+            }
+        }
+
+        cache
+            .adjust_prices(
+                "AAPL",
+                CandleDateKey::new(202101140000),
+                PriceAdjustmentKind::Multiply(0.5),
+                DateTimeAsMicroseconds::from_str("2021-01-14T09:00:00").unwrap(),
+            )
+            .unwrap();
+
+        let get = |cache: &CandlesInstrumentsCache| {
+            cache
+                .get_heikin_ashi_in_date_range(
+                    "AAPL",
+                    CandleDateKey::new(202101131000),
+                    CandleDateKey::new(202101131010),
+                    CandleType::Minute,
+                    BidOrAsk::Bid,
+                )
+                .unwrap()
+        };
+
+        let result = get(&cache);
+        let expected = get(&expected);
+
+        assert_eq!(3, result.len());
+
+        for (result, expected) in result.iter().zip(&expected) {
+            assert_eq!(expected.date_key, result.date_key);
+            assert_eq!(expected.data.open, result.data.open);
+            assert_eq!(expected.data.close, result.data.close);
+            assert_eq!(expected.data.high, result.data.high);
+            assert_eq!(expected.data.low, result.data.low);
         }
     }
+
+    #[test]
+    fn test_adjustments_apply_to_late_data() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        let range = cache
+            .add_non_time_bars(
+                "AAPL",
+                NonTimeBarSettings {
+                    bar_type: NonTimeBarType::Range { range: 1.0 },
+                    bid_or_ask: BidOrAsk::Bid,
+                    max_bars_amount: 100,
+                },
+            )
+            .unwrap();
+
+        let time_stamp = |value: &str| DateTimeAsMicroseconds::from_str(value).unwrap();
+
+        cache.handle_bid_ask("AAPL", 200.0, 200.0, time_stamp("2021-01-13T10:00:00"), 100);
+
+        let subscription = cache.subscribe("AAPL", BidOrAsk::Bid, CandleType::Day, 10);
+        let applied_at = time_stamp("2021-01-14T09:00:00");
+
+        let changes = cache
+            .adjust_prices(
+                "AAPL",
+                CandleDateKey::new(202101140000),
+                PriceAdjustmentKind::Multiply(0.5),
+                applied_at,
+            )
+            .unwrap();
+
+        assert_eq!(1, changes.non_time_bars_to_persist.len());
+        assert_eq!(100.0, changes.non_time_bars_to_persist[0].bar.data.close);
+
+        let update = subscription.try_recv().unwrap().unwrap();
+        assert_eq!(202101130000, update.candle.date_key.get_value());
+        assert_eq!(100.0, update.candle.data.close);
+
+        // A late tick before the split goes to the adjusted candles in the adjusted prices
+        cache.handle_bid_ask("AAPL", 210.0, 210.0, time_stamp("2021-01-13T11:00:00"), 100);
+
+        let get = |cache: &CandlesInstrumentsCache, date_key: u64, candle_type: CandleType| {
+            cache
+                .get_candle(
+                    "AAPL",
+                    CandleDateKey::new(date_key),
+                    candle_type,
+                    BidOrAsk::Bid,
+                )
+                .unwrap()
+                .data
+        };
+
+        assert_eq!(105.0, get(&cache, 202101130000, CandleType::Day).close);
+        assert_eq!(100.0, get(&cache, 202101130000, CandleType::Day).open);
+        // The month of the split is not adjusted
+        assert_eq!(210.0, get(&cache, 202101000000, CandleType::Month).close);
+
+        let last_bar = cache
+            .get_non_time_bars(range)
+            .unwrap()
+            .get_last_bar()
+            .unwrap()
+            .clone();
+        assert_eq!(105.0, last_bar.data.open);
+
+        cache.bulk_insert_or_update(
+            BidOrAsk::Bid,
+            "AAPL",
+            CandleType::Day,
+            [CandleModel {
+                date_key: CandleDateKey::new(202101120000),
+                data: CandleData::new_from_price(190.0, 0.0),
+            }]
+            .into_iter(),
+        );
+
+        assert_eq!(95.0, get(&cache, 202101120000, CandleType::Day).close);
+    }
+
+    #[test]
+    fn test_finalize_with_trading_calendar() {
+        let mut cache = CandlesInstrumentsCache::new();
+
+        // Open from Monday 00:00 till Friday 22:00
+        cache.set_trading_calendar(
+            "EURUSD",
+            Some(TradingCalendar::new(vec![WeeklySession {
+                open: WeekTime::new(0, 0, 0),
+                close: WeekTime::new(4, 22, 0),
+            }])),
+        );
+
+        let time_stamp = |value: &str| DateTimeAsMicroseconds::from_str(value).unwrap();
+
+        // 2021-01-08 is Friday
+        cache.handle_bid_ask("EURUSD", 1.1, 1.1, time_stamp("2021-01-08T21:58:00"), 100);
+
+        let settings = FinalizeCandlesSettings {
+            synthesize_empty_candles: true,
+            max_periods_per_series: 10000,
+            max_candles_amount: 10000,
+        };
+
+        cache.finalize_candles(time_stamp("2021-01-08T21:58:30"), &settings);
+
+        let events = cache.finalize_candles(time_stamp("2021-01-08T22:10:00"), &settings);
+        let minutes: Vec<(u64, bool)> = events
+            .iter()
+            .filter(|event| {
+                event.candle_type.to_u8() == CandleType::Minute.to_u8()
+                    && event.bid_or_ask.to_is_bid()
+            })
+            .map(|event| (event.candle.date_key.get_value(), event.synthesized))
+            .collect();
+
+        assert_eq!(vec![(202101082158, false), (202101082159, true)], minutes);
+
+        let in_session = cache.get_in_session_periods(
+            "EURUSD",
+            CandleDateKey::new(202101080000),
+            CandleDateKey::new(202101110000),
+            CandleType::Day,
+        );
+        assert_eq!(1, in_session.len());
+    }
 }
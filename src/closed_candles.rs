@@ -0,0 +1,26 @@
+use crate::{BidOrAsk, CandleModel, CandleType, InstrumentId};
+
+#[derive(Debug, Clone)]
+pub struct FinalizeCandlesSettings {
+    /// Periods without quotes get a flat candle built from the close of the previous candle.
+    pub synthesize_empty_candles: bool,
+    /// Limits the amount of periods walked through per series per call, so catching up after a long pause
+    /// is spread between several calls.
+    pub max_periods_per_series: usize,
+    pub max_candles_amount: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClosedCandle {
+    pub candle: CandleModel,
+    pub synthesized: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClosedCandleEvent {
+    pub instrument_id: InstrumentId,
+    pub bid_or_ask: BidOrAsk,
+    pub candle_type: CandleType,
+    pub candle: CandleModel,
+    pub synthesized: bool,
+}
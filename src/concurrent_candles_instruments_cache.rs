@@ -0,0 +1,560 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{
+    BidAskToHandle, BidOrAsk, CacheStats, CandleDateKey, CandleModel, CandleType,
+    CandlesInstrumentsCache, HandleBidAskChanges, InstrumentId, InstrumentRef, InstrumentsInterner,
+};
+
+pub const DEFAULT_SHARDS_AMOUNT: usize = 16;
+
+/// Same API as [`CandlesInstrumentsCache`] but usable through `&self`.
+///
+/// Instruments are spread between shards by the hash of their name and every shard has its own lock,
+/// so ingesting quotes of one instrument does not block reads of instruments living in other shards.
+/// Instrument ids are issued by one interner shared by all the shards, ids of the shards themselves
+/// are valid only inside [`Self::read_instrument`] and [`Self::write_instrument`] closures.
+/// Synthetic instruments are not supported here since their components may live in different shards.
+pub struct ConcurrentCandlesInstrumentsCache {
+    shards: Vec<RwLock<CandlesInstrumentsCache>>,
+    instruments: RwLock<InstrumentsInterner>,
+}
+
+impl ConcurrentCandlesInstrumentsCache {
+    pub fn new() -> Self {
+        Self::with_shards_amount(DEFAULT_SHARDS_AMOUNT)
+    }
+
+    pub fn with_shards_amount(shards_amount: usize) -> Self {
+        let shards_amount = shards_amount.max(1);
+        let mut shards = Vec::with_capacity(shards_amount);
+
+        for _ in 0..shards_amount {
+            shards.push(RwLock::new(CandlesInstrumentsCache::new()));
+        }
+
+        Self {
+            shards,
+            instruments: RwLock::new(InstrumentsInterner::new()),
+        }
+    }
+
+    pub fn get_shards_amount(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn get_instrument_id(&self, instrument: &str) -> Option<InstrumentId> {
+        let instruments = self
+            .instruments
+            .read()
+            .unwrap_or_else(|err| err.into_inner());
+        instruments.get_id(instrument)
+    }
+
+    /// Registers the instrument if it is met for the first time.
+    pub fn get_or_add_instrument_id(&self, instrument: &str) -> InstrumentId {
+        let mut instruments = self
+            .instruments
+            .write()
+            .unwrap_or_else(|err| err.into_inner());
+        instruments.get_or_add(instrument)
+    }
+
+    pub fn get_instrument_name(&self, instrument_id: InstrumentId) -> Option<String> {
+        let instruments = self
+            .instruments
+            .read()
+            .unwrap_or_else(|err| err.into_inner());
+        instruments
+            .get_name(instrument_id)
+            .map(|name| name.to_string())
+    }
+
+    /// Calls the action with the name of the instrument. Returns `None` if the id is unknown.
+    fn try_with_name<'s, TResult>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        action: impl FnOnce(&str) -> TResult,
+    ) -> Option<TResult> {
+        match instrument.into() {
+            InstrumentRef::Name(name) => Some(action(name)),
+            InstrumentRef::Id(instrument_id) => {
+                let instruments = self
+                    .instruments
+                    .read()
+                    .unwrap_or_else(|err| err.into_inner());
+                let name = instruments.get_name(instrument_id)?;
+                Some(action(name))
+            }
+        }
+    }
+
+    /// Same as [`Self::try_with_name`] for write paths: a name met for the first time is
+    /// registered, an id not issued by this cache panics.
+    fn with_name<'s, TResult>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        action: impl FnOnce(&str) -> TResult,
+    ) -> TResult {
+        let instrument = instrument.into();
+
+        if let InstrumentRef::Name(name) = instrument {
+            self.register_name(name);
+        }
+
+        match self.try_with_name(instrument, action) {
+            Some(result) => result,
+            None => panic!("Unknown instrument {:?}", instrument),
+        }
+    }
+
+    fn register_name(&self, instrument: &str) {
+        if self.get_instrument_id(instrument).is_none() {
+            self.get_or_add_instrument_id(instrument);
+        }
+    }
+
+    fn get_shard_index(&self, instrument_id: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        instrument_id.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    fn read_shard(&self, instrument_id: &str) -> RwLockReadGuard<'_, CandlesInstrumentsCache> {
+        let shard = &self.shards[self.get_shard_index(instrument_id)];
+        shard.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn write_shard(&self, instrument_id: &str) -> RwLockWriteGuard<'_, CandlesInstrumentsCache> {
+        let shard = &self.shards[self.get_shard_index(instrument_id)];
+        shard.write().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn iter_shards_read(
+        &self,
+    ) -> impl Iterator<Item = RwLockReadGuard<'_, CandlesInstrumentsCache>> {
+        self.shards
+            .iter()
+            .map(|shard| shard.read().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    fn iter_shards_write(
+        &self,
+    ) -> impl Iterator<Item = RwLockWriteGuard<'_, CandlesInstrumentsCache>> {
+        self.shards
+            .iter()
+            .map(|shard| shard.write().unwrap_or_else(|err| err.into_inner()))
+    }
+
+    /// Gives read access to the shard which holds the instrument.
+    /// Useful for iterator based queries which can not outlive the lock.
+    /// The closure gets the name of the instrument, ids of this cache are not valid inside the shard.
+    pub fn read_instrument<'s, TResult>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        read: impl FnOnce(&CandlesInstrumentsCache, &str) -> TResult,
+    ) -> TResult {
+        let instrument = instrument.into();
+
+        let result = self.try_with_name(instrument, |name| {
+            let shard = self.read_shard(name);
+            read(&shard, name)
+        });
+
+        match result {
+            Some(result) => result,
+            None => panic!("Unknown instrument {:?}", instrument),
+        }
+    }
+
+    pub fn write_instrument<'s, TResult>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        write: impl FnOnce(&mut CandlesInstrumentsCache, &str) -> TResult,
+    ) -> TResult {
+        self.with_name(instrument, |name| {
+            let mut shard = self.write_shard(name);
+            write(&mut shard, name)
+        })
+    }
+
+    pub fn handle_bid_ask<'s>(
+        &self,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        bid: f64,
+        ask: f64,
+        time_stamp: DateTimeAsMicroseconds,
+        max_candles_amount: usize,
+    ) -> HandleBidAskChanges {
+        self.with_name(instrument_id, |name| {
+            let mut shard = self.write_shard(name);
+            shard.handle_bid_ask(name, bid, ask, time_stamp, max_candles_amount)
+        })
+    }
+
+    pub fn handle_bid_ask_with_volume<'s>(
+        &self,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        bid: f64,
+        ask: f64,
+        volume: f64,
+        time_stamp: DateTimeAsMicroseconds,
+        max_candles_amount: usize,
+    ) -> HandleBidAskChanges {
+        self.with_name(instrument_id, |name| {
+            let mut shard = self.write_shard(name);
+            shard.handle_bid_ask_with_volume(name, bid, ask, volume, time_stamp, max_candles_amount)
+        })
+    }
+
+    /// Quotes are split by shards and every shard is locked once per batch.
+    pub fn handle_bid_ask_batch<'s>(
+        &self,
+        quotes: &[BidAskToHandle<'s>],
+        max_candles_amount: usize,
+    ) -> HashMap<&'s str, HandleBidAskChanges> {
+        let mut by_shard: Vec<Vec<BidAskToHandle<'s>>> = vec![Vec::new(); self.shards.len()];
+
+        for quote in quotes {
+            self.register_name(quote.instrument_id);
+            by_shard[self.get_shard_index(quote.instrument_id)].push(*quote);
+        }
+
+        let mut result = HashMap::new();
+
+        for (shard, quotes) in self.shards.iter().zip(by_shard) {
+            if quotes.is_empty() {
+                continue;
+            }
+
+            let mut shard = shard.write().unwrap_or_else(|err| err.into_inner());
+            result.extend(shard.handle_bid_ask_batch(&quotes, max_candles_amount));
+        }
+
+        result
+    }
+
+    pub fn init_candles<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        candles_to_init: impl Iterator<Item = CandleModel>,
+        pre_allocate_memory: Option<usize>,
+    ) {
+        self.with_name(instrument, |name| {
+            let mut shard = self.write_shard(name);
+            shard.init_candles(
+                bid_or_ask,
+                name,
+                candle_type,
+                candles_to_init,
+                pre_allocate_memory,
+            );
+        })
+    }
+
+    pub fn pre_allocate_memory<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        amount: usize,
+    ) {
+        self.with_name(instrument_id, |name| {
+            let mut shard = self.write_shard(name);
+            shard.pre_allocate_memory(bid_or_ask, name, candle_type, amount);
+        })
+    }
+
+    pub fn get_instruments(&self) -> HashSet<String> {
+        let mut result = HashSet::new();
+
+        for shard in self.iter_shards_read() {
+            result.extend(shard.get_instruments());
+        }
+
+        result
+    }
+
+    pub fn clean_by_type<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+    ) {
+        self.try_with_name(instrument_id, |name| {
+            let mut shard = self.write_shard(name);
+            shard.clean_by_type(bid_or_ask, name, candle_type);
+        });
+    }
+
+    pub fn bulk_insert_or_update<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        candles_to_init: impl Iterator<Item = CandleModel>,
+    ) {
+        self.with_name(instrument, |name| {
+            let mut shard = self.write_shard(name);
+            shard.bulk_insert_or_update(bid_or_ask, name, candle_type, candles_to_init);
+        })
+    }
+
+    pub fn get_candle<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        date_key: CandleDateKey,
+        candle_type: CandleType,
+        bid_or_ask: BidOrAsk,
+    ) -> Option<CandleModel> {
+        self.try_with_name(instrument, |name| {
+            let shard = self.read_shard(name);
+            shard.get_candle(name, date_key, candle_type, bid_or_ask)
+        })?
+    }
+
+    pub fn get_in_date_range<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        from: CandleDateKey,
+        to: CandleDateKey,
+        candle_type: CandleType,
+        bid_or_ask: BidOrAsk,
+    ) -> Option<Vec<CandleModel>> {
+        self.try_with_name(instrument, |name| {
+            let shard = self.read_shard(name);
+            let result = shard.get_in_date_range(name, from, to, candle_type, bid_or_ask)?;
+            Some(result.to_vec())
+        })?
+    }
+
+    pub fn get_highest_and_below<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        highest: CandleDateKey,
+        amount: usize,
+    ) -> Option<Vec<CandleModel>> {
+        self.try_with_name(instrument, |name| {
+            let shard = self.read_shard(name);
+            let result =
+                shard.get_highest_and_below(bid_or_ask, name, candle_type, highest, amount)?;
+            Some(result.to_vec())
+        })?
+    }
+
+    pub fn get_all_from_cache(
+        &self,
+        bid_or_ask: BidOrAsk,
+    ) -> HashMap<String, Vec<(CandleType, Vec<CandleModel>)>> {
+        let mut result = HashMap::new();
+
+        for shard in self.iter_shards_read() {
+            result.extend(shard.get_all_from_cache(bid_or_ask));
+        }
+
+        result
+    }
+
+    pub fn get_all_by_instrument<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+    ) -> Option<Vec<(CandleType, Vec<CandleModel>)>> {
+        self.try_with_name(instrument_id, |name| {
+            let shard = self.read_shard(name);
+            shard.get_all_by_instrument(bid_or_ask, name)
+        })?
+    }
+
+    pub fn gc_candles_by_instrument<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        max_candles_amount: usize,
+    ) {
+        self.try_with_name(instrument, |name| {
+            let mut shard = self.write_shard(name);
+            shard.gc_candles_by_instrument(name, candle_type, max_candles_amount);
+        });
+    }
+
+    pub fn get_first_candle<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+    ) -> Option<CandleModel> {
+        self.try_with_name(instrument, |name| {
+            let shard = self.read_shard(name);
+            shard
+                .get_first_candle(bid_or_ask, name, candle_type)
+                .cloned()
+        })?
+    }
+
+    /// Collects stats of the shards one by one, so they are not a consistent snapshot of the whole cache.
+    pub fn get_stats(&self) -> CacheStats {
+        let mut result = CacheStats::default();
+
+        for shard in self.iter_shards_read() {
+            result.merge(shard.get_stats());
+        }
+
+        result
+    }
+
+    /// Locks shards one by one, so readers of other shards are not blocked while gc is running.
+    pub fn gc_candles(&self, candle_type: CandleType, max_candles_amount: usize) {
+        for mut shard in self.iter_shards_write() {
+            shard.gc_candles(candle_type, max_candles_amount);
+        }
+    }
+}
+
+impl Default for ConcurrentCandlesInstrumentsCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{BidAskToHandle, BidOrAsk, CandleData, CandleDateKey, CandleModel, CandleType};
+
+    use super::ConcurrentCandlesInstrumentsCache;
+
+    #[test]
+    fn test_instruments_are_spread_between_shards() {
+        let cache = ConcurrentCandlesInstrumentsCache::with_shards_amount(4);
+
+        for instrument in ["EURUSD", "USDJPY", "GBPUSD", "XAUUSD", "BTCUSD"] {
+            cache.bulk_insert_or_update(
+                BidOrAsk::Bid,
+                instrument,
+                CandleType::Minute,
+                [CandleModel {
+                    date_key: CandleDateKey::new(202101011022),
+                    data: CandleData::new_from_price(1.5, 0.0),
+                }]
+                .into_iter(),
+            );
+        }
+
+        assert_eq!(5, cache.get_instruments().len());
+
+        let result = cache
+            .get_in_date_range(
+                "XAUUSD",
+                CandleDateKey::new(202101011000),
+                CandleDateKey::new(202101011100),
+                CandleType::Minute,
+                BidOrAsk::Bid,
+            )
+            .unwrap();
+
+        assert_eq!(1, result.len());
+        assert_eq!(202101011022, result[0].date_key.get_value());
+
+        assert!(cache
+            .get_first_candle(BidOrAsk::Ask, "XAUUSD", CandleType::Minute)
+            .is_none());
+    }
+
+    #[test]
+    fn test_names_are_registered_on_ingestion() {
+        let cache = ConcurrentCandlesInstrumentsCache::with_shards_amount(4);
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+
+        cache.handle_bid_ask("EURUSD", 1.1, 1.2, now, 100);
+        cache.handle_bid_ask_batch(
+            &[BidAskToHandle {
+                instrument_id: "USDJPY",
+                bid: 110.0,
+                ask: 110.1,
+                volume: 0.0,
+                time_stamp: now,
+            }],
+            100,
+        );
+
+        for (instrument, close) in [("EURUSD", 1.2), ("USDJPY", 110.1)] {
+            let instrument_id = cache.get_instrument_id(instrument).unwrap();
+            let candle = cache
+                .get_candle(
+                    instrument_id,
+                    CandleDateKey::new(202101011022),
+                    CandleType::Minute,
+                    BidOrAsk::Ask,
+                )
+                .unwrap();
+            assert_eq!(close, candle.data.close);
+        }
+
+        assert!(cache
+            .get_candle(
+                "UNKNOWN",
+                CandleDateKey::new(202101011022),
+                CandleType::Minute,
+                BidOrAsk::Ask,
+            )
+            .is_none());
+        assert!(cache.get_instrument_id("UNKNOWN").is_none());
+    }
+
+    #[test]
+    fn test_instrument_ids_are_global() {
+        let cache = ConcurrentCandlesInstrumentsCache::with_shards_amount(4);
+
+        let ids: Vec<_> = ["EURUSD", "USDJPY", "GBPUSD", "XAUUSD", "BTCUSD"]
+            .into_iter()
+            .map(|instrument| cache.get_or_add_instrument_id(instrument))
+            .collect();
+
+        for (index, id) in ids.iter().enumerate() {
+            assert!(!ids[..index].contains(id));
+        }
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+        cache.handle_bid_ask(ids[3], 1900.0, 1900.5, now, 100);
+
+        let candle = cache
+            .get_candle(
+                "XAUUSD",
+                CandleDateKey::new(202101011022),
+                CandleType::Minute,
+                BidOrAsk::Ask,
+            )
+            .unwrap();
+        assert_eq!(1900.5, candle.data.close);
+
+        assert!(cache
+            .get_first_candle(BidOrAsk::Bid, ids[0], CandleType::Minute)
+            .is_none());
+        assert_eq!(
+            "XAUUSD",
+            cache.read_instrument(ids[3], |_, name| name.to_string())
+        );
+
+        let other = ConcurrentCandlesInstrumentsCache::with_shards_amount(4);
+
+        let unknown = (0..10)
+            .map(|index| other.get_or_add_instrument_id(&format!("INSTR{}", index)))
+            .last()
+            .unwrap();
+
+        assert!(cache
+            .get_first_candle(BidOrAsk::Bid, unknown, CandleType::Minute)
+            .is_none());
+    }
+}
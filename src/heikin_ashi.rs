@@ -0,0 +1,168 @@
+use crate::{CandleData, CandleDateCache, CandleDateKey, CandleModel};
+
+/// Heikin-Ashi candle calculated from the regular candle and the previous Heikin-Ashi candle.
+pub fn to_heikin_ashi(prev: Option<&CandleData>, candle: &CandleData) -> CandleData {
+    let close = (candle.open + candle.high + candle.low + candle.close) / 4.0;
+
+    let open = match prev {
+        Some(prev) => (prev.open + prev.close) / 2.0,
+        None => (candle.open + candle.close) / 2.0,
+    };
+
+    CandleData {
+        open,
+        close,
+        high: candle.high.max(open).max(close),
+        low: candle.low.min(open).min(close),
+        volume: candle.volume,
+    }
+}
+
+/// Heikin-Ashi series of a cached series. The data is maintained by the cache on every update,
+/// so it includes the latest state of the live candle and keeps its values after gc.
+pub struct HeikinAshiView<'s> {
+    candles: &'s [CandleModel],
+    heikin_ashi: &'s [CandleData],
+}
+
+impl<'s> HeikinAshiView<'s> {
+    pub fn new(source: &'s CandleDateCache) -> Self {
+        Self {
+            candles: source.candles.as_slice(),
+            heikin_ashi: source.heikin_ashi.as_slice(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = CandleModel> + 's {
+        self.iter_slice(0, self.candles.len())
+    }
+
+    pub fn get_in_date_range(&self, from: CandleDateKey, to: CandleDateKey) -> Vec<CandleModel> {
+        let start = self.index_of(from);
+        let end = self.index_of(to).max(start);
+
+        self.iter_slice(start, end).collect()
+    }
+
+    pub fn get_highest_and_below(&self, highest: CandleDateKey, amount: usize) -> Vec<CandleModel> {
+        let end = self
+            .candles
+            .partition_point(|candle| candle.date_key <= highest);
+
+        self.iter_slice(end.saturating_sub(amount), end).collect()
+    }
+
+    pub fn get_candle(&self, date_key: CandleDateKey) -> Option<CandleModel> {
+        let index = self
+            .candles
+            .binary_search_by_key(&date_key, |candle| candle.date_key)
+            .ok()?;
+
+        self.iter_slice(index, index + 1).next()
+    }
+
+    fn index_of(&self, date_key: CandleDateKey) -> usize {
+        self.candles
+            .partition_point(|candle| candle.date_key < date_key)
+    }
+
+    fn iter_slice(&self, start: usize, end: usize) -> impl Iterator<Item = CandleModel> + 's {
+        let end = end.min(self.heikin_ashi.len());
+        let start = start.min(end);
+
+        self.candles[start..end]
+            .iter()
+            .zip(&self.heikin_ashi[start..end])
+            .map(|(candle, data)| CandleModel {
+                date_key: candle.date_key,
+                data: *data,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CandleData, CandleDateCache, CandleDateKey, CandleModel, CandleType};
+
+    use super::HeikinAshiView;
+
+    fn candle(date_key: u64, open: f64, close: f64, high: f64, low: f64) -> CandleModel {
+        CandleModel {
+            date_key: CandleDateKey::new(date_key),
+            data: CandleData {
+                open,
+                close,
+                high,
+                low,
+                volume: 0.0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_heikin_ashi() {
+        let mut cache = CandleDateCache::new(CandleType::Minute);
+
+        cache.insert_or_update(candle(202101011000, 10.0, 12.0, 13.0, 9.0));
+        cache.insert_or_update(candle(202101011001, 12.0, 14.0, 16.0, 12.0));
+
+        let view = HeikinAshiView::new(&cache);
+        let result = view.get_in_date_range(
+            CandleDateKey::new(202101011000),
+            CandleDateKey::new(202101011100),
+        );
+
+        assert_eq!(2, result.len());
+
+        assert_eq!(11.0, result[0].data.open);
+        assert_eq!(11.0, result[0].data.close);
+        assert_eq!(13.0, result[0].data.high);
+        assert_eq!(9.0, result[0].data.low);
+
+        assert_eq!(11.0, result[1].data.open);
+        assert_eq!(13.5, result[1].data.close);
+        assert_eq!(16.0, result[1].data.high);
+        assert_eq!(11.0, result[1].data.low);
+
+        cache.handle_price(18.0, CandleDateKey::new(202101011001), None);
+
+        let live = HeikinAshiView::new(&cache)
+            .get_candle(CandleDateKey::new(202101011001))
+            .unwrap();
+
+        assert_eq!(15.0, live.data.close);
+        assert_eq!(18.0, live.data.high);
+    }
+
+    #[test]
+    fn test_values_are_kept_after_gc() {
+        let mut cache = CandleDateCache::new(CandleType::Minute);
+
+        cache.insert_or_update(candle(202101011000, 10.0, 12.0, 13.0, 9.0));
+        cache.insert_or_update(candle(202101011001, 12.0, 14.0, 16.0, 12.0));
+        cache.insert_or_update(candle(202101011002, 14.0, 13.0, 15.0, 12.0));
+
+        let before = cache.heikin_ashi().iter().collect::<Vec<_>>();
+
+        cache.gc_candles(2);
+
+        let after = cache.heikin_ashi().iter().collect::<Vec<_>>();
+
+        assert_eq!(2, after.len());
+
+        for (before, after) in before[1..].iter().zip(&after) {
+            assert_eq!(before.date_key, after.date_key);
+            assert_eq!(before.data.open, after.data.open);
+            assert_eq!(before.data.close, after.data.close);
+        }
+
+        // A candle loaded into the middle recalculates the later ones.
+        cache.insert_or_update(candle(202101011001, 12.0, 12.0, 12.0, 12.0));
+
+        let updated = cache.heikin_ashi().iter().collect::<Vec<_>>();
+
+        assert_eq!(before[1].data.open, updated[0].data.open);
+        assert_eq!(12.0, updated[0].data.close);
+        assert_eq!((before[1].data.open + 12.0) / 2.0, updated[1].data.open);
+    }
+}
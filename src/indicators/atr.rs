@@ -0,0 +1,111 @@
+use crate::CandleData;
+
+use super::{IndicatorCalculator, IndicatorValue};
+
+/// Average true range with Wilder's smoothing.
+pub struct Atr {
+    pub period: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct AtrState {
+    prev_close: Option<f64>,
+    count: usize,
+    atr: f64,
+}
+
+impl Atr {
+    /// Count and average true range after the candle.
+    fn next_atr(&self, state: &AtrState, candle: &CandleData) -> (usize, f64) {
+        let true_range = match state.prev_close {
+            Some(prev_close) => (candle.high - candle.low)
+                .max((candle.high - prev_close).abs())
+                .max((candle.low - prev_close).abs()),
+            None => candle.high - candle.low,
+        };
+
+        let period = self.period as f64;
+
+        if state.count < self.period {
+            (state.count + 1, state.atr + true_range / period)
+        } else {
+            (
+                state.count,
+                (state.atr * (period - 1.0) + true_range) / period,
+            )
+        }
+    }
+}
+
+impl IndicatorCalculator for Atr {
+    type State = AtrState;
+
+    fn new_state(&self) -> Self::State {
+        AtrState {
+            prev_close: None,
+            count: 0,
+            atr: 0.0,
+        }
+    }
+
+    fn commit(&self, state: &mut Self::State, candle: &CandleData) {
+        let (count, atr) = self.next_atr(state, candle);
+        state.count = count;
+        state.atr = atr;
+        state.prev_close = Some(candle.close);
+    }
+
+    fn get_value(&self, state: &Self::State, candle: &CandleData) -> Option<IndicatorValue> {
+        let (count, atr) = self.next_atr(state, candle);
+
+        if count < self.period {
+            return None;
+        }
+
+        Some(IndicatorValue::Single(atr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CandleData, IndicatorCalculator, IndicatorValue};
+
+    use super::Atr;
+
+    fn candle(open: f64, high: f64, low: f64, close: f64) -> CandleData {
+        CandleData {
+            open,
+            close,
+            high,
+            low,
+            volume: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_wilder_smoothing() {
+        let atr = Atr { period: 2 };
+        let mut state = atr.new_state();
+        let mut values = Vec::new();
+
+        // True ranges: 2 (high - low), 3 (gap up from 11 to 14), 4 (low 10 vs previous close 14).
+        for candle in [
+            candle(10.0, 12.0, 10.0, 11.0),
+            candle(12.0, 14.0, 13.0, 13.5),
+            candle(13.5, 13.0, 10.0, 11.0),
+        ] {
+            values.push(atr.get_value(&state, &candle));
+            atr.commit(&mut state, &candle);
+        }
+
+        // Seed (2 + 3) / 2 = 2.5, then (2.5 * 1 + 3.5) / 2 = 3.
+        assert_eq!(
+            vec![
+                None,
+                Some(IndicatorValue::Single(2.5)),
+                Some(IndicatorValue::Single(3.0)),
+            ],
+            values
+        );
+    }
+}
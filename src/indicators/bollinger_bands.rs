@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+
+use crate::CandleData;
+
+use super::{IndicatorCalculator, IndicatorValue};
+
+/// Simple moving average of close prices with bands at `deviations` standard deviations.
+pub struct BollingerBands {
+    pub period: usize,
+    pub deviations: f64,
+}
+
+/// Keeps the latest `period - 1` committed closes, the live candle completes the window.
+#[derive(Debug, Clone)]
+pub struct BollingerBandsState {
+    window: VecDeque<f64>,
+}
+
+impl IndicatorCalculator for BollingerBands {
+    type State = BollingerBandsState;
+
+    fn new_state(&self) -> Self::State {
+        BollingerBandsState {
+            window: VecDeque::with_capacity(self.period),
+        }
+    }
+
+    fn commit(&self, state: &mut Self::State, candle: &CandleData) {
+        state.window.push_back(candle.close);
+
+        while !state.window.is_empty() && state.window.len() >= self.period {
+            state.window.pop_front();
+        }
+    }
+
+    fn get_value(&self, state: &Self::State, candle: &CandleData) -> Option<IndicatorValue> {
+        if self.period == 0 || state.window.len() + 1 < self.period {
+            return None;
+        }
+
+        let period = self.period as f64;
+        let middle = (state.window.iter().sum::<f64>() + candle.close) / period;
+        let variance = (state
+            .window
+            .iter()
+            .map(|close| (close - middle).powi(2))
+            .sum::<f64>()
+            + (candle.close - middle).powi(2))
+            / period;
+        let deviation = variance.sqrt() * self.deviations;
+
+        Some(IndicatorValue::Bands {
+            middle,
+            upper: middle + deviation,
+            lower: middle - deviation,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CandleData, IndicatorCalculator, IndicatorValue};
+
+    use super::BollingerBands;
+
+    #[test]
+    fn test_population_deviation() {
+        let bands = BollingerBands {
+            period: 4,
+            deviations: 2.0,
+        };
+        let mut state = bands.new_state();
+        let mut values = Vec::new();
+
+        for close in [2.0, 4.0, 4.0, 6.0, 10.0] {
+            let candle = CandleData::new_from_price(close, 0.0);
+            values.push(bands.get_value(&state, &candle));
+            bands.commit(&mut state, &candle);
+        }
+
+        assert_eq!(None, values[2]);
+
+        // 2, 4, 4, 6: mean 4, variance (4 + 0 + 0 + 4) / 4 = 2.
+        let deviation = 2.0 * 2f64.sqrt();
+        assert_eq!(
+            Some(IndicatorValue::Bands {
+                middle: 4.0,
+                upper: 4.0 + deviation,
+                lower: 4.0 - deviation,
+            }),
+            values[3]
+        );
+
+        // 4, 4, 6, 10: mean 6, variance (4 + 4 + 0 + 16) / 4 = 6.
+        let deviation = 2.0 * 6f64.sqrt();
+        assert_eq!(
+            Some(IndicatorValue::Bands {
+                middle: 6.0,
+                upper: 6.0 + deviation,
+                lower: 6.0 - deviation,
+            }),
+            values[4]
+        );
+    }
+}
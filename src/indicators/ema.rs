@@ -0,0 +1,90 @@
+use crate::CandleData;
+
+use super::{IndicatorCalculator, IndicatorValue};
+
+/// Exponential moving average of close prices seeded with the simple average of the first `period` closes.
+pub struct Ema {
+    pub period: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct EmaState {
+    count: usize,
+    seed_sum: f64,
+    ema: f64,
+}
+
+impl Ema {
+    fn next_ema(&self, state: &EmaState, close: f64) -> Option<f64> {
+        if state.count + 1 < self.period {
+            return None;
+        }
+
+        if state.count + 1 == self.period {
+            return Some((state.seed_sum + close) / self.period as f64);
+        }
+
+        let alpha = 2.0 / (self.period as f64 + 1.0);
+        Some(alpha * close + (1.0 - alpha) * state.ema)
+    }
+}
+
+impl IndicatorCalculator for Ema {
+    type State = EmaState;
+
+    fn new_state(&self) -> Self::State {
+        EmaState {
+            count: 0,
+            seed_sum: 0.0,
+            ema: 0.0,
+        }
+    }
+
+    fn commit(&self, state: &mut Self::State, candle: &CandleData) {
+        if let Some(ema) = self.next_ema(state, candle.close) {
+            state.ema = ema;
+        }
+
+        if state.count < self.period {
+            state.count += 1;
+            state.seed_sum += candle.close;
+        }
+    }
+
+    fn get_value(&self, state: &Self::State, candle: &CandleData) -> Option<IndicatorValue> {
+        self.next_ema(state, candle.close)
+            .map(IndicatorValue::Single)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CandleData, IndicatorCalculator, IndicatorValue};
+
+    use super::Ema;
+
+    #[test]
+    fn test_seeded_with_sma() {
+        let ema = Ema { period: 3 };
+        let mut state = ema.new_state();
+        let mut values = Vec::new();
+
+        for close in [2.0, 4.0, 6.0, 8.0, 4.0] {
+            let candle = CandleData::new_from_price(close, 0.0);
+            values.push(ema.get_value(&state, &candle));
+            ema.commit(&mut state, &candle);
+        }
+
+        // Seed is (2 + 4 + 6) / 3 = 4, alpha is 0.5: 0.5 * 8 + 0.5 * 4 = 6, 0.5 * 4 + 0.5 * 6 = 5.
+        assert_eq!(
+            vec![
+                None,
+                None,
+                Some(IndicatorValue::Single(4.0)),
+                Some(IndicatorValue::Single(6.0)),
+                Some(IndicatorValue::Single(5.0)),
+            ],
+            values
+        );
+    }
+}
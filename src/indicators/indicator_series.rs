@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+
+use crate::{CandleData, CandleDateCache, CandleDateKey, CandleModel};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndicatorValue {
+    Single(f64),
+    Bands { middle: f64, upper: f64, lower: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndicatorPoint {
+    pub date_key: CandleDateKey,
+    pub value: IndicatorValue,
+}
+
+/// Calculation of an indicator as a fold over candles.
+pub trait IndicatorCalculator {
+    type State;
+
+    fn new_state(&self) -> Self::State;
+
+    /// Adds the closed candle to the state.
+    fn commit(&self, state: &mut Self::State, candle: &CandleData);
+
+    /// Value aligned to the candle as if it was added to the state. Does not change the state,
+    /// so the live candle can be recalculated on every quote.
+    /// The value is `None` while the indicator is warming up.
+    fn get_value(&self, state: &Self::State, candle: &CandleData) -> Option<IndicatorValue>;
+}
+
+/// Indicator attached to a candles series.
+pub trait CandlesIndicator {
+    /// Handles the latest state of a candle. Updates of the live candle recalculate only the last value.
+    fn update(&mut self, candle: &CandleModel, source: &CandleDateCache);
+
+    /// Recalculates all the values from the series.
+    fn rebuild(&mut self, source: &CandleDateCache);
+
+    fn get_values(&self) -> &VecDeque<IndicatorPoint>;
+
+    /// Memory taken by the values. The state of the calculation is not counted.
+    fn get_estimated_bytes(&self) -> usize {
+        self.get_values().capacity() * std::mem::size_of::<IndicatorPoint>()
+    }
+
+    fn get_last_value(&self) -> Option<&IndicatorPoint> {
+        self.get_values().back()
+    }
+
+    fn get_value(&self, date_key: CandleDateKey) -> Option<IndicatorValue> {
+        let values = self.get_values();
+        let index = values
+            .binary_search_by_key(&date_key, |point| point.date_key)
+            .ok()?;
+        Some(values[index].value)
+    }
+
+    fn get_in_date_range(&self, from: CandleDateKey, to: CandleDateKey) -> Vec<IndicatorPoint> {
+        let values = self.get_values();
+        let start = values.partition_point(|point| point.date_key < from);
+
+        values
+            .iter()
+            .skip(start)
+            .take_while(|point| point.date_key < to)
+            .copied()
+            .collect()
+    }
+}
+
+/// Keeps the state of the indicator after all the closed candles, so an update of the live candle
+/// costs one step of the calculation.
+pub struct IndicatorSeries<TCalculator: IndicatorCalculator> {
+    calculator: TCalculator,
+    committed: TCalculator::State,
+    live: Option<CandleModel>,
+    values: VecDeque<IndicatorPoint>,
+    max_values: usize,
+}
+
+impl<TCalculator: IndicatorCalculator> IndicatorSeries<TCalculator> {
+    pub fn new(calculator: TCalculator, max_values: usize) -> Self {
+        Self {
+            committed: calculator.new_state(),
+            calculator,
+            live: None,
+            values: VecDeque::new(),
+            max_values,
+        }
+    }
+
+    fn set_live_value(&mut self, date_key: CandleDateKey, value: Option<IndicatorValue>) {
+        if let Some(last) = self.values.back() {
+            if last.date_key == date_key {
+                self.values.pop_back();
+            }
+        }
+
+        if let Some(value) = value {
+            self.values.push_back(IndicatorPoint { date_key, value });
+        }
+
+        while self.values.len() > self.max_values {
+            self.values.pop_front();
+        }
+    }
+
+    fn handle_candle(&mut self, candle: &CandleModel) {
+        if let Some(live) = self.live.as_ref() {
+            if live.date_key != candle.date_key {
+                self.calculator.commit(&mut self.committed, &live.data);
+            }
+        }
+
+        let value = self.calculator.get_value(&self.committed, &candle.data);
+        self.live = Some(candle.clone());
+        self.set_live_value(candle.date_key, value);
+    }
+}
+
+impl<TCalculator: IndicatorCalculator> CandlesIndicator for IndicatorSeries<TCalculator> {
+    fn update(&mut self, candle: &CandleModel, source: &CandleDateCache) {
+        match self.live.as_ref() {
+            Some(live) if candle.date_key < live.date_key => self.rebuild(source),
+            _ => self.handle_candle(candle),
+        }
+    }
+
+    fn rebuild(&mut self, source: &CandleDateCache) {
+        self.committed = self.calculator.new_state();
+        self.live = None;
+        self.values.clear();
+
+        for candle in source.iter() {
+            self.handle_candle(candle);
+        }
+    }
+
+    fn get_values(&self) -> &VecDeque<IndicatorPoint> {
+        &self.values
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use crate::{
+    BidOrAsk, CandleDateKey, CandleModel, CandleToPersist, CandleType, CandlesCacheByType,
+    InstrumentId,
+};
+
+use super::{
+    Atr, BollingerBands, CandlesIndicator, Ema, IndicatorPoint, IndicatorSeries, Rsi, Sma,
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum IndicatorSettings {
+    Sma { period: usize },
+    Ema { period: usize },
+    Rsi { period: usize },
+    Atr { period: usize },
+    BollingerBands { period: usize, deviations: f64 },
+}
+
+impl IndicatorSettings {
+    fn create(&self, max_values: usize) -> Box<dyn CandlesIndicator + Send + Sync> {
+        match *self {
+            Self::Sma { period } => Box::new(IndicatorSeries::new(
+                Sma {
+                    period: period.max(1),
+                },
+                max_values,
+            )),
+            Self::Ema { period } => Box::new(IndicatorSeries::new(
+                Ema {
+                    period: period.max(1),
+                },
+                max_values,
+            )),
+            Self::Rsi { period } => Box::new(IndicatorSeries::new(
+                Rsi {
+                    period: period.max(1),
+                },
+                max_values,
+            )),
+            Self::Atr { period } => Box::new(IndicatorSeries::new(
+                Atr {
+                    period: period.max(1),
+                },
+                max_values,
+            )),
+            Self::BollingerBands { period, deviations } => Box::new(IndicatorSeries::new(
+                BollingerBands {
+                    period: period.max(1),
+                    deviations,
+                },
+                max_values,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, core::hash::Hash)]
+pub struct IndicatorId(u64);
+
+impl IndicatorId {
+    pub fn get_value(&self) -> u64 {
+        self.0
+    }
+}
+
+struct AttachedIndicator {
+    id: IndicatorId,
+    settings: IndicatorSettings,
+    max_values: usize,
+    indicator: Box<dyn CandlesIndicator + Send + Sync>,
+}
+
+/// Indicators attached to (instrument, bid/ask, candle type) series.
+pub struct IndicatorsRegistry {
+    indicators: HashMap<(InstrumentId, bool, u8), Vec<AttachedIndicator>>,
+    next_id: u64,
+}
+
+impl IndicatorsRegistry {
+    pub fn new() -> Self {
+        Self {
+            indicators: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn attach(
+        &mut self,
+        instrument_id: InstrumentId,
+        bid_or_ask: BidOrAsk,
+        candle_type: CandleType,
+        settings: IndicatorSettings,
+        max_values: usize,
+        source: Option<&CandlesCacheByType>,
+    ) -> IndicatorId {
+        let id = IndicatorId(self.next_id);
+        self.next_id += 1;
+
+        let mut indicator = settings.create(max_values);
+
+        if let Some(source) = source.and_then(|source| source.get_by_type(candle_type)) {
+            indicator.rebuild(source);
+        }
+
+        self.indicators
+            .entry((instrument_id, bid_or_ask.to_is_bid(), candle_type.to_u8()))
+            .or_default()
+            .push(AttachedIndicator {
+                id,
+                settings,
+                max_values,
+                indicator,
+            });
+
+        id
+    }
+
+    pub fn detach(&mut self, id: IndicatorId) -> bool {
+        for indicators in self.indicators.values_mut() {
+            if let Some(index) = indicators.iter().position(|itm| itm.id == id) {
+                indicators.remove(index);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn get(&self, id: IndicatorId) -> Option<&AttachedIndicator> {
+        self.indicators.values().flatten().find(|itm| itm.id == id)
+    }
+
+    pub fn get_settings(&self, id: IndicatorId) -> Option<IndicatorSettings> {
+        Some(self.get(id)?.settings)
+    }
+
+    pub fn get_last_value(&self, id: IndicatorId) -> Option<IndicatorPoint> {
+        self.get(id)?.indicator.get_last_value().copied()
+    }
+
+    pub fn get_in_date_range(
+        &self,
+        id: IndicatorId,
+        from: CandleDateKey,
+        to: CandleDateKey,
+    ) -> Option<Vec<IndicatorPoint>> {
+        Some(self.get(id)?.indicator.get_in_date_range(from, to))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indicators.is_empty()
+    }
+
+    pub fn get_estimated_bytes(&self) -> usize {
+        let entry_size = std::mem::size_of::<((InstrumentId, bool, u8), Vec<AttachedIndicator>)>();
+
+        self.indicators.capacity() * (entry_size + 1)
+            + self
+                .indicators
+                .values()
+                .map(|attached| {
+                    attached.capacity() * std::mem::size_of::<AttachedIndicator>()
+                        + attached
+                            .iter()
+                            .map(|item| {
+                                std::mem::size_of_val(item.indicator.as_ref())
+                                    + item.indicator.get_estimated_bytes()
+                            })
+                            .sum::<usize>()
+                })
+                .sum::<usize>()
+    }
+
+    /// Applies candle changes produced by the ingestion.
+    pub fn handle_changes(
+        &mut self,
+        instrument_id: InstrumentId,
+        bid_or_ask: BidOrAsk,
+        changes: &[CandleToPersist],
+        source: Option<&CandlesCacheByType>,
+    ) {
+        if self.indicators.is_empty() {
+            return;
+        }
+
+        let source = match source {
+            Some(source) => source,
+            None => return,
+        };
+
+        for change in changes {
+            let key = (
+                instrument_id,
+                bid_or_ask.to_is_bid(),
+                change.candle_type.to_u8(),
+            );
+
+            let indicators = match self.indicators.get_mut(&key) {
+                Some(indicators) => indicators,
+                None => continue,
+            };
+
+            let candles = match source.get_by_type(change.candle_type) {
+                Some(candles) => candles,
+                None => continue,
+            };
+
+            let candle = CandleModel {
+                date_key: change.date_key,
+                data: change.data,
+            };
+
+            for attached in indicators {
+                attached.indicator.update(&candle, candles);
+            }
+        }
+    }
+
+    /// Recalculates indicators of the instrument after its candles were changed not by the ingestion.
+    pub fn rebuild(
+        &mut self,
+        instrument_id: InstrumentId,
+        bid_or_ask: BidOrAsk,
+        source: Option<&CandlesCacheByType>,
+    ) {
+        for candle_type in CandleType::ALL_CANDLE_TYPES {
+            let key = (instrument_id, bid_or_ask.to_is_bid(), candle_type.to_u8());
+
+            let indicators = match self.indicators.get_mut(&key) {
+                Some(indicators) => indicators,
+                None => continue,
+            };
+
+            let candles = source.and_then(|source| source.get_by_type(candle_type));
+
+            for attached in indicators {
+                match candles {
+                    Some(candles) => attached.indicator.rebuild(candles),
+                    None => attached.indicator = attached.settings.create(attached.max_values),
+                }
+            }
+        }
+    }
+}
+
+impl Default for IndicatorsRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,15 @@
+mod atr;
+mod bollinger_bands;
+mod ema;
+mod indicator_series;
+mod indicators_registry;
+mod rsi;
+mod sma;
+
+pub use atr::*;
+pub use bollinger_bands::*;
+pub use ema::*;
+pub use indicator_series::*;
+pub use indicators_registry::*;
+pub use rsi::*;
+pub use sma::*;
@@ -0,0 +1,118 @@
+use crate::CandleData;
+
+use super::{IndicatorCalculator, IndicatorValue};
+
+/// Relative strength index with Wilder's smoothing.
+pub struct Rsi {
+    pub period: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RsiState {
+    prev_close: Option<f64>,
+    count: usize,
+    avg_gain: f64,
+    avg_loss: f64,
+}
+
+impl Rsi {
+    /// Count and average gain and loss after the close. `None` for the very first close.
+    fn next_averages(&self, state: &RsiState, close: f64) -> Option<(usize, f64, f64)> {
+        let change = close - state.prev_close?;
+        let gain = change.max(0.0);
+        let loss = (-change).max(0.0);
+        let period = self.period as f64;
+
+        if state.count < self.period {
+            return Some((
+                state.count + 1,
+                state.avg_gain + gain / period,
+                state.avg_loss + loss / period,
+            ));
+        }
+
+        Some((
+            state.count,
+            (state.avg_gain * (period - 1.0) + gain) / period,
+            (state.avg_loss * (period - 1.0) + loss) / period,
+        ))
+    }
+}
+
+impl IndicatorCalculator for Rsi {
+    type State = RsiState;
+
+    fn new_state(&self) -> Self::State {
+        RsiState {
+            prev_close: None,
+            count: 0,
+            avg_gain: 0.0,
+            avg_loss: 0.0,
+        }
+    }
+
+    fn commit(&self, state: &mut Self::State, candle: &CandleData) {
+        if let Some((count, avg_gain, avg_loss)) = self.next_averages(state, candle.close) {
+            state.count = count;
+            state.avg_gain = avg_gain;
+            state.avg_loss = avg_loss;
+        }
+
+        state.prev_close = Some(candle.close);
+    }
+
+    fn get_value(&self, state: &Self::State, candle: &CandleData) -> Option<IndicatorValue> {
+        let (count, avg_gain, avg_loss) = self.next_averages(state, candle.close)?;
+
+        if count < self.period {
+            return None;
+        }
+
+        let rsi = if avg_loss == 0.0 {
+            100.0
+        } else {
+            100.0 - 100.0 / (1.0 + avg_gain / avg_loss)
+        };
+
+        Some(IndicatorValue::Single(rsi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CandleData, IndicatorCalculator, IndicatorValue};
+
+    use super::Rsi;
+
+    #[test]
+    fn test_wilder_smoothing() {
+        let rsi = Rsi { period: 2 };
+        let mut state = rsi.new_state();
+        let mut values = Vec::new();
+
+        for close in [10.0, 12.0, 11.0, 14.0, 14.0] {
+            let candle = CandleData::new_from_price(close, 0.0);
+            values.push(rsi.get_value(&state, &candle));
+            rsi.commit(&mut state, &candle);
+        }
+
+        // Changes: +2, -1, +3, 0.
+        // Seed: gain 1, loss 0.5 -> RSI 66.67.
+        // Then gain (1 + 3) / 2 = 2, loss 0.25 -> 88.89, then gain 1, loss 0.125 -> 88.89.
+        assert_eq!(None, values[0]);
+        assert_eq!(None, values[1]);
+
+        let expected = [
+            100.0 - 100.0 / 3.0,
+            100.0 - 100.0 / 9.0,
+            100.0 - 100.0 / 9.0,
+        ];
+
+        for (value, expected) in values[2..].iter().zip(expected) {
+            match value {
+                Some(IndicatorValue::Single(value)) => assert!((value - expected).abs() < 1e-9),
+                _ => panic!("Expected a value, got {:?}", value),
+            }
+        }
+    }
+}
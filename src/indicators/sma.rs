@@ -0,0 +1,81 @@
+use std::collections::VecDeque;
+
+use crate::CandleData;
+
+use super::{IndicatorCalculator, IndicatorValue};
+
+/// Simple moving average of close prices.
+pub struct Sma {
+    pub period: usize,
+}
+
+/// Keeps the latest `period - 1` committed closes, the live candle completes the window.
+#[derive(Debug, Clone)]
+pub struct SmaState {
+    window: VecDeque<f64>,
+    sum: f64,
+}
+
+impl IndicatorCalculator for Sma {
+    type State = SmaState;
+
+    fn new_state(&self) -> Self::State {
+        SmaState {
+            window: VecDeque::with_capacity(self.period),
+            sum: 0.0,
+        }
+    }
+
+    fn commit(&self, state: &mut Self::State, candle: &CandleData) {
+        state.window.push_back(candle.close);
+        state.sum += candle.close;
+
+        while state.window.len() >= self.period {
+            match state.window.pop_front() {
+                Some(removed) => state.sum -= removed,
+                None => break,
+            }
+        }
+    }
+
+    fn get_value(&self, state: &Self::State, candle: &CandleData) -> Option<IndicatorValue> {
+        if self.period == 0 || state.window.len() + 1 < self.period {
+            return None;
+        }
+
+        Some(IndicatorValue::Single(
+            (state.sum + candle.close) / self.period as f64,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CandleData, IndicatorCalculator, IndicatorValue};
+
+    use super::Sma;
+
+    #[test]
+    fn test_known_values() {
+        let sma = Sma { period: 3 };
+        let mut state = sma.new_state();
+        let mut values = Vec::new();
+
+        for close in [1.0, 2.0, 3.0, 4.0, 8.0] {
+            let candle = CandleData::new_from_price(close, 0.0);
+            values.push(sma.get_value(&state, &candle));
+            sma.commit(&mut state, &candle);
+        }
+
+        assert_eq!(
+            vec![
+                None,
+                None,
+                Some(IndicatorValue::Single(2.0)),
+                Some(IndicatorValue::Single(3.0)),
+                Some(IndicatorValue::Single(5.0)),
+            ],
+            values
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+
+/// Integer handle of an instrument issued by [`InstrumentsInterner`].
+/// A handle is valid only for the cache which issued it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, core::hash::Hash, PartialOrd, Ord)]
+pub struct InstrumentId(u32);
+
+impl InstrumentId {
+    pub fn get_value(&self) -> u32 {
+        self.0
+    }
+
+    fn as_index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Instrument given either by name or by an already resolved [`InstrumentId`].
+#[derive(Debug, Clone, Copy)]
+pub enum InstrumentRef<'s> {
+    Id(InstrumentId),
+    Name(&'s str),
+}
+
+impl<'s> From<&'s str> for InstrumentRef<'s> {
+    fn from(value: &'s str) -> Self {
+        Self::Name(value)
+    }
+}
+
+impl<'s> From<&'s String> for InstrumentRef<'s> {
+    fn from(value: &'s String) -> Self {
+        Self::Name(value.as_str())
+    }
+}
+
+impl From<InstrumentId> for InstrumentRef<'static> {
+    fn from(value: InstrumentId) -> Self {
+        Self::Id(value)
+    }
+}
+
+pub struct InstrumentsInterner {
+    ids: HashMap<String, InstrumentId>,
+    names: Vec<String>,
+}
+
+impl InstrumentsInterner {
+    pub fn new() -> Self {
+        Self {
+            ids: HashMap::new(),
+            names: Vec::new(),
+        }
+    }
+
+    pub fn get_id(&self, name: &str) -> Option<InstrumentId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn get_or_add(&mut self, name: &str) -> InstrumentId {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+
+        let id = InstrumentId(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    pub fn get_name(&self, id: InstrumentId) -> Option<&str> {
+        self.names.get(id.as_index()).map(|name| name.as_str())
+    }
+
+    pub fn resolve<'s>(&self, instrument: impl Into<InstrumentRef<'s>>) -> Option<InstrumentId> {
+        match instrument.into() {
+            InstrumentRef::Id(id) => {
+                if id.as_index() < self.names.len() {
+                    Some(id)
+                } else {
+                    None
+                }
+            }
+            InstrumentRef::Name(name) => self.get_id(name),
+        }
+    }
+
+    /// Resolves the instrument registering the name if it is met for the first time.
+    /// Panics if the id was not issued by this interner.
+    pub fn resolve_or_add<'s>(&mut self, instrument: impl Into<InstrumentRef<'s>>) -> InstrumentId {
+        match instrument.into() {
+            InstrumentRef::Id(id) => {
+                if id.as_index() >= self.names.len() {
+                    panic!("Unknown instrument id {}", id.get_value());
+                }
+                id
+            }
+            InstrumentRef::Name(name) => self.get_or_add(name),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (InstrumentId, &str)> {
+        self.names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (InstrumentId(index as u32), name.as_str()))
+    }
+}
+
+impl Default for InstrumentsInterner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Dense storage of per instrument values indexed by [`InstrumentId`].
+pub struct InstrumentsMap<T> {
+    items: Vec<Option<T>>,
+}
+
+impl<T> InstrumentsMap<T> {
+    pub fn new() -> Self {
+        Self { items: Vec::new() }
+    }
+
+    pub fn get(&self, id: InstrumentId) -> Option<&T> {
+        self.items.get(id.as_index())?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: InstrumentId) -> Option<&mut T> {
+        self.items.get_mut(id.as_index())?.as_mut()
+    }
+
+    pub fn contains(&self, id: InstrumentId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn get_or_insert_with(&mut self, id: InstrumentId, create: impl FnOnce() -> T) -> &mut T {
+        let index = id.as_index();
+
+        if self.items.len() <= index {
+            self.items.resize_with(index + 1, || None);
+        }
+
+        self.items[index].get_or_insert_with(create)
+    }
+
+    pub fn insert(&mut self, id: InstrumentId, value: T) -> Option<T> {
+        let index = id.as_index();
+
+        if self.items.len() <= index {
+            self.items.resize_with(index + 1, || None);
+        }
+
+        self.items[index].replace(value)
+    }
+
+    pub fn remove(&mut self, id: InstrumentId) -> Option<T> {
+        self.items.get_mut(id.as_index())?.take()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (InstrumentId, &T)> {
+        self.items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| Some((InstrumentId(index as u32), item.as_ref()?)))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (InstrumentId, &mut T)> {
+        self.items
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, item)| Some((InstrumentId(index as u32), item.as_mut()?)))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.items.iter().filter_map(|item| item.as_ref())
+    }
+
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.items.iter_mut().filter_map(|item| item.as_mut())
+    }
+}
+
+impl<T> Default for InstrumentsMap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InstrumentRef, InstrumentsInterner, InstrumentsMap};
+
+    #[test]
+    fn test_interner() {
+        let mut interner = InstrumentsInterner::new();
+
+        let eurusd = interner.get_or_add("EURUSD");
+        let usdjpy = interner.get_or_add("USDJPY");
+
+        assert_eq!(eurusd, interner.get_or_add("EURUSD"));
+        assert_ne!(eurusd, usdjpy);
+        assert_eq!(Some("USDJPY"), interner.get_name(usdjpy));
+
+        assert_eq!(Some(eurusd), interner.resolve("EURUSD"));
+        assert_eq!(Some(usdjpy), interner.resolve(usdjpy));
+        assert_eq!(None, interner.resolve(InstrumentRef::Name("GBPUSD")));
+    }
+
+    #[test]
+    fn test_instruments_map() {
+        let mut interner = InstrumentsInterner::new();
+        let eurusd = interner.get_or_add("EURUSD");
+        let usdjpy = interner.get_or_add("USDJPY");
+
+        let mut map = InstrumentsMap::new();
+        *map.get_or_insert_with(usdjpy, || 0) += 5;
+
+        assert!(map.get(eurusd).is_none());
+        assert_eq!(Some(&5), map.get(usdjpy));
+        assert_eq!(vec![(usdjpy, &5)], map.iter().collect::<Vec<_>>());
+    }
+}
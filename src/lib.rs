@@ -1,15 +1,49 @@
 mod bid_or_ask;
+mod cache_stats;
 mod candle_date_cache;
+mod candle_subscriptions;
 
 mod candles_cache_by_type;
 mod candles_instrument_cache;
+mod closed_candles;
+mod concurrent_candles_instruments_cache;
+mod heikin_ashi;
+mod indicators;
+mod instrument_id;
+mod markup_profiles;
 mod models;
+mod non_time_bars;
+mod period_arithmetic;
+mod price_adjustments;
+mod replay;
+mod snapshot_candles_instruments_cache;
+mod synthetic_instruments;
+mod tick_buffer;
+mod tick_filter;
+mod trading_calendar;
 
 pub use bid_or_ask::*;
+pub use cache_stats::*;
 
 pub use candles_cache_by_type::*;
 pub use candles_instrument_cache::*;
+pub use closed_candles::*;
+pub use concurrent_candles_instruments_cache::*;
+pub use heikin_ashi::*;
+pub use indicators::*;
+pub use instrument_id::*;
 
 pub use candle_date_cache::*;
+pub use candle_subscriptions::*;
+pub use markup_profiles::*;
 pub use models::*;
+pub use non_time_bars::*;
+pub use period_arithmetic::*;
+pub use price_adjustments::*;
+pub use replay::*;
+pub use snapshot_candles_instruments_cache::*;
+pub use synthetic_instruments::*;
+pub use tick_buffer::*;
+pub use tick_filter::*;
+pub use trading_calendar::*;
 pub mod utils;
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use crate::{round_price, BidOrAsk, CandleData, CandleModel, InstrumentId};
+
+/// Markup widening the spread: it is subtracted from the bid and added to the ask.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Markup {
+    /// Fixed markup in price units, e.g. 2 pips of EURUSD is `Absolute(0.0002)`.
+    Absolute(f64),
+    /// Markup in percent of the price.
+    Percent(f64),
+}
+
+impl Markup {
+    pub fn apply(&self, price: f64, bid_or_ask: BidOrAsk) -> f64 {
+        let delta = match self {
+            Self::Absolute(value) => *value,
+            Self::Percent(percent) => price * percent / 100.0,
+        };
+
+        match bid_or_ask {
+            BidOrAsk::Bid => price - delta,
+            BidOrAsk::Ask => price + delta,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstrumentMarkup {
+    pub bid: Markup,
+    pub ask: Markup,
+}
+
+impl InstrumentMarkup {
+    pub fn get(&self, bid_or_ask: BidOrAsk) -> Markup {
+        match bid_or_ask {
+            BidOrAsk::Bid => self.bid,
+            BidOrAsk::Ask => self.ask,
+        }
+    }
+
+    /// Rounds the marked up prices to `digits` if given.
+    pub fn apply_to_candle(
+        &self,
+        candle: &CandleModel,
+        bid_or_ask: BidOrAsk,
+        digits: Option<u32>,
+    ) -> CandleModel {
+        let markup = self.get(bid_or_ask);
+
+        let apply = |price: f64| {
+            let price = markup.apply(price, bid_or_ask);
+
+            match digits {
+                Some(digits) => round_price(price, digits),
+                None => price,
+            }
+        };
+
+        CandleModel {
+            date_key: candle.date_key,
+            data: CandleData {
+                open: apply(candle.data.open),
+                close: apply(candle.data.close),
+                high: apply(candle.data.high),
+                low: apply(candle.data.low),
+                volume: candle.data.volume,
+            },
+        }
+    }
+}
+
+/// Markups of the instruments by trading group.
+pub struct MarkupProfiles {
+    groups: HashMap<String, HashMap<InstrumentId, InstrumentMarkup>>,
+}
+
+impl MarkupProfiles {
+    pub fn new() -> Self {
+        Self {
+            groups: HashMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, group: &str, instrument_id: InstrumentId, markup: InstrumentMarkup) {
+        match self.groups.get_mut(group) {
+            Some(markups) => {
+                markups.insert(instrument_id, markup);
+            }
+            None => {
+                let mut markups = HashMap::new();
+                markups.insert(instrument_id, markup);
+                self.groups.insert(group.to_string(), markups);
+            }
+        }
+    }
+
+    pub fn get(&self, group: &str, instrument_id: InstrumentId) -> Option<InstrumentMarkup> {
+        self.groups.get(group)?.get(&instrument_id).copied()
+    }
+
+    pub fn remove(&mut self, group: &str, instrument_id: InstrumentId) -> Option<InstrumentMarkup> {
+        let markups = self.groups.get_mut(group)?;
+        let result = markups.remove(&instrument_id);
+
+        if markups.is_empty() {
+            self.groups.remove(group);
+        }
+
+        result
+    }
+
+    pub fn remove_group(&mut self, group: &str) -> bool {
+        self.groups.remove(group).is_some()
+    }
+
+    pub fn get_groups(&self) -> impl Iterator<Item = &str> {
+        self.groups.keys().map(|group| group.as_str())
+    }
+}
+
+impl Default for MarkupProfiles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BidOrAsk, CandleData, CandleDateKey, CandleModel};
+
+    use super::{InstrumentMarkup, Markup};
+
+    #[test]
+    fn test_apply_to_candle() {
+        let markup = InstrumentMarkup {
+            bid: Markup::Absolute(0.0002),
+            ask: Markup::Percent(1.0),
+        };
+
+        let candle = CandleModel {
+            date_key: CandleDateKey::new(202101011000),
+            data: CandleData {
+                open: 1.1,
+                close: 1.2,
+                high: 1.3,
+                low: 1.0,
+                volume: 5.0,
+            },
+        };
+
+        let bid = markup.apply_to_candle(&candle, BidOrAsk::Bid, Some(5));
+        assert_eq!(1.0998, bid.data.open);
+        assert_eq!(1.2998, bid.data.high);
+        assert_eq!(0.9998, bid.data.low);
+        assert_eq!(5.0, bid.data.volume);
+
+        let ask = markup.apply_to_candle(&candle, BidOrAsk::Ask, Some(5));
+        assert_eq!(1.111, ask.data.open);
+        assert_eq!(1.212, ask.data.close);
+        assert_eq!(1.01, ask.data.low);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::{round_price, FixedPointPrice};
+
 #[derive(Debug, Clone, Copy)]
 pub struct CandleData {
     pub open: f64,
@@ -24,4 +26,48 @@ impl CandleData {
         self.low = self.low.min(price);
         self.volume += volume;
     }
+
+    /// Same as [`Self::new_from_price`] with the price rounded to `digits` in fixed-point mode.
+    pub fn new_from_price_with_digits(price: f64, volume: f64, digits: Option<u32>) -> Self {
+        Self::new_from_price(round_price_to(price, digits), volume)
+    }
+
+    /// Same as [`Self::update_from_price`] with the price rounded to `digits` in fixed-point mode.
+    pub fn update_from_price_with_digits(&mut self, price: f64, volume: f64, digits: Option<u32>) {
+        self.update_from_price(round_price_to(price, digits), volume);
+    }
+
+    /// Rounds open, close, high and low to the given amount of digits.
+    pub fn round_to_digits(&mut self, digits: u32) {
+        self.open = round_price(self.open, digits);
+        self.close = round_price(self.close, digits);
+        self.high = round_price(self.high, digits);
+        self.low = round_price(self.low, digits);
+    }
+
+    pub fn to_fixed_point(&self, digits: u32) -> FixedPointCandleData {
+        FixedPointCandleData {
+            open: FixedPointPrice::from_f64(self.open, digits),
+            close: FixedPointPrice::from_f64(self.close, digits),
+            high: FixedPointPrice::from_f64(self.high, digits),
+            low: FixedPointPrice::from_f64(self.low, digits),
+            volume: self.volume,
+        }
+    }
+}
+
+fn round_price_to(price: f64, digits: Option<u32>) -> f64 {
+    match digits {
+        Some(digits) => round_price(price, digits),
+        None => price,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedPointCandleData {
+    pub open: FixedPointPrice,
+    pub close: FixedPointPrice,
+    pub high: FixedPointPrice,
+    pub low: FixedPointPrice,
+    pub volume: f64,
 }
@@ -1,4 +1,6 @@
-use rust_extensions::date_time::{DateTimeAsMicroseconds, DateTimeStruct, TimeStruct};
+use rust_extensions::date_time::{
+    DateTimeAsMicroseconds, DateTimeStruct, TimeStruct, MICRO_SECONDS_IN_ONE_MINUTE,
+};
 
 use crate::CandleType;
 
@@ -67,60 +69,160 @@ impl CandleDateKey {
     }
 
     pub fn get_next_period_date_key(&self, candle_type: CandleType) -> CandleDateKey {
-        match candle_type {
-            CandleType::Minute => {
-                let mut dt: DateTimeAsMicroseconds = self.into();
-                dt.add_minutes(1);
-                return dt.into_candle_date_key(candle_type);
-            }
-            CandleType::Hour => {
-                let mut dt: DateTimeAsMicroseconds = self.into();
-                dt.add_hours(1);
-                return dt.into_candle_date_key(candle_type);
-            }
-            CandleType::Day => {
-                let mut dt: DateTimeAsMicroseconds = self.into();
-                dt.add_days(1);
-                return dt.into_candle_date_key(candle_type);
-            }
-            CandleType::Month => {
-                let dt: DateTimeAsMicroseconds = self.into();
-                let mut dt: DateTimeStruct = dt.into();
-                dt.inc_month();
-                let dt = dt.to_date_time_as_microseconds().unwrap();
-                return dt.into_candle_date_key(candle_type);
-            }
-        }
+        self.add_periods(1, candle_type)
     }
 
     pub fn get_prev_period_date_key(&self, candle_type: CandleType) -> CandleDateKey {
-        match candle_type {
-            CandleType::Minute => {
-                let mut dt: DateTimeAsMicroseconds = self.into();
-                dt.add_minutes(-1);
-                return dt.into_candle_date_key(candle_type);
-            }
-            CandleType::Hour => {
-                let mut dt: DateTimeAsMicroseconds = self.into();
-                dt.add_hours(-1);
-                return dt.into_candle_date_key(candle_type);
-            }
-            CandleType::Day => {
-                let mut dt: DateTimeAsMicroseconds = self.into();
-                dt.add_days(-1);
-                return dt.into_candle_date_key(candle_type);
+        self.add_periods(-1, candle_type)
+    }
+
+    /// Key of the period of the candle type containing this key.
+    pub fn floor_to(&self, candle_type: CandleType) -> CandleDateKey {
+        from_minutes(self.get_minutes(), candle_type)
+    }
+
+    /// Key of the period `periods` periods away from the period containing this key.
+    pub fn add_periods(&self, periods: i64, candle_type: CandleType) -> CandleDateKey {
+        match get_period_minutes(candle_type) {
+            Some(period_minutes) => from_minutes(
+                self.get_minutes().div_euclid(period_minutes) * period_minutes
+                    + periods * period_minutes,
+                candle_type,
+            ),
+            None => {
+                let month_index = self.get_month_index() + periods;
+                let year = month_index.div_euclid(12) as u64;
+                let month = month_index.rem_euclid(12) as u64 + 1;
+                CandleDateKey::new(year * 100000000 + month * 1000000)
             }
-            CandleType::Month => {
-                let dt: DateTimeAsMicroseconds = self.into();
-                let mut dt: DateTimeStruct = dt.into();
-                dt.dec_month();
-                let dt = dt.to_date_time_as_microseconds().unwrap();
-                return dt.into_candle_date_key(candle_type);
+        }
+    }
+
+    /// Amount of periods from the period containing this key to the one containing `to`.
+    /// Negative if `to` is before.
+    pub fn diff_periods(&self, to: CandleDateKey, candle_type: CandleType) -> i64 {
+        match get_period_minutes(candle_type) {
+            Some(period_minutes) => {
+                to.get_minutes().div_euclid(period_minutes)
+                    - self.get_minutes().div_euclid(period_minutes)
             }
+            None => to.get_month_index() - self.get_month_index(),
+        }
+    }
+
+    /// Keys of the candle type from the period containing this key up to `to` (exclusive).
+    pub fn iter_to(&self, to: CandleDateKey, candle_type: CandleType) -> CandleDateKeyRange {
+        CandleDateKeyRange::new(*self, to, candle_type)
+    }
+
+    fn get_parts(&self) -> (i64, i64, i64, i64, i64) {
+        let value = self.0 as i64;
+
+        (
+            value / 100000000,
+            value / 1000000 % 100,
+            value / 10000 % 100,
+            value / 100 % 100,
+            value % 100,
+        )
+    }
+
+    /// Minutes since unix epoch. Day 0 of a month key is treated as the first day.
+    fn get_minutes(&self) -> i64 {
+        let (year, month, day, hour, minute) = self.get_parts();
+        days_from_civil(year, month, day.max(1)) * 1440 + hour * 60 + minute
+    }
+
+    fn get_month_index(&self) -> i64 {
+        let (year, month, _, _, _) = self.get_parts();
+        year * 12 + month - 1
+    }
+}
+
+/// Iterates keys of the candle type within `from..to`.
+#[derive(Debug, Clone)]
+pub struct CandleDateKeyRange {
+    next: CandleDateKey,
+    to: CandleDateKey,
+    candle_type: CandleType,
+}
+
+impl CandleDateKeyRange {
+    pub fn new(from: CandleDateKey, to: CandleDateKey, candle_type: CandleType) -> Self {
+        Self {
+            next: from.floor_to(candle_type),
+            to,
+            candle_type,
         }
     }
 }
 
+impl Iterator for CandleDateKeyRange {
+    type Item = CandleDateKey;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.to {
+            return None;
+        }
+
+        let result = self.next;
+        self.next = result.add_periods(1, self.candle_type);
+        Some(result)
+    }
+}
+
+fn get_period_minutes(candle_type: CandleType) -> Option<i64> {
+    match candle_type {
+        CandleType::Minute => Some(1),
+        CandleType::Hour => Some(60),
+        CandleType::Day => Some(1440),
+        CandleType::Month => None,
+    }
+}
+
+fn from_minutes(minutes: i64, candle_type: CandleType) -> CandleDateKey {
+    let (year, month, day) = civil_from_days(minutes.div_euclid(1440));
+    let minutes = minutes.rem_euclid(1440);
+
+    let value = (year * 100000000 + month * 1000000) as u64;
+
+    let value = match candle_type {
+        CandleType::Minute => value + (day * 10000 + minutes / 60 * 100 + minutes % 60) as u64,
+        CandleType::Hour => value + (day * 10000 + minutes / 60 * 100) as u64,
+        CandleType::Day => value + (day * 10000) as u64,
+        CandleType::Month => value,
+    };
+
+    CandleDateKey::new(value)
+}
+
+/// Days since unix epoch of the civil date.
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// Civil date (year, month, day) of the days since unix epoch.
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400;
+
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
 impl Into<CandleDateKey> for u64 {
     fn into(self) -> CandleDateKey {
         CandleDateKey::new(self)
@@ -139,16 +241,11 @@ pub trait GetCandleDateKey {
 
 impl GetCandleDateKey for DateTimeAsMicroseconds {
     fn into_candle_date_key(&self, candle_type: CandleType) -> CandleDateKey {
-        let format = match candle_type {
-            CandleType::Minute => "%Y%m%d%H%M",
-            CandleType::Hour => "%Y%m%d%H",
-            CandleType::Day => "%Y%m%d",
-            CandleType::Month => "%Y%m",
-        };
-
-        let date = format!("{:0<12}", self.to_chrono_utc().format(format));
-
-        return CandleDateKey::new(date.parse().unwrap());
+        from_minutes(
+            self.unix_microseconds
+                .div_euclid(MICRO_SECONDS_IN_ONE_MINUTE),
+            candle_type,
+        )
     }
 }
 
@@ -285,4 +382,110 @@ mod tests {
         let next_key = key.get_next_period_date_key(CandleType::Day);
         assert_eq!(202201010000, next_key.get_value());
     }
+
+    #[test]
+    fn test_add_periods() {
+        let key = CandleDateKey::new(202402281530);
+
+        assert_eq!(
+            202402290000,
+            key.add_periods(1, CandleType::Day).get_value()
+        );
+        assert_eq!(
+            202403010000,
+            key.add_periods(2, CandleType::Day).get_value()
+        );
+        assert_eq!(
+            202402281200,
+            key.add_periods(-3, CandleType::Hour).get_value()
+        );
+        assert_eq!(
+            202312000000,
+            key.add_periods(-2, CandleType::Month).get_value()
+        );
+        assert_eq!(
+            202501000000,
+            key.add_periods(11, CandleType::Month).get_value()
+        );
+        assert_eq!(
+            202402290000,
+            key.add_periods(510, CandleType::Minute).get_value()
+        );
+
+        let month_key = CandleDateKey::new(202103000000);
+        assert_eq!(
+            202102280000,
+            month_key.add_periods(-1, CandleType::Day).get_value()
+        );
+    }
+
+    #[test]
+    fn test_diff_periods_and_floor() {
+        let from = CandleDateKey::new(202012311530);
+        let to = CandleDateKey::new(202103011000);
+
+        assert_eq!(60, from.diff_periods(to, CandleType::Day));
+        assert_eq!(-60, to.diff_periods(from, CandleType::Day));
+        assert_eq!(3, from.diff_periods(to, CandleType::Month));
+        assert_eq!(60 * 24 - 5, from.diff_periods(to, CandleType::Hour));
+
+        assert_eq!(202012311500, from.floor_to(CandleType::Hour).get_value());
+        assert_eq!(202012310000, from.floor_to(CandleType::Day).get_value());
+        assert_eq!(202012000000, from.floor_to(CandleType::Month).get_value());
+        assert_eq!(
+            202103010000,
+            CandleDateKey::new(202103000000)
+                .floor_to(CandleType::Day)
+                .get_value()
+        );
+    }
+
+    #[test]
+    fn test_iter_to() {
+        let keys: Vec<u64> = CandleDateKey::new(202112302359)
+            .iter_to(CandleDateKey::new(202201020000), CandleType::Day)
+            .map(|key| key.get_value())
+            .collect();
+
+        assert_eq!(vec![202112300000, 202112310000, 202201010000], keys);
+    }
+
+    #[test]
+    fn test_arithmetic_matches_date_time() {
+        let mut date_time =
+            DateTimeAsMicroseconds::parse_iso_string("1999-12-25T00:00:00.000000Z").unwrap();
+        let mut key = date_time.into_candle_date_key(CandleType::Hour);
+
+        for _ in 0..24 * 800 {
+            date_time.add_hours(7);
+            key = key.add_periods(7, CandleType::Hour);
+
+            let expected = format!("{:0<12}", date_time.to_chrono_utc().format("%Y%m%d%H"));
+            assert_eq!(expected.parse::<u64>().unwrap(), key.get_value());
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_matches_formatting() {
+        let mut date_time =
+            DateTimeAsMicroseconds::parse_iso_string("1999-12-25T00:00:00.000000Z").unwrap();
+
+        for _ in 0..24 * 800 {
+            date_time.add_minutes(7 * 60 + 13);
+
+            for (candle_type, format) in [
+                (CandleType::Minute, "%Y%m%d%H%M"),
+                (CandleType::Hour, "%Y%m%d%H"),
+                (CandleType::Day, "%Y%m%d"),
+                (CandleType::Month, "%Y%m"),
+            ] {
+                let expected = format!("{:0<12}", date_time.to_chrono_utc().format(format));
+
+                assert_eq!(
+                    expected.parse::<u64>().unwrap(),
+                    date_time.into_candle_date_key(candle_type).get_value()
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,46 @@
+/// Price as an integer amount of minimal price steps, where a step is `10^-digits`.
+/// Matches the representation used by the trading engine, so candles can be reconciled without rounding noise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, core::hash::Hash, PartialOrd, Ord)]
+pub struct FixedPointPrice {
+    pub value: i64,
+    pub digits: u32,
+}
+
+impl FixedPointPrice {
+    pub fn from_f64(price: f64, digits: u32) -> Self {
+        Self {
+            value: (price * get_scale(digits)).round() as i64,
+            digits,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.value as f64 / get_scale(self.digits)
+    }
+}
+
+/// Rounds the price to the given amount of digits. The result is the `f64` closest to the decimal value,
+/// so rounding an already rounded price does not change it.
+pub fn round_price(price: f64, digits: u32) -> f64 {
+    FixedPointPrice::from_f64(price, digits).to_f64()
+}
+
+fn get_scale(digits: u32) -> f64 {
+    10f64.powi(digits as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{round_price, FixedPointPrice};
+
+    #[test]
+    fn test_round_trip() {
+        let price = FixedPointPrice::from_f64(1.123456789, 5);
+        assert_eq!(112346, price.value);
+        assert_eq!(1.12346, price.to_f64());
+
+        assert_eq!(0.3, round_price(0.1 + 0.2, 5));
+        assert_eq!(-1.5, round_price(-1.49999, 2));
+        assert_eq!(110.12, round_price(round_price(110.1249, 2), 2));
+    }
+}
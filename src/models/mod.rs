@@ -3,9 +3,11 @@ mod candle_date_key;
 mod candle_date_key_utils;
 mod candle_model;
 mod candle_type;
+mod fixed_point_price;
 
 pub use candle_data::*;
 pub use candle_model::*;
 
 pub use candle_date_key::*;
 pub use candle_type::*;
+pub use fixed_point_price::*;
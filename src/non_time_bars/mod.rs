@@ -0,0 +1,9 @@
+mod non_time_bar;
+mod non_time_bar_builders;
+mod non_time_bars_cache;
+mod non_time_bars_registry;
+
+pub use non_time_bar::*;
+pub use non_time_bar_builders::*;
+pub use non_time_bars_cache::*;
+pub use non_time_bars_registry::*;
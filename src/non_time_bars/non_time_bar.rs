@@ -0,0 +1,73 @@
+use rust_extensions::{date_time::DateTimeAsMicroseconds, sorted_vec::EntityWithKey};
+
+use crate::{BidOrAsk, CandleData};
+
+/// Bar which is closed by the price movement instead of the clock. Bars of a series are keyed by a sequence
+/// number which grows by one with every new bar.
+#[derive(Debug, Clone)]
+pub struct NonTimeBar {
+    pub sequence: u64,
+    pub started: DateTimeAsMicroseconds,
+    pub finished: DateTimeAsMicroseconds,
+    pub data: CandleData,
+}
+
+impl EntityWithKey<u64> for NonTimeBar {
+    fn get_key(&self) -> &u64 {
+        &self.sequence
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum NonTimeBarType {
+    /// Bricks of a fixed size. A brick is added when the price moves by the brick size beyond the last brick.
+    Renko { brick_size: f64 },
+    /// Bars with `high - low` not above the range. The price breaking the range opens the next bar.
+    Range { range: f64 },
+    /// Bars of a fixed amount of ticks.
+    Tick { ticks: u64 },
+    /// Bars closed by the tick which makes the traded volume of the bar reach the given volume.
+    Volume { volume: f64 },
+}
+
+impl NonTimeBarType {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Renko { brick_size } => {
+                if !brick_size.is_finite() || *brick_size <= 0.0 {
+                    return Err(format!(
+                        "Brick size must be a positive number. Got {}",
+                        brick_size
+                    ));
+                }
+            }
+            Self::Range { range } => {
+                if !range.is_finite() || *range < 0.0 {
+                    return Err(format!(
+                        "Range must be a non-negative number. Got {}",
+                        range
+                    ));
+                }
+            }
+            Self::Tick { ticks } => {
+                if *ticks == 0 {
+                    return Err("Ticks amount must be positive".to_string());
+                }
+            }
+            Self::Volume { volume } => {
+                if !volume.is_finite() || *volume <= 0.0 {
+                    return Err(format!("Volume must be a positive number. Got {}", volume));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NonTimeBarSettings {
+    pub bar_type: NonTimeBarType,
+    pub bid_or_ask: BidOrAsk,
+    pub max_bars_amount: usize,
+}
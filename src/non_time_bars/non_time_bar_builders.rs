@@ -0,0 +1,280 @@
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{CandleData, PriceAdjustmentKind};
+
+#[derive(Debug, Clone)]
+pub struct BuiltBar {
+    pub started: DateTimeAsMicroseconds,
+    pub data: CandleData,
+}
+
+/// What the builder did with the price.
+#[derive(Debug, Clone)]
+pub enum NonTimeBarUpdate {
+    /// The last bar of the series was updated.
+    UpdateLast(CandleData),
+    /// New bars are added after the last bar of the series.
+    NewBars(Vec<BuiltBar>),
+    Nothing,
+}
+
+pub trait NonTimeBarBuilder {
+    fn handle_price(
+        &mut self,
+        price: f64,
+        volume: f64,
+        time_stamp: DateTimeAsMicroseconds,
+    ) -> NonTimeBarUpdate;
+
+    /// Adjusts the prices the next bars are built from, e.g. on a split.
+    fn adjust_prices(&mut self, kind: &PriceAdjustmentKind);
+}
+
+/// A price which would add more bricks at once is treated as a bad tick and ignored.
+pub const MAX_RENKO_BRICKS_PER_TICK: usize = 1000;
+
+/// Renko bricks are added only when they are complete, so the last brick is never updated.
+/// Volume traded while a brick is forming goes to the first brick completed.
+/// Prices which are not finite are ignored.
+pub struct RenkoBarBuilder {
+    brick_size: f64,
+    top: Option<f64>,
+    bottom: Option<f64>,
+    forming_started: Option<DateTimeAsMicroseconds>,
+    forming_volume: f64,
+}
+
+impl RenkoBarBuilder {
+    pub fn new(brick_size: f64) -> Self {
+        Self {
+            brick_size,
+            top: None,
+            bottom: None,
+            forming_started: None,
+            forming_volume: 0.0,
+        }
+    }
+}
+
+impl NonTimeBarBuilder for RenkoBarBuilder {
+    fn handle_price(
+        &mut self,
+        price: f64,
+        volume: f64,
+        time_stamp: DateTimeAsMicroseconds,
+    ) -> NonTimeBarUpdate {
+        if !price.is_finite() || self.brick_size <= 0.0 {
+            return NonTimeBarUpdate::Nothing;
+        }
+
+        let (mut top, mut bottom) = match (self.top, self.bottom) {
+            (Some(top), Some(bottom)) => (top, bottom),
+            _ => (price, price),
+        };
+
+        let bricks_amount = ((price - top).max(bottom - price) / self.brick_size).floor();
+
+        if bricks_amount > MAX_RENKO_BRICKS_PER_TICK as f64 {
+            return NonTimeBarUpdate::Nothing;
+        }
+
+        let forming_started = *self.forming_started.get_or_insert(time_stamp);
+        self.forming_volume += volume;
+
+        let mut bricks = Vec::new();
+
+        while price >= top + self.brick_size && bricks.len() <= MAX_RENKO_BRICKS_PER_TICK {
+            bricks.push(CandleData {
+                open: top,
+                close: top + self.brick_size,
+                high: top + self.brick_size,
+                low: top,
+                volume: 0.0,
+            });
+
+            bottom = top;
+            top += self.brick_size;
+        }
+
+        while price <= bottom - self.brick_size && bricks.len() <= MAX_RENKO_BRICKS_PER_TICK {
+            bricks.push(CandleData {
+                open: bottom,
+                close: bottom - self.brick_size,
+                high: bottom,
+                low: bottom - self.brick_size,
+                volume: 0.0,
+            });
+
+            top = bottom;
+            bottom -= self.brick_size;
+        }
+
+        self.top = Some(top);
+        self.bottom = Some(bottom);
+
+        if bricks.is_empty() {
+            return NonTimeBarUpdate::Nothing;
+        }
+
+        bricks[0].volume = self.forming_volume;
+        self.forming_started = None;
+        self.forming_volume = 0.0;
+
+        let bricks = bricks
+            .into_iter()
+            .enumerate()
+            .map(|(index, data)| BuiltBar {
+                started: if index == 0 {
+                    forming_started
+                } else {
+                    time_stamp
+                },
+                data,
+            })
+            .collect();
+
+        NonTimeBarUpdate::NewBars(bricks)
+    }
+
+    fn adjust_prices(&mut self, kind: &PriceAdjustmentKind) {
+        self.top = self.top.map(|top| kind.apply(top));
+        self.bottom = self.bottom.map(|bottom| kind.apply(bottom));
+    }
+}
+
+/// The price breaking the range of the last bar opens the next bar.
+pub struct RangeBarBuilder {
+    range: f64,
+    current: Option<CandleData>,
+}
+
+impl RangeBarBuilder {
+    pub fn new(range: f64) -> Self {
+        Self {
+            range,
+            current: None,
+        }
+    }
+}
+
+impl NonTimeBarBuilder for RangeBarBuilder {
+    fn handle_price(
+        &mut self,
+        price: f64,
+        volume: f64,
+        time_stamp: DateTimeAsMicroseconds,
+    ) -> NonTimeBarUpdate {
+        if let Some(current) = self.current.as_mut() {
+            if current.high.max(price) - current.low.min(price) <= self.range {
+                current.update_from_price(price, volume);
+                return NonTimeBarUpdate::UpdateLast(*current);
+            }
+        }
+
+        let data = CandleData::new_from_price(price, volume);
+        self.current = Some(data);
+
+        NonTimeBarUpdate::NewBars(vec![BuiltBar {
+            started: time_stamp,
+            data,
+        }])
+    }
+
+    fn adjust_prices(&mut self, kind: &PriceAdjustmentKind) {
+        if let Some(current) = self.current.as_mut() {
+            kind.adjust_candle(current);
+        }
+    }
+}
+
+/// Bar is closed by the tick which makes its ticks amount reach the limit.
+pub struct TickBarBuilder {
+    ticks: u64,
+    current: Option<(CandleData, u64)>,
+}
+
+impl TickBarBuilder {
+    pub fn new(ticks: u64) -> Self {
+        Self {
+            ticks: ticks.max(1),
+            current: None,
+        }
+    }
+}
+
+impl NonTimeBarBuilder for TickBarBuilder {
+    fn handle_price(
+        &mut self,
+        price: f64,
+        volume: f64,
+        time_stamp: DateTimeAsMicroseconds,
+    ) -> NonTimeBarUpdate {
+        if let Some((current, ticks)) = self.current.as_mut() {
+            if *ticks < self.ticks {
+                current.update_from_price(price, volume);
+                *ticks += 1;
+                return NonTimeBarUpdate::UpdateLast(*current);
+            }
+        }
+
+        let data = CandleData::new_from_price(price, volume);
+        self.current = Some((data, 1));
+
+        NonTimeBarUpdate::NewBars(vec![BuiltBar {
+            started: time_stamp,
+            data,
+        }])
+    }
+
+    fn adjust_prices(&mut self, kind: &PriceAdjustmentKind) {
+        if let Some((current, _)) = self.current.as_mut() {
+            kind.adjust_candle(current);
+        }
+    }
+}
+
+/// Bar is closed by the tick which makes its volume reach the limit.
+/// Volume of a tick is never split between bars, so a bar may exceed the limit.
+pub struct VolumeBarBuilder {
+    volume: f64,
+    current: Option<CandleData>,
+}
+
+impl VolumeBarBuilder {
+    pub fn new(volume: f64) -> Self {
+        Self {
+            volume,
+            current: None,
+        }
+    }
+}
+
+impl NonTimeBarBuilder for VolumeBarBuilder {
+    fn handle_price(
+        &mut self,
+        price: f64,
+        volume: f64,
+        time_stamp: DateTimeAsMicroseconds,
+    ) -> NonTimeBarUpdate {
+        if let Some(current) = self.current.as_mut() {
+            if current.volume < self.volume {
+                current.update_from_price(price, volume);
+                return NonTimeBarUpdate::UpdateLast(*current);
+            }
+        }
+
+        let data = CandleData::new_from_price(price, volume);
+        self.current = Some(data);
+
+        NonTimeBarUpdate::NewBars(vec![BuiltBar {
+            started: time_stamp,
+            data,
+        }])
+    }
+
+    fn adjust_prices(&mut self, kind: &PriceAdjustmentKind) {
+        if let Some(current) = self.current.as_mut() {
+            kind.adjust_candle(current);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use rust_extensions::{date_time::DateTimeAsMicroseconds, sorted_vec::*};
+
+use crate::{
+    NonTimeBar, NonTimeBarBuilder, NonTimeBarType, NonTimeBarUpdate, PriceAdjustmentKind,
+    RangeBarBuilder, RenkoBarBuilder, TickBarBuilder, VolumeBarBuilder,
+};
+
+/// Series of non time bars with the same query surface as [`crate::CandleDateCache`],
+/// where keys are sequence numbers of bars.
+pub struct NonTimeBarsCache {
+    pub bars: SortedVec<u64, NonTimeBar>,
+    pub bar_type: NonTimeBarType,
+    builder: Box<dyn NonTimeBarBuilder + Send + Sync>,
+    next_sequence: u64,
+    last_time_stamp: Option<DateTimeAsMicroseconds>,
+}
+
+impl NonTimeBarsCache {
+    pub fn new(bar_type: NonTimeBarType) -> Self {
+        let builder: Box<dyn NonTimeBarBuilder + Send + Sync> = match bar_type {
+            NonTimeBarType::Renko { brick_size } => Box::new(RenkoBarBuilder::new(brick_size)),
+            NonTimeBarType::Range { range } => Box::new(RangeBarBuilder::new(range)),
+            NonTimeBarType::Tick { ticks } => Box::new(TickBarBuilder::new(ticks)),
+            NonTimeBarType::Volume { volume } => Box::new(VolumeBarBuilder::new(volume)),
+        };
+
+        Self {
+            bars: SortedVec::new(),
+            bar_type,
+            builder,
+            next_sequence: 0,
+            last_time_stamp: None,
+        }
+    }
+
+    /// Returns the bars which were added or changed by the price.
+    pub fn handle_price(
+        &mut self,
+        price: f64,
+        volume: f64,
+        time_stamp: DateTimeAsMicroseconds,
+        max_bars_amount: Option<usize>,
+    ) -> Vec<NonTimeBar> {
+        let mut result = Vec::new();
+        self.last_time_stamp = Some(time_stamp);
+
+        match self.builder.handle_price(price, volume, time_stamp) {
+            NonTimeBarUpdate::UpdateLast(data) => {
+                if let Some(last) = self.get_last_bar() {
+                    let sequence = last.sequence;
+
+                    if let InsertOrUpdateEntry::Update(entry) =
+                        self.bars.insert_or_update(&sequence)
+                    {
+                        entry.item.data = data;
+                        entry.item.finished = time_stamp;
+                        result.push(entry.item.clone());
+                    }
+                }
+            }
+            NonTimeBarUpdate::NewBars(bars) => {
+                for bar in bars {
+                    let bar = NonTimeBar {
+                        sequence: self.next_sequence,
+                        started: bar.started,
+                        finished: time_stamp,
+                        data: bar.data,
+                    };
+
+                    self.next_sequence += 1;
+
+                    if let InsertOrUpdateEntry::Insert(entry) =
+                        self.bars.insert_or_update(&bar.sequence)
+                    {
+                        entry.insert(bar.clone());
+                    }
+
+                    result.push(bar);
+                }
+            }
+            NonTimeBarUpdate::Nothing => {}
+        }
+
+        if let Some(max_bars_amount) = max_bars_amount {
+            self.gc_bars(max_bars_amount);
+        }
+
+        result
+    }
+
+    /// Adjusts the bars finished before `before` and returns them. The bar in progress at `before`
+    /// stays as it is. The next bars are built from the adjusted prices only if every price so far
+    /// was before `before`.
+    pub fn adjust_prices(
+        &mut self,
+        before: DateTimeAsMicroseconds,
+        kind: &PriceAdjustmentKind,
+    ) -> Vec<NonTimeBar> {
+        let mut result = Vec::new();
+
+        let sequences: Vec<u64> = self
+            .bars
+            .as_slice()
+            .iter()
+            .take_while(|bar| bar.finished.unix_microseconds < before.unix_microseconds)
+            .map(|bar| bar.sequence)
+            .collect();
+
+        for sequence in sequences {
+            if let InsertOrUpdateEntry::Update(entry) = self.bars.insert_or_update(&sequence) {
+                kind.adjust_candle(&mut entry.item.data);
+                result.push(entry.item.clone());
+            }
+        }
+
+        if let Some(last_time_stamp) = self.last_time_stamp {
+            if last_time_stamp.unix_microseconds < before.unix_microseconds {
+                self.builder.adjust_prices(kind);
+            }
+        }
+
+        result
+    }
+
+    pub fn get_in_range(&self, from: u64, to: u64) -> &[NonTimeBar] {
+        self.bars.range(from..to)
+    }
+
+    pub fn get_highest_and_below(&self, highest: u64, amount: usize) -> &[NonTimeBar] {
+        self.bars.get_highest_and_below_amount(&highest, amount)
+    }
+
+    /// Bars started within `from..to`.
+    pub fn get_in_time_range(
+        &self,
+        from: DateTimeAsMicroseconds,
+        to: DateTimeAsMicroseconds,
+    ) -> &[NonTimeBar] {
+        let bars = self.bars.as_slice();
+        let start =
+            bars.partition_point(|bar| bar.started.unix_microseconds < from.unix_microseconds);
+        let end = bars.partition_point(|bar| bar.started.unix_microseconds < to.unix_microseconds);
+
+        &bars[start..end.max(start)]
+    }
+
+    pub fn get_bar(&self, sequence: u64) -> Option<NonTimeBar> {
+        self.bars.get(&sequence).cloned()
+    }
+
+    pub fn get_first_bar(&self) -> Option<&NonTimeBar> {
+        self.bars.first()
+    }
+
+    pub fn get_last_bar(&self) -> Option<&NonTimeBar> {
+        self.bars.as_slice().last()
+    }
+
+    pub fn get_all_from_cache(&self) -> Vec<NonTimeBar> {
+        self.bars.as_slice().to_vec()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NonTimeBar> {
+        self.bars.as_slice().iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bars.len() == 0
+    }
+
+    pub fn get_estimated_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.bars.capacity() * std::mem::size_of::<NonTimeBar>()
+    }
+
+    pub fn gc_bars(&mut self, max_bars_amount: usize) {
+        while self.bars.len() > max_bars_amount {
+            self.bars.remove_at(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::NonTimeBarType;
+
+    use super::NonTimeBarsCache;
+
+    #[test]
+    fn test_renko() {
+        let mut cache = NonTimeBarsCache::new(NonTimeBarType::Renko { brick_size: 1.0 });
+
+        let started = DateTimeAsMicroseconds::new(1_000_000);
+        let finished = DateTimeAsMicroseconds::new(2_000_000);
+
+        assert!(cache.handle_price(10.0, 0.0, started, None).is_empty());
+        assert!(cache.handle_price(10.5, 0.0, started, None).is_empty());
+
+        let bars = cache.handle_price(12.2, 0.0, finished, None);
+        assert_eq!(2, bars.len());
+        assert_eq!((10.0, 11.0), (bars[0].data.open, bars[0].data.close));
+        assert_eq!((11.0, 12.0), (bars[1].data.open, bars[1].data.close));
+        assert_eq!(started, bars[0].started);
+        assert_eq!(finished, bars[0].finished);
+
+        // A reversal needs two bricks from the last close
+        assert!(cache.handle_price(10.5, 0.0, finished, None).is_empty());
+
+        let bars = cache.handle_price(9.9, 0.0, finished, Some(2));
+        assert_eq!(1, bars.len());
+        assert_eq!(2, bars[0].sequence);
+        assert_eq!((11.0, 10.0), (bars[0].data.open, bars[0].data.close));
+
+        assert_eq!(2, cache.len());
+        assert_eq!(1, cache.get_first_bar().unwrap().sequence);
+    }
+
+    #[test]
+    fn test_renko_ignores_bad_prices() {
+        let mut cache = NonTimeBarsCache::new(NonTimeBarType::Renko { brick_size: 1.0 });
+
+        let now = DateTimeAsMicroseconds::new(1_000_000);
+
+        assert!(cache.handle_price(f64::NAN, 1.0, now, None).is_empty());
+        assert!(cache.handle_price(10.0, 1.0, now, None).is_empty());
+        assert!(cache.handle_price(f64::INFINITY, 1.0, now, None).is_empty());
+        assert!(cache.handle_price(1e12, 1.0, now, None).is_empty());
+        assert!(cache.handle_price(-1e12, 1.0, now, None).is_empty());
+
+        let bars = cache.handle_price(11.0, 1.0, now, None);
+        assert_eq!(1, bars.len());
+        assert_eq!((10.0, 11.0), (bars[0].data.open, bars[0].data.close));
+        assert_eq!(2.0, bars[0].data.volume);
+
+        assert!(NonTimeBarType::Renko { brick_size: 0.0 }
+            .validate()
+            .is_err());
+        assert!(NonTimeBarType::Renko {
+            brick_size: f64::NAN
+        }
+        .validate()
+        .is_err());
+        assert!(NonTimeBarType::Tick { ticks: 0 }.validate().is_err());
+        assert!(NonTimeBarType::Range { range: 0.5 }.validate().is_ok());
+    }
+
+    #[test]
+    fn test_range_bars() {
+        let mut cache = NonTimeBarsCache::new(NonTimeBarType::Range { range: 1.0 });
+
+        let now = DateTimeAsMicroseconds::new(1_000_000);
+
+        cache.handle_price(10.0, 0.0, now, None);
+        cache.handle_price(10.8, 0.0, now, None);
+        cache.handle_price(10.2, 0.0, now, None);
+
+        let bars = cache.handle_price(11.1, 0.0, now, None);
+        assert_eq!(1, bars[0].sequence);
+        assert_eq!(11.1, bars[0].data.open);
+
+        let first = cache.get_bar(0).unwrap();
+        assert_eq!(10.0, first.data.open);
+        assert_eq!(10.2, first.data.close);
+        assert_eq!(10.8, first.data.high);
+
+        assert_eq!(2, cache.get_in_range(0, 10).len());
+        assert_eq!(1, cache.get_highest_and_below(0, 10).len());
+    }
+
+    #[test]
+    fn test_tick_and_volume_bars() {
+        let mut ticks = NonTimeBarsCache::new(NonTimeBarType::Tick { ticks: 2 });
+        let mut volumes = NonTimeBarsCache::new(NonTimeBarType::Volume { volume: 10.0 });
+
+        for (index, (price, volume)) in [(1.0, 4.0), (2.0, 5.0), (3.0, 3.0), (4.0, 1.0), (5.0, 2.0)]
+            .iter()
+            .enumerate()
+        {
+            let time_stamp = DateTimeAsMicroseconds::new(index as i64 * 1_000_000);
+            ticks.handle_price(*price, *volume, time_stamp, None);
+            volumes.handle_price(*price, *volume, time_stamp, None);
+        }
+
+        let bars: Vec<(f64, f64, f64)> = ticks
+            .iter()
+            .map(|bar| (bar.data.open, bar.data.close, bar.data.volume))
+            .collect();
+        assert_eq!(
+            vec![(1.0, 2.0, 9.0), (3.0, 4.0, 4.0), (5.0, 5.0, 2.0)],
+            bars
+        );
+
+        let second = ticks.get_bar(1).unwrap();
+        assert_eq!(2_000_000, second.started.unix_microseconds);
+        assert_eq!(3_000_000, second.finished.unix_microseconds);
+
+        let bars: Vec<(f64, f64, f64)> = volumes
+            .iter()
+            .map(|bar| (bar.data.open, bar.data.close, bar.data.volume))
+            .collect();
+        assert_eq!(vec![(1.0, 3.0, 12.0), (4.0, 5.0, 3.0)], bars);
+
+        let in_range = ticks.get_in_time_range(
+            DateTimeAsMicroseconds::new(1_000_000),
+            DateTimeAsMicroseconds::new(5_000_000),
+        );
+        assert_eq!(2, in_range.len());
+        assert_eq!(1, in_range[0].sequence);
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{BidOrAsk, InstrumentId, InstrumentsMap, PriceAdjustmentKind};
+
+use super::{NonTimeBar, NonTimeBarSettings, NonTimeBarsCache};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, core::hash::Hash)]
+pub struct NonTimeBarsId(u64);
+
+impl NonTimeBarsId {
+    pub fn get_value(&self) -> u64 {
+        self.0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NonTimeBarToPersist {
+    pub non_time_bars_id: NonTimeBarsId,
+    pub bar: NonTimeBar,
+}
+
+struct NonTimeBarsSeries {
+    id: NonTimeBarsId,
+    settings: NonTimeBarSettings,
+    cache: NonTimeBarsCache,
+}
+
+/// Non time bar series configured per instrument.
+pub struct NonTimeBarsRegistry {
+    series: InstrumentsMap<Vec<NonTimeBarsSeries>>,
+    next_id: u64,
+}
+
+impl NonTimeBarsRegistry {
+    pub fn new() -> Self {
+        Self {
+            series: InstrumentsMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn add(
+        &mut self,
+        instrument_id: InstrumentId,
+        settings: NonTimeBarSettings,
+    ) -> Result<NonTimeBarsId, String> {
+        settings.bar_type.validate()?;
+
+        let id = NonTimeBarsId(self.next_id);
+        self.next_id += 1;
+
+        self.series
+            .get_or_insert_with(instrument_id, Vec::new)
+            .push(NonTimeBarsSeries {
+                id,
+                settings,
+                cache: NonTimeBarsCache::new(settings.bar_type),
+            });
+
+        Ok(id)
+    }
+
+    pub fn remove(&mut self, id: NonTimeBarsId) -> bool {
+        for series in self.series.values_mut() {
+            if let Some(index) = series.iter().position(|item| item.id == id) {
+                series.remove(index);
+                return true;
+            }
+        }
+
+        false
+    }
+
+    pub fn get(&self, id: NonTimeBarsId) -> Option<&NonTimeBarsCache> {
+        self.find(id).map(|series| &series.cache)
+    }
+
+    pub fn get_settings(&self, id: NonTimeBarsId) -> Option<NonTimeBarSettings> {
+        self.find(id).map(|series| series.settings)
+    }
+
+    pub fn get_ids(&self, instrument_id: InstrumentId) -> Vec<NonTimeBarsId> {
+        match self.series.get(instrument_id) {
+            Some(series) => series.iter().map(|item| item.id).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn find(&self, id: NonTimeBarsId) -> Option<&NonTimeBarsSeries> {
+        self.series.values().flatten().find(|item| item.id == id)
+    }
+
+    pub fn get_estimated_bytes(&self) -> usize {
+        self.series
+            .values()
+            .map(|series| {
+                series.capacity() * std::mem::size_of::<NonTimeBarsSeries>()
+                    + series
+                        .iter()
+                        .map(|item| {
+                            item.cache.get_estimated_bytes()
+                                - std::mem::size_of::<NonTimeBarsCache>()
+                        })
+                        .sum::<usize>()
+            })
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.series.values().all(|series| series.is_empty())
+    }
+
+    /// Adjusts the bars of every series of the instrument finished before `before`.
+    pub fn adjust_prices(
+        &mut self,
+        instrument_id: InstrumentId,
+        before: DateTimeAsMicroseconds,
+        kind: &PriceAdjustmentKind,
+    ) -> Vec<NonTimeBarToPersist> {
+        let mut result = Vec::new();
+
+        if let Some(series) = self.series.get_mut(instrument_id) {
+            for item in series.iter_mut() {
+                for bar in item.cache.adjust_prices(before, kind) {
+                    result.push(NonTimeBarToPersist {
+                        non_time_bars_id: item.id,
+                        bar,
+                    });
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Feeds the quotes to every series of the instrument. Every added or updated bar is reported once
+    /// with its latest state.
+    pub fn handle_quotes(
+        &mut self,
+        instrument_id: InstrumentId,
+        quotes: impl Iterator<Item = (f64, f64, f64, DateTimeAsMicroseconds)>,
+    ) -> Vec<NonTimeBarToPersist> {
+        let series = match self.series.get_mut(instrument_id) {
+            Some(series) if !series.is_empty() => series,
+            _ => return Vec::new(),
+        };
+
+        let mut changes = BTreeMap::new();
+
+        for (bid, ask, volume, time_stamp) in quotes {
+            for item in series.iter_mut() {
+                let price = match item.settings.bid_or_ask {
+                    BidOrAsk::Bid => bid,
+                    BidOrAsk::Ask => ask,
+                };
+
+                for bar in item.cache.handle_price(
+                    price,
+                    volume,
+                    time_stamp,
+                    Some(item.settings.max_bars_amount),
+                ) {
+                    changes.insert((item.id, bar.sequence), bar);
+                }
+            }
+        }
+
+        changes
+            .into_iter()
+            .map(|((non_time_bars_id, _), bar)| NonTimeBarToPersist {
+                non_time_bars_id,
+                bar,
+            })
+            .collect()
+    }
+}
+
+impl Default for NonTimeBarsRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,324 @@
+use rust_extensions::date_time::{
+    DateTimeAsMicroseconds, MICRO_SECONDS_IN_ONE_DAY, MICRO_SECONDS_IN_ONE_HOUR,
+    MICRO_SECONDS_IN_ONE_MINUTE,
+};
+
+use crate::{CandleDateKey, CandleType, TradingCalendar};
+
+/// Timeframe made of several periods of the candle type, e.g. 5 minutes or 4 hours.
+#[derive(Debug, Clone, Copy)]
+pub struct Timeframe {
+    pub candle_type: CandleType,
+    pub periods: u32,
+}
+
+impl Timeframe {
+    pub fn new(candle_type: CandleType, periods: u32) -> Self {
+        Self {
+            candle_type,
+            periods: periods.max(1),
+        }
+    }
+}
+
+impl From<CandleType> for Timeframe {
+    fn from(candle_type: CandleType) -> Self {
+        Self::new(candle_type, 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeriodsCount {
+    /// Whole periods from `from` to `to`. Negative if `to` is before `from`.
+    pub periods: i64,
+    /// The range does not consist of whole periods, the remainder is not counted.
+    pub partial: bool,
+}
+
+/// Counts periods of the timeframe between the keys. With a calendar only the whole periods of the candle type
+/// which are in session are counted, a period being in session if the market is open at any moment of it.
+pub fn count_periods(
+    from: CandleDateKey,
+    to: CandleDateKey,
+    timeframe: Timeframe,
+    calendar: Option<&TradingCalendar>,
+) -> PeriodsCount {
+    if to < from {
+        let result = count_periods(to, from, timeframe, calendar);
+
+        return PeriodsCount {
+            periods: -result.periods,
+            partial: result.partial,
+        };
+    }
+
+    let base = match calendar {
+        Some(calendar) => {
+            let whole = count_base_periods(from, to, timeframe.candle_type);
+            PeriodsCount {
+                periods: count_in_session_periods(
+                    from,
+                    whole.periods,
+                    timeframe.candle_type,
+                    calendar,
+                ),
+                partial: whole.partial,
+            }
+        }
+        None => count_base_periods(from, to, timeframe.candle_type),
+    };
+
+    let periods = timeframe.periods as i64;
+
+    PeriodsCount {
+        periods: base.periods / periods,
+        partial: base.partial || base.periods % periods != 0,
+    }
+}
+
+fn get_period_length(candle_type: CandleType) -> Option<i64> {
+    match candle_type {
+        CandleType::Minute => Some(MICRO_SECONDS_IN_ONE_MINUTE),
+        CandleType::Hour => Some(MICRO_SECONDS_IN_ONE_HOUR),
+        CandleType::Day => Some(MICRO_SECONDS_IN_ONE_DAY),
+        CandleType::Month => None,
+    }
+}
+
+fn count_base_periods(
+    from: CandleDateKey,
+    to: CandleDateKey,
+    candle_type: CandleType,
+) -> PeriodsCount {
+    let period_length = match get_period_length(candle_type) {
+        Some(period_length) => period_length,
+        None => return count_months(from, to),
+    };
+
+    let from: DateTimeAsMicroseconds = from.into();
+    let to: DateTimeAsMicroseconds = to.into();
+    let diff = to.unix_microseconds - from.unix_microseconds;
+
+    PeriodsCount {
+        periods: diff / period_length,
+        partial: diff % period_length != 0,
+    }
+}
+
+/// Amount of the first `whole` periods counted from `from` which are in session.
+/// The periods start at `from`, the same way they are counted without a calendar.
+fn count_in_session_periods(
+    from: CandleDateKey,
+    whole: i64,
+    candle_type: CandleType,
+    calendar: &TradingCalendar,
+) -> i64 {
+    let start: DateTimeAsMicroseconds = from.into();
+    let start = start.unix_microseconds;
+
+    let period_length = match get_period_length(candle_type) {
+        Some(period_length) => period_length,
+        None => {
+            return (0..whole)
+                .filter(|index| {
+                    let open = get_month_period_start(from, *index);
+                    let close = get_month_period_start(from, *index + 1);
+                    !calendar.get_open_intervals(open, close).is_empty()
+                })
+                .count() as i64;
+        }
+    };
+
+    let mut result = 0;
+    let mut last_index: Option<i64> = None;
+
+    for (open, close) in calendar.get_open_intervals(start, start + whole * period_length) {
+        let mut first = (open - start).div_euclid(period_length);
+        let last = (close - 1 - start).div_euclid(period_length);
+
+        if let Some(last_index) = last_index {
+            first = first.max(last_index + 1);
+        }
+
+        if first <= last {
+            result += last - first + 1;
+            last_index = Some(last);
+        }
+    }
+
+    result
+}
+
+/// Start of the month period `index` months after the one starting at `from`, in microseconds.
+fn get_month_period_start(from: CandleDateKey, index: i64) -> i64 {
+    let from_struct = from.to_date_time_struct();
+    let months = from_struct.year as i64 * 12 + from_struct.month as i64 - 1 + index;
+
+    let month_start =
+        CandleDateKey::new(((months / 12 * 100 + months % 12 + 1) * 1000000 + 10000) as u64);
+    let month_start: DateTimeAsMicroseconds = month_start.into();
+
+    month_start.unix_microseconds + get_offset_in_month(from)
+}
+
+fn count_months(from: CandleDateKey, to: CandleDateKey) -> PeriodsCount {
+    let from_struct = from.to_date_time_struct();
+    let to_struct = to.to_date_time_struct();
+
+    let mut periods = (to_struct.year as i64 - from_struct.year as i64) * 12
+        + to_struct.month as i64
+        - from_struct.month as i64;
+
+    let from_offset = get_offset_in_month(from);
+    let to_offset = get_offset_in_month(to);
+
+    if to_offset < from_offset {
+        periods -= 1;
+    }
+
+    PeriodsCount {
+        periods,
+        partial: to_offset != from_offset,
+    }
+}
+
+fn get_offset_in_month(date_key: CandleDateKey) -> i64 {
+    let month_start = CandleDateKey::new(date_key.get_value() / 1000000 * 1000000 + 10000);
+
+    let date_time: DateTimeAsMicroseconds = date_key.into();
+    let month_start: DateTimeAsMicroseconds = month_start.into();
+
+    date_time.unix_microseconds - month_start.unix_microseconds
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CandleDateKey, CandleType, TradingCalendar, WeekTime, WeeklySession};
+
+    use super::{count_periods, PeriodsCount, Timeframe};
+
+    fn count(from: u64, to: u64, timeframe: Timeframe) -> PeriodsCount {
+        count_periods(
+            CandleDateKey::new(from),
+            CandleDateKey::new(to),
+            timeframe,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_signed_and_partial() {
+        let hour = Timeframe::from(CandleType::Hour);
+
+        assert_eq!(
+            PeriodsCount {
+                periods: 1,
+                partial: true
+            },
+            count(202101011000, 202101011130, hour)
+        );
+
+        assert_eq!(
+            PeriodsCount {
+                periods: -1,
+                partial: true
+            },
+            count(202101011130, 202101011000, hour)
+        );
+
+        let month = Timeframe::from(CandleType::Month);
+
+        assert_eq!(-13, count(202102000000, 202001000000, month).periods);
+        assert_eq!(
+            PeriodsCount {
+                periods: 0,
+                partial: true
+            },
+            count(202101150000, 202102100000, month)
+        );
+        assert_eq!(1, count(202101150000, 202102150000, month).periods);
+    }
+
+    #[test]
+    fn test_custom_timeframe() {
+        let five_minutes = Timeframe::new(CandleType::Minute, 5);
+
+        assert_eq!(
+            PeriodsCount {
+                periods: 12,
+                partial: false
+            },
+            count(202101011000, 202101011100, five_minutes)
+        );
+
+        assert!(count(202101011000, 202101011007, five_minutes).partial);
+    }
+
+    #[test]
+    fn test_with_calendar() {
+        let calendar = TradingCalendar::new(vec![WeeklySession {
+            open: WeekTime::new(0, 0, 0),
+            close: WeekTime::new(5, 0, 0),
+        }]);
+
+        // Friday 2021-01-01 till Friday 2021-01-15
+        let result = count_periods(
+            CandleDateKey::new(202101010000),
+            CandleDateKey::new(202101150000),
+            Timeframe::from(CandleType::Day),
+            Some(&calendar),
+        );
+
+        assert_eq!(10, result.periods);
+    }
+
+    #[test]
+    fn test_always_open_calendar_counts_whole_periods() {
+        let calendar = TradingCalendar::default();
+
+        for (from, to, timeframe) in [
+            (
+                202101011000,
+                202101011130,
+                Timeframe::from(CandleType::Hour),
+            ),
+            (
+                202101011030,
+                202101011129,
+                Timeframe::from(CandleType::Hour),
+            ),
+            (
+                202101011130,
+                202101011000,
+                Timeframe::from(CandleType::Hour),
+            ),
+            (
+                202101011007,
+                202101011100,
+                Timeframe::new(CandleType::Minute, 5),
+            ),
+            (202101011200, 202101051100, Timeframe::from(CandleType::Day)),
+            (
+                202101150000,
+                202103100000,
+                Timeframe::from(CandleType::Month),
+            ),
+            (
+                202101310000,
+                202105010000,
+                Timeframe::from(CandleType::Month),
+            ),
+        ] {
+            let from = CandleDateKey::new(from);
+            let to = CandleDateKey::new(to);
+
+            assert_eq!(
+                count_periods(from, to, timeframe, None),
+                count_periods(from, to, timeframe, Some(&calendar)),
+                "{:?} - {:?}",
+                from,
+                to
+            );
+        }
+    }
+}
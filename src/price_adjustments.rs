@@ -0,0 +1,205 @@
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{CandleData, CandleDateKey, CandleModel, CandleType, InstrumentId, InstrumentsMap};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceAdjustmentKind {
+    /// E.g. `Multiply(0.5)` for a 2:1 split.
+    Multiply(f64),
+    /// E.g. the price difference between the new and the old contract on a roll.
+    Add(f64),
+}
+
+impl PriceAdjustmentKind {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Multiply(factor) => {
+                if !factor.is_finite() || *factor <= 0.0 {
+                    return Err(format!(
+                        "Multiplier must be a positive number. Got {}",
+                        factor
+                    ));
+                }
+            }
+            Self::Add(value) => {
+                if !value.is_finite() {
+                    return Err(format!("Addend must be a finite number. Got {}", value));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn apply(&self, price: f64) -> f64 {
+        match self {
+            Self::Multiply(factor) => price * factor,
+            Self::Add(value) => price + value,
+        }
+    }
+
+    pub fn revert(&self, price: f64) -> f64 {
+        match self {
+            Self::Multiply(factor) => price / factor,
+            Self::Add(value) => price - value,
+        }
+    }
+
+    pub fn adjust_candle(&self, data: &mut CandleData) {
+        self.apply_to_candle(data, Self::apply);
+    }
+
+    pub fn revert_candle(&self, data: &mut CandleData) {
+        self.apply_to_candle(data, Self::revert);
+    }
+
+    fn apply_to_candle(&self, data: &mut CandleData, apply: impl Fn(&Self, f64) -> f64) {
+        data.open = apply(self, data.open);
+        data.close = apply(self, data.close);
+        data.high = apply(self, data.high);
+        data.low = apply(self, data.low);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PriceAdjustment {
+    /// Candles whose whole period ends at or before this key are adjusted.
+    pub before: CandleDateKey,
+    pub kind: PriceAdjustmentKind,
+    pub applied_at: DateTimeAsMicroseconds,
+}
+
+impl PriceAdjustment {
+    /// Candles whose period contains `before` (e.g. the month of a split) stay as they are.
+    pub fn affects(&self, candle_type: CandleType, date_key: CandleDateKey) -> bool {
+        date_key.get_next_period_date_key(candle_type) <= self.before
+    }
+
+    /// Ticks before `before` are adjusted.
+    pub fn affects_tick(&self, time_stamp: DateTimeAsMicroseconds) -> bool {
+        let before: DateTimeAsMicroseconds = self.before.into();
+        time_stamp.unix_microseconds < before.unix_microseconds
+    }
+
+    /// Price of a quote arriving after the adjustment as it goes to the candle.
+    pub fn apply_to_price(
+        &self,
+        candle_type: CandleType,
+        date_key: CandleDateKey,
+        price: f64,
+    ) -> f64 {
+        if self.affects(candle_type, date_key) {
+            self.kind.apply(price)
+        } else {
+            price
+        }
+    }
+
+    /// Price of a quote arriving after the adjustment as it goes to the tick buffer and non time bars.
+    pub fn apply_to_tick_price(&self, time_stamp: DateTimeAsMicroseconds, price: f64) -> f64 {
+        if self.affects_tick(time_stamp) {
+            self.kind.apply(price)
+        } else {
+            price
+        }
+    }
+
+    pub fn apply(&self, candle_type: CandleType, candle: &mut CandleModel) -> bool {
+        if !self.affects(candle_type, candle.date_key) {
+            return false;
+        }
+
+        self.kind
+            .apply_to_candle(&mut candle.data, PriceAdjustmentKind::apply);
+        true
+    }
+}
+
+/// History of the adjustments applied to the candles of every instrument.
+pub struct PriceAdjustments {
+    history: InstrumentsMap<Vec<PriceAdjustment>>,
+}
+
+impl PriceAdjustments {
+    pub fn new() -> Self {
+        Self {
+            history: InstrumentsMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, instrument_id: InstrumentId, adjustment: PriceAdjustment) {
+        self.history
+            .get_or_insert_with(instrument_id, Vec::new)
+            .push(adjustment);
+    }
+
+    pub fn get(&self, instrument_id: InstrumentId) -> &[PriceAdjustment] {
+        match self.history.get(instrument_id) {
+            Some(history) => history,
+            None => &[],
+        }
+    }
+
+    /// Reverts the adjustments applied to the candle, newest first.
+    /// The result may differ from the original prices by rounding.
+    pub fn unadjust(
+        &self,
+        instrument_id: InstrumentId,
+        candle_type: CandleType,
+        candle: &CandleModel,
+    ) -> CandleModel {
+        let mut result = candle.clone();
+
+        for adjustment in self.get(instrument_id).iter().rev() {
+            if adjustment.affects(candle_type, candle.date_key) {
+                adjustment
+                    .kind
+                    .apply_to_candle(&mut result.data, PriceAdjustmentKind::revert);
+            }
+        }
+
+        result
+    }
+}
+
+impl Default for PriceAdjustments {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{CandleDateKey, CandleType};
+
+    use super::{PriceAdjustment, PriceAdjustmentKind};
+
+    #[test]
+    fn test_affects() {
+        let adjustment = PriceAdjustment {
+            before: CandleDateKey::new(202101150000),
+            kind: PriceAdjustmentKind::Multiply(0.5),
+            applied_at: DateTimeAsMicroseconds::new(0),
+        };
+
+        assert!(adjustment.affects(CandleType::Minute, CandleDateKey::new(202101142359)));
+        assert!(adjustment.affects(CandleType::Day, CandleDateKey::new(202101140000)));
+        assert!(!adjustment.affects(CandleType::Day, CandleDateKey::new(202101150000)));
+        assert!(!adjustment.affects(CandleType::Month, CandleDateKey::new(202101000000)));
+        assert!(adjustment.affects(CandleType::Month, CandleDateKey::new(202012000000)));
+
+        assert_eq!(
+            5.0,
+            adjustment.apply_to_price(CandleType::Minute, CandleDateKey::new(202101142359), 10.0)
+        );
+        assert_eq!(
+            10.0,
+            adjustment.apply_to_price(CandleType::Month, CandleDateKey::new(202101000000), 10.0)
+        );
+
+        assert!(PriceAdjustmentKind::Multiply(0.0).validate().is_err());
+        assert!(PriceAdjustmentKind::Add(-5.0).validate().is_ok());
+    }
+}
@@ -0,0 +1,299 @@
+use std::io::{BufRead, Write};
+
+use crate::{BidOrAsk, CandleData, CandleDateKey, CandleType, CandlesInstrumentsCache};
+
+#[derive(Debug, Clone)]
+pub struct CandleDumpRecord {
+    pub instrument_id: String,
+    pub bid_or_ask: BidOrAsk,
+    pub candle_type: CandleType,
+    pub date_key: CandleDateKey,
+    pub data: CandleData,
+}
+
+impl CandleDumpRecord {
+    fn get_sort_key(&self) -> (&str, bool, u8, u64) {
+        (
+            self.instrument_id.as_str(),
+            !self.bid_or_ask.to_is_bid(),
+            self.candle_type.to_u8(),
+            self.date_key.get_value(),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CandleDiff {
+    /// Candle exists in the reference only.
+    Missing(CandleDumpRecord),
+    /// Candle exists in the replayed result only.
+    Unexpected(CandleDumpRecord),
+    Different {
+        expected: CandleDumpRecord,
+        actual: CandleDumpRecord,
+    },
+}
+
+/// All the candles of the cache ordered by instrument, side, candle type and date.
+pub fn dump_candles(cache: &CandlesInstrumentsCache) -> Vec<CandleDumpRecord> {
+    let mut result = Vec::new();
+
+    for instrument_id in cache.get_instruments() {
+        for bid_or_ask in [BidOrAsk::Bid, BidOrAsk::Ask] {
+            let by_type = match cache.get_all_by_instrument(bid_or_ask, &instrument_id) {
+                Some(by_type) => by_type,
+                None => continue,
+            };
+
+            for (candle_type, candles) in by_type {
+                for candle in candles {
+                    result.push(CandleDumpRecord {
+                        instrument_id: instrument_id.clone(),
+                        bid_or_ask,
+                        candle_type,
+                        date_key: candle.date_key,
+                        data: candle.data,
+                    });
+                }
+            }
+        }
+    }
+
+    result.sort_by(|a, b| a.get_sort_key().cmp(&b.get_sort_key()));
+    result
+}
+
+/// Writes `instrument,side,candle_type,date_key,open,high,low,close,volume` lines with a header.
+pub fn write_candle_dump<'s>(
+    mut writer: impl Write,
+    records: impl Iterator<Item = &'s CandleDumpRecord>,
+) -> Result<(), String> {
+    writeln!(
+        writer,
+        "instrument,side,candle_type,date_key,open,high,low,close,volume"
+    )
+    .map_err(|err| err.to_string())?;
+
+    for record in records {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{}",
+            record.instrument_id,
+            if record.bid_or_ask.to_is_bid() {
+                "bid"
+            } else {
+                "ask"
+            },
+            record.candle_type.to_u8(),
+            record.date_key.get_value(),
+            record.data.open,
+            record.data.high,
+            record.data.low,
+            record.data.close,
+            record.data.volume
+        )
+        .map_err(|err| err.to_string())?;
+    }
+
+    Ok(())
+}
+
+pub fn read_candle_dump(reader: impl BufRead) -> Result<Vec<CandleDumpRecord>, String> {
+    let mut result = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| format!("Can not read line {}: {}", index + 1, err))?;
+        let line = line.trim();
+
+        if line.is_empty() || (index == 0 && line.starts_with("instrument,")) {
+            continue;
+        }
+
+        let record =
+            parse_dump_record(line).map_err(|err| format!("Line {}: {}", index + 1, err))?;
+        result.push(record);
+    }
+
+    result.sort_by(|a, b| a.get_sort_key().cmp(&b.get_sort_key()));
+    Ok(result)
+}
+
+/// Checks the parts [`CandleDateKey::to_date_time_struct`] panics on, so a broken dump is reported as an error.
+fn check_date_key_parts(date_key: CandleDateKey) -> Result<(), String> {
+    let value = date_key.get_value();
+
+    let parts = [
+        ("year", value / 100000000, 9999),
+        ("month", value / 1000000 % 100, 12),
+        ("day", value / 10000 % 100, 31),
+        ("hour", value / 100 % 100, 23),
+        ("minute", value % 100, 59),
+    ];
+
+    for (name, part, max) in parts {
+        if part > max {
+            return Err(format!("Invalid {} {} in date key {}", name, part, value));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_dump_record(line: &str) -> Result<CandleDumpRecord, String> {
+    let columns: Vec<&str> = line.split(',').map(|column| column.trim()).collect();
+
+    if columns.len() != 9 {
+        return Err(format!("Expected 9 columns, got {}", columns.len()));
+    }
+
+    let bid_or_ask = match columns[1] {
+        "bid" => BidOrAsk::Bid,
+        "ask" => BidOrAsk::Ask,
+        side => return Err(format!("Invalid side {}", side)),
+    };
+
+    let candle_type = match columns[2].parse::<u8>() {
+        Ok(value) if value <= 3 => CandleType::from_u8(value),
+        _ => return Err(format!("Invalid candle type {}", columns[2])),
+    };
+
+    let date_key = columns[3]
+        .parse::<u64>()
+        .map(CandleDateKey::new)
+        .map_err(|_| format!("Invalid date key {}", columns[3]))?;
+
+    check_date_key_parts(date_key)?;
+    candle_type.verify_date_key(date_key)?;
+
+    let mut values = [0.0; 5];
+
+    for (value, column) in values.iter_mut().zip(&columns[4..]) {
+        *value = column
+            .parse()
+            .map_err(|_| format!("Invalid number {}", column))?;
+    }
+
+    Ok(CandleDumpRecord {
+        instrument_id: columns[0].to_string(),
+        bid_or_ask,
+        candle_type,
+        date_key,
+        data: CandleData {
+            open: values[0],
+            high: values[1],
+            low: values[2],
+            close: values[3],
+            volume: values[4],
+        },
+    })
+}
+
+/// Compares the replayed candles with the reference. Both must be ordered as [`dump_candles`] does.
+/// Prices and volumes are considered equal if they differ by no more than `tolerance`.
+pub fn diff_candle_dumps(
+    expected: &[CandleDumpRecord],
+    actual: &[CandleDumpRecord],
+    tolerance: f64,
+) -> Vec<CandleDiff> {
+    let mut result = Vec::new();
+    let mut expected = expected.iter().peekable();
+    let mut actual = actual.iter().peekable();
+
+    loop {
+        let ordering = match (expected.peek(), actual.peek()) {
+            (Some(e), Some(a)) => e.get_sort_key().cmp(&a.get_sort_key()),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => break,
+        };
+
+        match ordering {
+            std::cmp::Ordering::Less => {
+                result.push(CandleDiff::Missing(expected.next().unwrap().clone()));
+            }
+            std::cmp::Ordering::Greater => {
+                result.push(CandleDiff::Unexpected(actual.next().unwrap().clone()));
+            }
+            std::cmp::Ordering::Equal => {
+                let expected = expected.next().unwrap();
+                let actual = actual.next().unwrap();
+
+                if !is_same_data(&expected.data, &actual.data, tolerance) {
+                    result.push(CandleDiff::Different {
+                        expected: expected.clone(),
+                        actual: actual.clone(),
+                    });
+                }
+            }
+        }
+    }
+
+    result
+}
+
+fn is_same_data(a: &CandleData, b: &CandleData, tolerance: f64) -> bool {
+    [
+        (a.open, b.open),
+        (a.high, b.high),
+        (a.low, b.low),
+        (a.close, b.close),
+        (a.volume, b.volume),
+    ]
+    .iter()
+    .all(|(a, b)| (a - b).abs() <= tolerance)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{CandleType, CandlesInstrumentsCache, ReplayTick};
+
+    use super::{diff_candle_dumps, dump_candles, read_candle_dump, write_candle_dump, CandleDiff};
+
+    #[test]
+    fn test_replay_and_diff() {
+        let ticks = [1.1, 1.3, 1.2].map(|price| ReplayTick {
+            instrument_id: "EURUSD".to_string(),
+            bid: price,
+            ask: price + 0.1,
+            volume: 0.0,
+            time_stamp: DateTimeAsMicroseconds::parse_iso_string("2021-01-01T10:22:33.000000Z")
+                .unwrap(),
+        });
+
+        let mut cache = CandlesInstrumentsCache::new();
+        assert_eq!(3, crate::replay_ticks(&mut cache, ticks.into_iter(), 100));
+
+        let actual = dump_candles(&cache);
+        assert_eq!(8, actual.len());
+
+        let mut dump = Vec::new();
+        write_candle_dump(&mut dump, actual.iter()).unwrap();
+        let mut reference = read_candle_dump(dump.as_slice()).unwrap();
+
+        assert!(diff_candle_dumps(&reference, &actual, 0.0).is_empty());
+
+        reference[0].data.high += 0.5;
+        reference.remove(3);
+
+        let diff = diff_candle_dumps(&reference, &actual, 1e-9);
+
+        assert_eq!(2, diff.len());
+        assert!(matches!(diff[0], CandleDiff::Different { .. }));
+        assert!(
+            matches!(&diff[1], CandleDiff::Unexpected(record) if record.candle_type.to_u8() == CandleType::Month.to_u8())
+        );
+
+        for malformed in [
+            "202113011000",
+            "202101321000",
+            "202101012400",
+            "202101011060",
+        ] {
+            let line = format!("EURUSD,bid,0,{},1.1,1.1,1.1,1.1,0\n", malformed);
+            let err = read_candle_dump(line.as_bytes()).unwrap_err();
+            assert!(err.starts_with("Line 1: Invalid"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,30 @@
+mod candle_dump;
+mod tick_file;
+
+pub use candle_dump::*;
+pub use tick_file::*;
+
+use crate::CandlesInstrumentsCache;
+
+/// Feeds the ticks into the cache one by one in the given order. Returns the amount of ticks handled.
+pub fn replay_ticks(
+    cache: &mut CandlesInstrumentsCache,
+    ticks: impl Iterator<Item = ReplayTick>,
+    max_candles_amount: usize,
+) -> usize {
+    let mut amount = 0;
+
+    for tick in ticks {
+        cache.handle_bid_ask_with_volume(
+            &tick.instrument_id,
+            tick.bid,
+            tick.ask,
+            tick.volume,
+            tick.time_stamp,
+            max_candles_amount,
+        );
+        amount += 1;
+    }
+
+    amount
+}
@@ -0,0 +1,201 @@
+use std::io::{BufRead, Read, Write};
+
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+#[derive(Debug, Clone)]
+pub struct ReplayTick {
+    pub instrument_id: String,
+    pub bid: f64,
+    pub ask: f64,
+    pub volume: f64,
+    pub time_stamp: DateTimeAsMicroseconds,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickFileFormat {
+    /// `instrument,time_stamp,bid,ask[,volume]` per line. Time stamp is either unix microseconds
+    /// or an ISO string. Empty lines, lines starting with `#` and a header line starting with `instrument,`
+    /// are skipped.
+    Csv,
+    /// Little endian records: name length (u16), name, time stamp (i64 microseconds), bid, ask, volume (f64).
+    Binary,
+}
+
+impl TickFileFormat {
+    /// Detects the format by the file extension: `.bin` is binary, everything else is csv.
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".bin") {
+            Self::Binary
+        } else {
+            Self::Csv
+        }
+    }
+}
+
+pub fn read_ticks(reader: impl BufRead, format: TickFileFormat) -> Result<Vec<ReplayTick>, String> {
+    match format {
+        TickFileFormat::Csv => read_csv_ticks(reader),
+        TickFileFormat::Binary => read_binary_ticks(reader),
+    }
+}
+
+pub fn read_csv_ticks(reader: impl BufRead) -> Result<Vec<ReplayTick>, String> {
+    let mut result = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|err| format!("Can not read line {}: {}", index + 1, err))?;
+        let line = line.trim();
+
+        if line.is_empty()
+            || line.starts_with('#')
+            || (index == 0 && line.starts_with("instrument,"))
+        {
+            continue;
+        }
+
+        let tick = parse_csv_tick(line).map_err(|err| format!("Line {}: {}", index + 1, err))?;
+        result.push(tick);
+    }
+
+    Ok(result)
+}
+
+fn parse_csv_tick(line: &str) -> Result<ReplayTick, String> {
+    let columns: Vec<&str> = line.split(',').map(|column| column.trim()).collect();
+
+    if columns.len() < 4 || columns.len() > 5 {
+        return Err(format!("Expected 4 or 5 columns, got {}", columns.len()));
+    }
+
+    let time_stamp = match columns[1].parse::<i64>() {
+        Ok(micros) => DateTimeAsMicroseconds::new(micros),
+        Err(_) => DateTimeAsMicroseconds::parse_iso_string(columns[1])
+            .ok_or_else(|| format!("Invalid time stamp {}", columns[1]))?,
+    };
+
+    Ok(ReplayTick {
+        instrument_id: columns[0].to_string(),
+        bid: parse_f64(columns[2])?,
+        ask: parse_f64(columns[3])?,
+        volume: match columns.get(4) {
+            Some(volume) => parse_f64(volume)?,
+            None => 0.0,
+        },
+        time_stamp,
+    })
+}
+
+fn parse_f64(src: &str) -> Result<f64, String> {
+    src.parse().map_err(|_| format!("Invalid number {}", src))
+}
+
+pub fn read_binary_ticks(mut reader: impl Read) -> Result<Vec<ReplayTick>, String> {
+    let mut result = Vec::new();
+
+    loop {
+        let mut name_len = [0u8; 2];
+        let mut read = 0;
+
+        // The end of the file is clean only between the records
+        while read < name_len.len() {
+            match reader.read(&mut name_len[read..]) {
+                Ok(0) if read == 0 => return Ok(result),
+                Ok(0) => return Err(format!("Truncated tick {}", result.len())),
+                Ok(amount) => read += amount,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(format!("Can not read tick {}: {}", result.len(), err)),
+            }
+        }
+
+        let mut name = vec![0u8; u16::from_le_bytes(name_len) as usize];
+        let mut values = [0u8; 32];
+
+        reader
+            .read_exact(&mut name)
+            .and_then(|_| reader.read_exact(&mut values))
+            .map_err(|err| format!("Truncated tick {}: {}", result.len(), err))?;
+
+        let instrument_id = String::from_utf8(name)
+            .map_err(|_| format!("Invalid instrument name of tick {}", result.len()))?;
+
+        let read_f64 =
+            |index: usize| f64::from_le_bytes(values[index * 8..index * 8 + 8].try_into().unwrap());
+
+        result.push(ReplayTick {
+            instrument_id,
+            time_stamp: DateTimeAsMicroseconds::new(i64::from_le_bytes(
+                values[0..8].try_into().unwrap(),
+            )),
+            bid: read_f64(1),
+            ask: read_f64(2),
+            volume: read_f64(3),
+        });
+    }
+}
+
+pub fn write_binary_ticks<'s>(
+    mut writer: impl Write,
+    ticks: impl Iterator<Item = &'s ReplayTick>,
+) -> Result<(), String> {
+    for tick in ticks {
+        let name = tick.instrument_id.as_bytes();
+        let name_len: u16 = name
+            .len()
+            .try_into()
+            .map_err(|_| format!("Instrument name is too long: {}", tick.instrument_id))?;
+
+        let mut record = Vec::with_capacity(2 + name.len() + 32);
+        record.extend_from_slice(&name_len.to_le_bytes());
+        record.extend_from_slice(name);
+        record.extend_from_slice(&tick.time_stamp.unix_microseconds.to_le_bytes());
+        record.extend_from_slice(&tick.bid.to_le_bytes());
+        record.extend_from_slice(&tick.ask.to_le_bytes());
+        record.extend_from_slice(&tick.volume.to_le_bytes());
+
+        writer.write_all(&record).map_err(|err| err.to_string())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_binary_ticks, read_csv_ticks, write_binary_ticks};
+
+    #[test]
+    fn test_csv_and_binary() {
+        let csv = "instrument,time_stamp,bid,ask,volume\n\
+            EURUSD,1609496553000000,1.1,1.2,5\n\
+            # comment\n\
+            \n\
+            USDJPY,2021-01-01T10:22:34.000000Z,103.1,103.2\n";
+
+        let ticks = read_csv_ticks(csv.as_bytes()).unwrap();
+
+        assert_eq!(2, ticks.len());
+        assert_eq!(5.0, ticks[0].volume);
+        assert_eq!(1609496554000000, ticks[1].time_stamp.unix_microseconds);
+
+        let mut binary = Vec::new();
+        write_binary_ticks(&mut binary, ticks.iter()).unwrap();
+
+        let restored = read_binary_ticks(binary.as_slice()).unwrap();
+
+        assert_eq!(2, restored.len());
+        assert_eq!("USDJPY", restored[1].instrument_id);
+        assert_eq!(103.2, restored[1].ask);
+        assert_eq!(ticks[0].time_stamp, restored[0].time_stamp);
+
+        assert!(read_binary_ticks(&binary[..binary.len() - 1]).is_err());
+
+        binary.push(0);
+        assert_eq!(
+            "Truncated tick 2",
+            read_binary_ticks(binary.as_slice()).unwrap_err()
+        );
+        assert!(read_csv_ticks("a,b,c,d\nEURUSD,1,1.1,1.2\n".as_bytes()).is_err());
+        assert!(read_csv_ticks("EURUSD,1,x,1.2\n".as_bytes())
+            .unwrap_err()
+            .starts_with("Line 1:"));
+    }
+}
@@ -0,0 +1,878 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard},
+};
+
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{
+    BidAskToHandle, BidOrAsk, CandleDateCache, CandleDateKey, CandleModel, CandleToPersist,
+    CandleType, CandlesCacheByType, CandlesInstrumentsCache, HandleBidAskChanges, InstrumentId,
+    InstrumentRef, InstrumentsMap,
+};
+
+const HISTORY_CHUNK_SIZE: usize = 1024;
+
+/// Closed candles stored as immutable chunks shared between snapshots.
+/// Every chunk except the last one holds exactly [`HISTORY_CHUNK_SIZE`] candles,
+/// evicted candles are skipped at the start of the first chunk.
+#[derive(Debug, Clone, Default)]
+struct CandlesHistory {
+    chunks: Vec<Arc<Vec<CandleModel>>>,
+    skip: usize,
+    len: usize,
+}
+
+impl CandlesHistory {
+    fn from_slice(candles: &[CandleModel]) -> Self {
+        Self {
+            chunks: candles
+                .chunks(HISTORY_CHUNK_SIZE)
+                .map(|chunk| Arc::new(chunk.to_vec()))
+                .collect(),
+            skip: 0,
+            len: candles.len(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, index: usize) -> Option<&CandleModel> {
+        if index >= self.len {
+            return None;
+        }
+
+        let index = index + self.skip;
+        self.chunks[index / HISTORY_CHUNK_SIZE].get(index % HISTORY_CHUNK_SIZE)
+    }
+
+    /// Copies only the last chunk if it is shared with other snapshots.
+    fn push(&mut self, candle: CandleModel) {
+        match self.chunks.last_mut() {
+            Some(chunk) if chunk.len() < HISTORY_CHUNK_SIZE => Arc::make_mut(chunk).push(candle),
+            _ => self.chunks.push(Arc::new(vec![candle])),
+        }
+
+        self.len += 1;
+    }
+
+    fn remove_first(&mut self, amount: usize) {
+        let amount = amount.min(self.len);
+        self.skip += amount;
+        self.len -= amount;
+
+        if self.len == 0 {
+            self.chunks.clear();
+            self.skip = 0;
+            return;
+        }
+
+        let full_chunks = self.skip / HISTORY_CHUNK_SIZE;
+
+        if full_chunks > 0 {
+            self.chunks.drain(..full_chunks);
+            self.skip -= full_chunks * HISTORY_CHUNK_SIZE;
+        }
+    }
+
+    /// Index of the first candle the predicate is false for. Candles must be partitioned by it.
+    fn partition_point(&self, mut pred: impl FnMut(&CandleModel) -> bool) -> usize {
+        let (mut low, mut high) = (0, self.len);
+
+        while low < high {
+            let middle = (low + high) / 2;
+
+            if pred(self.get(middle).unwrap()) {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        low
+    }
+
+    fn iter_from(&self, index: usize) -> impl Iterator<Item = &CandleModel> {
+        let index = (index + self.skip).min(self.skip + self.len);
+
+        self.chunks[(index / HISTORY_CHUNK_SIZE).min(self.chunks.len())..]
+            .iter()
+            .flat_map(|chunk| chunk.iter())
+            .skip(index % HISTORY_CHUNK_SIZE)
+            .take(self.len + self.skip - index)
+    }
+}
+
+/// Immutable view of one (instrument, bid/ask, candle type) series.
+///
+/// Closed candles are shared between snapshots in immutable chunks, so publishing an update of the live
+/// candle does not copy the history and opening a new candle copies only the last chunk.
+/// The whole history is copied only when the series is changed in any other way.
+#[derive(Debug, Clone)]
+pub struct CandlesSeriesSnapshot {
+    pub candle_type: CandleType,
+    history: Arc<CandlesHistory>,
+    live: Option<CandleModel>,
+}
+
+impl CandlesSeriesSnapshot {
+    fn from_cache(cache: &CandleDateCache) -> Self {
+        let candles = cache.candles.as_slice();
+
+        match candles.split_last() {
+            Some((live, history)) => Self {
+                candle_type: cache.candle_type,
+                history: Arc::new(CandlesHistory::from_slice(history)),
+                live: Some(live.clone()),
+            },
+            None => Self {
+                candle_type: cache.candle_type,
+                history: Arc::new(CandlesHistory::default()),
+                live: None,
+            },
+        }
+    }
+
+    /// Follows the cache if the change is for its latest candle: updates the live candle or closes it and opens
+    /// the latest one, dropping candles evicted from the start. The history is shared, only the last chunk
+    /// is copied when a candle is closed. `None` if the series changed in any other way.
+    fn with_latest_candle(
+        &self,
+        change: &CandleToPersist,
+        cache: &CandleDateCache,
+    ) -> Option<Self> {
+        let candles = cache.candles.as_slice();
+        let (live, closed) = candles.split_last()?;
+        let current_live = self.live.as_ref()?;
+
+        if live.date_key != change.date_key {
+            return None;
+        }
+
+        let closes_live = current_live.date_key != live.date_key;
+
+        if closes_live && closed.last()?.date_key != current_live.date_key {
+            return None;
+        }
+
+        let history_len = self.history.len() + if closes_live { 1 } else { 0 };
+        let evicted = history_len.checked_sub(closed.len())?;
+
+        let first_key = match self.history.get(evicted) {
+            Some(candle) => candle.date_key,
+            None if evicted < history_len => current_live.date_key,
+            None => live.date_key,
+        };
+
+        if first_key != candles[0].date_key {
+            return None;
+        }
+
+        let history = if closes_live || evicted > 0 {
+            let mut history = self.history.as_ref().clone();
+
+            if closes_live {
+                history.push(closed.last()?.clone());
+            }
+
+            history.remove_first(evicted);
+            Arc::new(history)
+        } else {
+            self.history.clone()
+        };
+
+        Some(Self {
+            candle_type: self.candle_type,
+            history,
+            live: Some(live.clone()),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len() + if self.live.is_some() { 1 } else { 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live.is_none()
+    }
+
+    /// The latest candle of the series which is still being updated by incoming quotes.
+    pub fn get_live_candle(&self) -> Option<&CandleModel> {
+        self.live.as_ref()
+    }
+
+    pub fn get_first_candle(&self) -> Option<&CandleModel> {
+        self.history.get(0).or(self.live.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &CandleModel> {
+        self.history.iter_from(0).chain(self.live.iter())
+    }
+
+    pub fn get_candle(&self, date_key: CandleDateKey) -> Option<CandleModel> {
+        let index = self
+            .history
+            .partition_point(|candle| candle.date_key < date_key);
+
+        match self.history.get(index) {
+            Some(candle) if candle.date_key == date_key => Some(candle.clone()),
+            _ => self
+                .live
+                .as_ref()
+                .filter(|live| live.date_key == date_key)
+                .cloned(),
+        }
+    }
+
+    pub fn get_in_date_range(&self, from: CandleDateKey, to: CandleDateKey) -> Vec<CandleModel> {
+        let start = self
+            .history
+            .partition_point(|candle| candle.date_key < from);
+
+        self.history
+            .iter_from(start)
+            .chain(self.live.iter().filter(|live| live.date_key >= from))
+            .take_while(|candle| candle.date_key < to)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get_highest_and_below(&self, highest: CandleDateKey, amount: usize) -> Vec<CandleModel> {
+        let end = self
+            .history
+            .partition_point(|candle| candle.date_key <= highest);
+
+        let live = self
+            .live
+            .as_ref()
+            .filter(|live| end == self.history.len() && live.date_key <= highest);
+
+        let history_amount = amount.saturating_sub(live.iter().count());
+        let start = end.saturating_sub(history_amount);
+
+        self.history
+            .iter_from(start)
+            .take(end - start)
+            .chain(live)
+            .cloned()
+            .collect()
+    }
+}
+
+/// Consistent view of all the series of one instrument at the moment of publishing.
+#[derive(Debug, Clone, Default)]
+pub struct InstrumentCandlesSnapshot {
+    bids: HashMap<u8, Arc<CandlesSeriesSnapshot>>,
+    asks: HashMap<u8, Arc<CandlesSeriesSnapshot>>,
+}
+
+impl InstrumentCandlesSnapshot {
+    fn from_cache(bids: Option<&CandlesCacheByType>, asks: Option<&CandlesCacheByType>) -> Self {
+        Self {
+            bids: build_series_snapshots(bids),
+            asks: build_series_snapshots(asks),
+        }
+    }
+
+    fn get_series_mut(
+        &mut self,
+        bid_or_ask: BidOrAsk,
+    ) -> &mut HashMap<u8, Arc<CandlesSeriesSnapshot>> {
+        match bid_or_ask {
+            BidOrAsk::Bid => &mut self.bids,
+            BidOrAsk::Ask => &mut self.asks,
+        }
+    }
+
+    pub fn get(
+        &self,
+        bid_or_ask: BidOrAsk,
+        candle_type: CandleType,
+    ) -> Option<&Arc<CandlesSeriesSnapshot>> {
+        match bid_or_ask {
+            BidOrAsk::Bid => self.bids.get(&candle_type.to_u8()),
+            BidOrAsk::Ask => self.asks.get(&candle_type.to_u8()),
+        }
+    }
+}
+
+fn build_series_snapshots(
+    cache: Option<&CandlesCacheByType>,
+) -> HashMap<u8, Arc<CandlesSeriesSnapshot>> {
+    let mut result = HashMap::new();
+
+    if let Some(cache) = cache {
+        for (candle_type, candles) in &cache.candles {
+            result.insert(
+                *candle_type,
+                Arc::new(CandlesSeriesSnapshot::from_cache(candles)),
+            );
+        }
+    }
+
+    result
+}
+
+#[derive(Default)]
+struct PublishedSnapshots {
+    ids: HashMap<String, InstrumentId>,
+    snapshots: InstrumentsMap<Arc<InstrumentCandlesSnapshot>>,
+}
+
+impl PublishedSnapshots {
+    fn get<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Option<&Arc<InstrumentCandlesSnapshot>> {
+        let instrument_id = match instrument.into() {
+            InstrumentRef::Id(instrument_id) => instrument_id,
+            InstrumentRef::Name(name) => *self.ids.get(name)?,
+        };
+
+        self.snapshots.get(instrument_id)
+    }
+}
+
+/// Cache where writes go through a single writer and every instrument is published to readers as an
+/// immutable [`InstrumentCandlesSnapshot`].
+///
+/// Readers take a lock only to clone an `Arc` of the published snapshot, so they never wait for the
+/// ingestion and never block it while copying ranges of candles.
+pub struct SnapshotCandlesInstrumentsCache {
+    writer: Mutex<CandlesInstrumentsCache>,
+    published: RwLock<PublishedSnapshots>,
+}
+
+impl SnapshotCandlesInstrumentsCache {
+    pub fn new() -> Self {
+        Self {
+            writer: Mutex::new(CandlesInstrumentsCache::new()),
+            published: RwLock::new(PublishedSnapshots::default()),
+        }
+    }
+
+    fn lock_writer(&self) -> MutexGuard<'_, CandlesInstrumentsCache> {
+        self.writer.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn read_published(&self) -> RwLockReadGuard<'_, PublishedSnapshots> {
+        self.published.read().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn get_published<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+    ) -> Option<Arc<InstrumentCandlesSnapshot>> {
+        self.read_published().get(instrument).cloned()
+    }
+
+    fn publish(
+        &self,
+        cache: &CandlesInstrumentsCache,
+        instrument_id: InstrumentId,
+        snapshot: InstrumentCandlesSnapshot,
+    ) {
+        let mut published = self
+            .published
+            .write()
+            .unwrap_or_else(|err| err.into_inner());
+
+        if let Some(name) = cache.get_instrument_name(instrument_id) {
+            if !published.ids.contains_key(name) {
+                published.ids.insert(name.to_string(), instrument_id);
+            }
+        }
+
+        published
+            .snapshots
+            .insert(instrument_id, Arc::new(snapshot));
+    }
+
+    fn republish_instrument(&self, cache: &CandlesInstrumentsCache, instrument_id: InstrumentId) {
+        let snapshot = InstrumentCandlesSnapshot::from_cache(
+            cache.get_candles_by_type(BidOrAsk::Bid, instrument_id),
+            cache.get_candles_by_type(BidOrAsk::Ask, instrument_id),
+        );
+
+        self.publish(cache, instrument_id, snapshot);
+    }
+
+    fn republish_all(&self, cache: &CandlesInstrumentsCache) {
+        let instruments: Vec<InstrumentId> = cache
+            .iter_instruments()
+            .map(|(instrument_id, _)| instrument_id)
+            .collect();
+
+        for instrument_id in instruments {
+            self.republish_instrument(cache, instrument_id);
+        }
+    }
+
+    /// Id of the instrument which was already published.
+    pub fn get_instrument_id(&self, instrument: &str) -> Option<InstrumentId> {
+        self.read_published().ids.get(instrument).copied()
+    }
+
+    /// Registers the instrument if it is met for the first time. Waits for the writer.
+    pub fn get_or_add_instrument_id(&self, instrument: &str) -> InstrumentId {
+        self.lock_writer().get_or_add_instrument_id(instrument)
+    }
+
+    pub fn handle_bid_ask<'s>(
+        &self,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        bid: f64,
+        ask: f64,
+        time_stamp: DateTimeAsMicroseconds,
+        max_candles_amount: usize,
+    ) -> HandleBidAskChanges {
+        let mut cache = self.lock_writer();
+        let instrument_id = cache.instruments.resolve_or_add(instrument_id);
+
+        let changes = cache.handle_bid_ask(instrument_id, bid, ask, time_stamp, max_candles_amount);
+
+        self.publish_changes(&cache, instrument_id, &changes);
+
+        changes
+    }
+
+    pub fn handle_bid_ask_with_volume<'s>(
+        &self,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        bid: f64,
+        ask: f64,
+        volume: f64,
+        time_stamp: DateTimeAsMicroseconds,
+        max_candles_amount: usize,
+    ) -> HandleBidAskChanges {
+        let mut cache = self.lock_writer();
+        let instrument_id = cache.instruments.resolve_or_add(instrument_id);
+
+        let changes = cache.handle_bid_ask_with_volume(
+            instrument_id,
+            bid,
+            ask,
+            volume,
+            time_stamp,
+            max_candles_amount,
+        );
+
+        self.publish_changes(&cache, instrument_id, &changes);
+
+        changes
+    }
+
+    pub fn handle_bid_ask_batch<'s>(
+        &self,
+        quotes: &[BidAskToHandle<'s>],
+        max_candles_amount: usize,
+    ) -> HashMap<&'s str, HandleBidAskChanges> {
+        let mut cache = self.lock_writer();
+
+        let result = cache.handle_bid_ask_batch(quotes, max_candles_amount);
+
+        for (instrument_id, changes) in &result {
+            if let Some(instrument_id) = cache.get_instrument_id(instrument_id) {
+                self.publish_changes(&cache, instrument_id, changes);
+            }
+        }
+
+        result
+    }
+
+    fn publish_changes(
+        &self,
+        cache: &CandlesInstrumentsCache,
+        instrument_id: InstrumentId,
+        changes: &HandleBidAskChanges,
+    ) {
+        let mut snapshot = match self.get_published(instrument_id) {
+            Some(snapshot) => snapshot.as_ref().clone(),
+            None => InstrumentCandlesSnapshot::default(),
+        };
+
+        update_series_snapshots(
+            snapshot.get_series_mut(BidOrAsk::Bid),
+            cache.get_candles_by_type(BidOrAsk::Bid, instrument_id),
+            &changes.bids_to_persist,
+        );
+
+        update_series_snapshots(
+            snapshot.get_series_mut(BidOrAsk::Ask),
+            cache.get_candles_by_type(BidOrAsk::Ask, instrument_id),
+            &changes.asks_to_persist,
+        );
+
+        self.publish(cache, instrument_id, snapshot);
+
+        for synthetic in &changes.synthetics {
+            self.publish_changes(cache, synthetic.instrument_id, &synthetic.changes);
+        }
+    }
+
+    pub fn init_candles<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        candles_to_init: impl Iterator<Item = CandleModel>,
+        pre_allocate_memory: Option<usize>,
+    ) {
+        let mut cache = self.lock_writer();
+        let instrument_id = cache.instruments.resolve_or_add(instrument);
+        cache.init_candles(
+            bid_or_ask,
+            instrument_id,
+            candle_type,
+            candles_to_init,
+            pre_allocate_memory,
+        );
+        self.republish_instrument(&cache, instrument_id);
+    }
+
+    pub fn bulk_insert_or_update<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        candles_to_init: impl Iterator<Item = CandleModel>,
+    ) {
+        let mut cache = self.lock_writer();
+        let instrument_id = cache.instruments.resolve_or_add(instrument);
+        cache.bulk_insert_or_update(bid_or_ask, instrument_id, candle_type, candles_to_init);
+        self.republish_instrument(&cache, instrument_id);
+    }
+
+    pub fn clean_by_type<'s>(
+        &self,
+        bid_or_ask: BidOrAsk,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+    ) {
+        let mut cache = self.lock_writer();
+
+        if let Some(instrument_id) = cache.instruments.resolve(instrument_id) {
+            cache.clean_by_type(bid_or_ask, instrument_id, candle_type);
+            self.republish_instrument(&cache, instrument_id);
+        }
+    }
+
+    pub fn gc_candles_by_instrument<'s>(
+        &self,
+        instrument: impl Into<InstrumentRef<'s>>,
+        candle_type: CandleType,
+        max_candles_amount: usize,
+    ) {
+        let mut cache = self.lock_writer();
+
+        if let Some(instrument_id) = cache.instruments.resolve(instrument) {
+            cache.gc_candles_by_instrument(instrument_id, candle_type, max_candles_amount);
+            self.republish_instrument(&cache, instrument_id);
+        }
+    }
+
+    pub fn gc_candles(&self, candle_type: CandleType, max_candles_amount: usize) {
+        let mut cache = self.lock_writer();
+        cache.gc_candles(candle_type, max_candles_amount);
+        self.republish_all(&cache);
+    }
+
+    pub fn get_instruments(&self) -> HashSet<String> {
+        self.read_published().ids.keys().cloned().collect()
+    }
+
+    /// Snapshot of all the series of the instrument. Stays valid and unchanged as long as it is held.
+    pub fn get_snapshot<'s>(
+        &self,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+    ) -> Option<Arc<InstrumentCandlesSnapshot>> {
+        self.get_published(instrument_id)
+    }
+
+    pub fn get_series_snapshot<'s>(
+        &self,
+        instrument_id: impl Into<InstrumentRef<'s>>,
+        bid_or_ask: BidOrAsk,
+        candle_type: CandleType,
+    ) -> Option<Arc<CandlesSeriesSnapshot>> {
+        let snapshot = self.get_published(instrument_id)?;
+        snapshot.get(bid_or_ask, candle_type).cloned()
+    }
+}
+
+fn update_series_snapshots(
+    series: &mut HashMap<u8, Arc<CandlesSeriesSnapshot>>,
+    cache: Option<&CandlesCacheByType>,
+    changes: &[CandleToPersist],
+) {
+    let cache = match cache {
+        Some(cache) => cache,
+        None => return,
+    };
+
+    for change in changes {
+        let candle_type = change.candle_type.to_u8();
+
+        let candles = match cache.candles.get(&candle_type) {
+            Some(candles) => candles,
+            None => continue,
+        };
+
+        let next = match series
+            .get(&candle_type)
+            .and_then(|current| current.with_latest_candle(change, candles))
+        {
+            Some(next) => next,
+            None => CandlesSeriesSnapshot::from_cache(candles),
+        };
+
+        series.insert(candle_type, Arc::new(next));
+    }
+}
+
+impl Default for SnapshotCandlesInstrumentsCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{BidOrAsk, CandleDateKey, CandleType};
+
+    use super::SnapshotCandlesInstrumentsCache;
+
+    #[test]
+    fn test_readers_keep_consistent_snapshot() {
+        let cache = SnapshotCandlesInstrumentsCache::new();
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+        cache.handle_bid_ask("EURUSD", 1.1, 1.2, now, 100);
+
+        let before = cache
+            .get_series_snapshot("EURUSD", BidOrAsk::Bid, CandleType::Minute)
+            .unwrap();
+
+        let next_tick = DateTimeAsMicroseconds::new(now.unix_microseconds + 5_000_000);
+        cache.handle_bid_ask("EURUSD", 1.3, 1.4, next_tick, 100);
+
+        let after_live_update = cache
+            .get_series_snapshot("EURUSD", BidOrAsk::Bid, CandleType::Minute)
+            .unwrap();
+
+        assert_eq!(1.1, before.get_live_candle().unwrap().data.close);
+        assert_eq!(1.3, after_live_update.get_live_candle().unwrap().data.close);
+        assert!(Arc::ptr_eq(&before.history, &after_live_update.history));
+
+        let mut next_minute = now;
+        next_minute.add_minutes(1);
+        cache.handle_bid_ask("EURUSD", 1.5, 1.6, next_minute, 100);
+
+        let after_new_candle = cache
+            .get_series_snapshot("EURUSD", BidOrAsk::Bid, CandleType::Minute)
+            .unwrap();
+
+        assert_eq!(1, before.len());
+        assert_eq!(2, after_new_candle.len());
+        assert_eq!(
+            202101011022,
+            after_new_candle
+                .get_first_candle()
+                .unwrap()
+                .date_key
+                .get_value()
+        );
+        assert_eq!(
+            202101011023,
+            after_new_candle
+                .get_live_candle()
+                .unwrap()
+                .date_key
+                .get_value()
+        );
+    }
+
+    #[test]
+    fn test_instrument_ids() {
+        let cache = SnapshotCandlesInstrumentsCache::new();
+        let eurusd = cache.get_or_add_instrument_id("EURUSD");
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+        cache.handle_bid_ask(eurusd, 1.1, 1.2, now, 100);
+
+        assert_eq!(Some(eurusd), cache.get_instrument_id("EURUSD"));
+        assert!(cache
+            .get_series_snapshot("EURUSD", BidOrAsk::Ask, CandleType::Hour)
+            .is_some());
+        assert!(Arc::ptr_eq(
+            &cache.get_snapshot(eurusd).unwrap(),
+            &cache.get_snapshot("EURUSD").unwrap()
+        ));
+        assert!(cache.get_snapshot("USDJPY").is_none());
+    }
+
+    #[test]
+    fn test_range_after_last_candle() {
+        let cache = SnapshotCandlesInstrumentsCache::new();
+
+        let now = DateTimeAsMicroseconds::from_str("2021-01-01T10:22:33").unwrap();
+        cache.handle_bid_ask("EURUSD", 1.1, 1.2, now, 100);
+
+        let mut next_minute = now;
+        next_minute.add_minutes(1);
+        cache.handle_bid_ask("EURUSD", 1.5, 1.6, next_minute, 100);
+
+        let series = cache
+            .get_series_snapshot("EURUSD", BidOrAsk::Bid, CandleType::Minute)
+            .unwrap();
+
+        assert!(series
+            .get_in_date_range(
+                CandleDateKey::new(202101011024),
+                CandleDateKey::new(202101011100)
+            )
+            .is_empty());
+
+        assert_eq!(
+            1,
+            series
+                .get_in_date_range(
+                    CandleDateKey::new(202101011023),
+                    CandleDateKey::new(202101011100)
+                )
+                .len()
+        );
+
+        assert_eq!(
+            1.5,
+            series
+                .get_candle(CandleDateKey::new(202101011023))
+                .unwrap()
+                .data
+                .open
+        );
+        assert!(series
+            .get_candle(CandleDateKey::new(202101011021))
+            .is_none());
+    }
+
+    #[test]
+    fn test_history_chunks_follow_the_cache() {
+        let cache = SnapshotCandlesInstrumentsCache::new();
+        let start = DateTimeAsMicroseconds::from_str("2021-01-01T00:00:00").unwrap();
+
+        for minute in 0..3000 {
+            let mut time_stamp = start;
+            time_stamp.add_minutes(minute);
+            let price = 1.0 + minute as f64 * 0.0001;
+            cache.handle_bid_ask("EURUSD", price, price, time_stamp, 1500);
+        }
+
+        let series = cache
+            .get_series_snapshot("EURUSD", BidOrAsk::Bid, CandleType::Minute)
+            .unwrap();
+
+        let writer = cache.lock_writer();
+        let expected = writer
+            .get_candles_by_type(BidOrAsk::Bid, "EURUSD")
+            .unwrap()
+            .get_by_type(CandleType::Minute)
+            .unwrap()
+            .candles
+            .as_slice();
+
+        assert_eq!(expected.len(), series.len());
+
+        for (expected, actual) in expected.iter().zip(series.iter()) {
+            assert_eq!(expected.date_key, actual.date_key);
+            assert_eq!(expected.data.close, actual.data.close);
+        }
+
+        let highest = expected[expected.len() - 2].date_key;
+        let below = series.get_highest_and_below(highest, 3);
+        assert_eq!(3, below.len());
+        assert_eq!(highest, below[2].date_key);
+
+        let below = series.get_highest_and_below(expected[expected.len() - 1].date_key, 1);
+        assert_eq!(expected[expected.len() - 1].date_key, below[0].date_key);
+        drop(writer);
+
+        let mut time_stamp = start;
+        time_stamp.add_minutes(3000);
+        cache.handle_bid_ask("EURUSD", 1.5, 1.5, time_stamp, 1500);
+
+        let next = cache
+            .get_series_snapshot("EURUSD", BidOrAsk::Bid, CandleType::Minute)
+            .unwrap();
+
+        assert_eq!(series.len(), next.len());
+        assert!(Arc::ptr_eq(
+            &series.history.chunks[0],
+            &next.history.chunks[0]
+        ));
+    }
+
+    #[test]
+    fn test_history_chunks_are_shared_at_retention_cap() {
+        let cache = SnapshotCandlesInstrumentsCache::new();
+        let start = DateTimeAsMicroseconds::from_str("2021-01-01T00:00:00").unwrap();
+
+        let handle_minute = |minute: i64| {
+            for second in [0, 20, 40] {
+                let mut time_stamp = start;
+                time_stamp.add_minutes(minute);
+                time_stamp.add_seconds(second);
+                let price = 1.0 + (minute * 60 + second) as f64 * 0.00001;
+                cache.handle_bid_ask("EURUSD", price, price, time_stamp, 1500);
+            }
+        };
+
+        for minute in 0..1600 {
+            handle_minute(minute);
+        }
+
+        let first = cache
+            .get_series_snapshot("EURUSD", BidOrAsk::Bid, CandleType::Minute)
+            .unwrap();
+
+        for minute in 1600..1610 {
+            handle_minute(minute);
+
+            let next = cache
+                .get_series_snapshot("EURUSD", BidOrAsk::Bid, CandleType::Minute)
+                .unwrap();
+
+            assert!(Arc::ptr_eq(
+                &first.history.chunks[0],
+                &next.history.chunks[0]
+            ));
+        }
+
+        let series = cache
+            .get_series_snapshot("EURUSD", BidOrAsk::Bid, CandleType::Minute)
+            .unwrap();
+
+        let writer = cache.lock_writer();
+        let expected = writer
+            .get_candles_by_type(BidOrAsk::Bid, "EURUSD")
+            .unwrap()
+            .get_by_type(CandleType::Minute)
+            .unwrap()
+            .candles
+            .as_slice();
+
+        assert_eq!(expected.len(), series.len());
+
+        for (expected, actual) in expected.iter().zip(series.iter()) {
+            assert_eq!(expected.date_key, actual.date_key);
+            assert_eq!(expected.data.open, actual.data.open);
+            assert_eq!(expected.data.close, actual.data.close);
+        }
+    }
+}
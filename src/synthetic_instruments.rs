@@ -0,0 +1,306 @@
+use crate::{InstrumentId, InstrumentsInterner, InstrumentsMap};
+
+#[derive(Debug, Clone)]
+pub struct SyntheticLeg {
+    pub instrument_id: String,
+    /// Leg is used as `1 / price`, bid and ask are swapped.
+    pub invert: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntheticBasketItem {
+    pub instrument_id: String,
+    /// Negative weights take the ask of the item for the bid of the basket and vice versa.
+    pub weight: f64,
+}
+
+/// Formula of a synthetic instrument over the bid/ask of other instruments.
+#[derive(Debug, Clone)]
+pub enum SyntheticFormula {
+    /// Product of the legs, e.g. EURJPY = EURUSD * USDJPY.
+    Product(Vec<SyntheticLeg>),
+    /// Weighted sum of the items, e.g. an index basket.
+    Basket(Vec<SyntheticBasketItem>),
+}
+
+enum ResolvedFormula {
+    Product(Vec<(InstrumentId, bool)>),
+    Basket(Vec<(InstrumentId, f64)>),
+}
+
+impl ResolvedFormula {
+    fn get_components(&self) -> Vec<InstrumentId> {
+        match self {
+            Self::Product(legs) => legs.iter().map(|(id, _)| *id).collect(),
+            Self::Basket(items) => items.iter().map(|(id, _)| *id).collect(),
+        }
+    }
+}
+
+struct SyntheticDefinition {
+    formula: SyntheticFormula,
+    resolved: ResolvedFormula,
+}
+
+/// Synthetic instruments with the latest quotes of their components.
+pub struct SyntheticInstruments {
+    definitions: InstrumentsMap<SyntheticDefinition>,
+    dependents: InstrumentsMap<Vec<InstrumentId>>,
+    last_quotes: InstrumentsMap<(f64, f64)>,
+}
+
+impl SyntheticInstruments {
+    pub fn new() -> Self {
+        Self {
+            definitions: InstrumentsMap::new(),
+            dependents: InstrumentsMap::new(),
+            last_quotes: InstrumentsMap::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.values().next().is_none()
+    }
+
+    pub fn is_synthetic(&self, instrument_id: InstrumentId) -> bool {
+        self.definitions.contains(instrument_id)
+    }
+
+    pub fn get_formula(&self, instrument_id: InstrumentId) -> Option<&SyntheticFormula> {
+        self.definitions
+            .get(instrument_id)
+            .map(|definition| &definition.formula)
+    }
+
+    /// Components must be real instruments. Names of the components are registered in the interner.
+    pub fn add(
+        &mut self,
+        instruments: &mut InstrumentsInterner,
+        instrument_id: InstrumentId,
+        formula: SyntheticFormula,
+    ) -> Result<(), String> {
+        if self.is_synthetic(instrument_id) {
+            return Err(format!(
+                "Synthetic instrument {} is already defined",
+                instrument_id.get_value()
+            ));
+        }
+
+        if self.dependents.contains(instrument_id) {
+            return Err(format!(
+                "Instrument {} is a component of a synthetic instrument",
+                instrument_id.get_value()
+            ));
+        }
+
+        let resolved = match &formula {
+            SyntheticFormula::Product(legs) => ResolvedFormula::Product(
+                legs.iter()
+                    .map(|leg| (instruments.get_or_add(&leg.instrument_id), leg.invert))
+                    .collect(),
+            ),
+            SyntheticFormula::Basket(items) => ResolvedFormula::Basket(
+                items
+                    .iter()
+                    .map(|item| (instruments.get_or_add(&item.instrument_id), item.weight))
+                    .collect(),
+            ),
+        };
+
+        let components = resolved.get_components();
+
+        if components.is_empty() {
+            return Err("Synthetic instrument must have at least one component".to_string());
+        }
+
+        for component in &components {
+            if *component == instrument_id {
+                return Err("Synthetic instrument can not be a component of itself".to_string());
+            }
+
+            if self.is_synthetic(*component) {
+                return Err(format!(
+                    "Component {} is a synthetic instrument",
+                    instruments.get_name(*component).unwrap_or_default()
+                ));
+            }
+        }
+
+        for component in components {
+            let dependents = self.dependents.get_or_insert_with(component, Vec::new);
+
+            if !dependents.contains(&instrument_id) {
+                dependents.push(instrument_id);
+            }
+        }
+
+        self.definitions
+            .insert(instrument_id, SyntheticDefinition { formula, resolved });
+
+        Ok(())
+    }
+
+    pub fn remove(&mut self, instrument_id: InstrumentId) -> Option<SyntheticFormula> {
+        let definition = self.definitions.remove(instrument_id)?;
+
+        for component in definition.resolved.get_components() {
+            let has_dependents = match self.dependents.get_mut(component) {
+                Some(dependents) => {
+                    dependents.retain(|id| *id != instrument_id);
+                    !dependents.is_empty()
+                }
+                None => false,
+            };
+
+            if !has_dependents {
+                self.dependents.remove(component);
+                self.last_quotes.remove(component);
+            }
+        }
+
+        Some(definition.formula)
+    }
+
+    /// Remembers the quote if the instrument is a component and returns the synthetic instruments to recalculate.
+    pub fn update_quote(
+        &mut self,
+        instrument_id: InstrumentId,
+        bid: f64,
+        ask: f64,
+    ) -> &[InstrumentId] {
+        match self.dependents.get(instrument_id) {
+            Some(dependents) => {
+                self.last_quotes.insert(instrument_id, (bid, ask));
+                dependents
+            }
+            None => &[],
+        }
+    }
+
+    /// Returns bid and ask of the synthetic instrument. `None` until every component is quoted.
+    pub fn calculate(&self, instrument_id: InstrumentId) -> Option<(f64, f64)> {
+        match &self.definitions.get(instrument_id)?.resolved {
+            ResolvedFormula::Product(legs) => {
+                let mut bid = 1.0;
+                let mut ask = 1.0;
+
+                for (component, invert) in legs {
+                    let (leg_bid, leg_ask) = *self.last_quotes.get(*component)?;
+
+                    if *invert {
+                        if leg_bid <= 0.0 || leg_ask <= 0.0 {
+                            return None;
+                        }
+
+                        bid /= leg_ask;
+                        ask /= leg_bid;
+                    } else {
+                        bid *= leg_bid;
+                        ask *= leg_ask;
+                    }
+                }
+
+                Some((bid, ask))
+            }
+            ResolvedFormula::Basket(items) => {
+                let mut bid = 0.0;
+                let mut ask = 0.0;
+
+                for (component, weight) in items {
+                    let (item_bid, item_ask) = *self.last_quotes.get(*component)?;
+
+                    if *weight >= 0.0 {
+                        bid += weight * item_bid;
+                        ask += weight * item_ask;
+                    } else {
+                        bid += weight * item_ask;
+                        ask += weight * item_bid;
+                    }
+                }
+
+                Some((bid, ask))
+            }
+        }
+    }
+}
+
+impl Default for SyntheticInstruments {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::InstrumentsInterner;
+
+    use super::{SyntheticBasketItem, SyntheticFormula, SyntheticInstruments, SyntheticLeg};
+
+    #[test]
+    fn test_calculate() {
+        let mut interner = InstrumentsInterner::new();
+        let mut synthetics = SyntheticInstruments::new();
+
+        let jpyeur = interner.get_or_add("JPYEUR");
+        let basket = interner.get_or_add("BASKET");
+
+        synthetics
+            .add(
+                &mut interner,
+                jpyeur,
+                SyntheticFormula::Product(vec![
+                    SyntheticLeg {
+                        instrument_id: "EURUSD".to_string(),
+                        invert: true,
+                    },
+                    SyntheticLeg {
+                        instrument_id: "USDJPY".to_string(),
+                        invert: true,
+                    },
+                ]),
+            )
+            .unwrap();
+
+        synthetics
+            .add(
+                &mut interner,
+                basket,
+                SyntheticFormula::Basket(vec![
+                    SyntheticBasketItem {
+                        instrument_id: "EURUSD".to_string(),
+                        weight: 2.0,
+                    },
+                    SyntheticBasketItem {
+                        instrument_id: "USDJPY".to_string(),
+                        weight: -1.0,
+                    },
+                ]),
+            )
+            .unwrap();
+
+        let eurusd = interner.get_id("EURUSD").unwrap();
+        let usdjpy = interner.get_id("USDJPY").unwrap();
+
+        assert_eq!(2, synthetics.update_quote(eurusd, 2.0, 4.0).len());
+        assert_eq!(None, synthetics.calculate(jpyeur));
+
+        synthetics.update_quote(usdjpy, 5.0, 10.0);
+
+        assert_eq!(Some((1.0 / 40.0, 1.0 / 10.0)), synthetics.calculate(jpyeur));
+        assert_eq!(Some((4.0 - 10.0, 8.0 - 5.0)), synthetics.calculate(basket));
+
+        assert!(synthetics
+            .add(
+                &mut interner,
+                eurusd,
+                SyntheticFormula::Product(vec![SyntheticLeg {
+                    instrument_id: "JPYEUR".to_string(),
+                    invert: false,
+                }]),
+            )
+            .is_err());
+
+        synthetics.remove(jpyeur);
+        assert_eq!(1, synthetics.update_quote(usdjpy, 5.0, 10.0).len());
+    }
+}
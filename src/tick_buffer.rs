@@ -0,0 +1,179 @@
+use std::collections::VecDeque;
+
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{BidOrAsk, CandleData};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BufferedTick {
+    pub bid: f64,
+    pub ask: f64,
+    pub volume: f64,
+    pub time_stamp: DateTimeAsMicroseconds,
+}
+
+impl BufferedTick {
+    pub fn get_price(&self, bid_or_ask: BidOrAsk) -> f64 {
+        match bid_or_ask {
+            BidOrAsk::Bid => self.bid,
+            BidOrAsk::Ask => self.ask,
+        }
+    }
+}
+
+/// Ticks of the last `horizon_microseconds` ordered by time stamp.
+pub struct TickBuffer {
+    ticks: VecDeque<BufferedTick>,
+    horizon_microseconds: i64,
+    covered_from: Option<DateTimeAsMicroseconds>,
+}
+
+impl TickBuffer {
+    pub fn new(horizon_microseconds: i64) -> Self {
+        Self {
+            ticks: VecDeque::new(),
+            horizon_microseconds,
+            covered_from: None,
+        }
+    }
+
+    /// Every tick handled at or after this moment is in the buffer.
+    pub fn get_covered_from(&self) -> Option<DateTimeAsMicroseconds> {
+        self.covered_from
+    }
+
+    pub fn len(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ticks.is_empty()
+    }
+
+    pub fn push(&mut self, tick: BufferedTick) {
+        if self.covered_from.is_none() {
+            self.covered_from = Some(tick.time_stamp);
+        }
+
+        let index = self.ticks.partition_point(|itm| {
+            itm.time_stamp.unix_microseconds <= tick.time_stamp.unix_microseconds
+        });
+        self.ticks.insert(index, tick);
+
+        let last = self.ticks.back().unwrap().time_stamp.unix_microseconds;
+
+        while let Some(first) = self.ticks.front() {
+            if first.time_stamp.unix_microseconds >= last - self.horizon_microseconds {
+                break;
+            }
+
+            // A late tick older than the covered range must not extend it
+            let removed = self.ticks.pop_front().unwrap();
+            let covered_from = removed.time_stamp.unix_microseconds + 1;
+
+            match self.covered_from {
+                Some(current) if current.unix_microseconds >= covered_from => {}
+                _ => self.covered_from = Some(DateTimeAsMicroseconds::new(covered_from)),
+            }
+        }
+    }
+
+    /// Index of the first tick with the time stamp.
+    pub fn find(&self, time_stamp: DateTimeAsMicroseconds) -> Option<usize> {
+        let index = self
+            .ticks
+            .partition_point(|itm| itm.time_stamp.unix_microseconds < time_stamp.unix_microseconds);
+
+        let tick = self.ticks.get(index)?;
+
+        if tick.time_stamp.unix_microseconds == time_stamp.unix_microseconds {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut BufferedTick> {
+        self.ticks.get_mut(index)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<BufferedTick> {
+        self.ticks.remove(index)
+    }
+
+    pub fn get_estimated_bytes(&self) -> usize {
+        std::mem::size_of::<Self>() + self.ticks.capacity() * std::mem::size_of::<BufferedTick>()
+    }
+
+    pub fn update_before(
+        &mut self,
+        before: DateTimeAsMicroseconds,
+        mut update: impl FnMut(&mut BufferedTick),
+    ) {
+        for tick in self.ticks.iter_mut() {
+            if tick.time_stamp.unix_microseconds >= before.unix_microseconds {
+                break;
+            }
+
+            update(tick);
+        }
+    }
+
+    pub fn iter_in_range(
+        &self,
+        from: DateTimeAsMicroseconds,
+        to: DateTimeAsMicroseconds,
+    ) -> impl Iterator<Item = &BufferedTick> {
+        self.ticks.iter().filter(move |tick| {
+            tick.time_stamp.unix_microseconds >= from.unix_microseconds
+                && tick.time_stamp.unix_microseconds < to.unix_microseconds
+        })
+    }
+
+    /// Candle built from the ticks within `from..to`.
+    pub fn build_candle(
+        &self,
+        bid_or_ask: BidOrAsk,
+        from: DateTimeAsMicroseconds,
+        to: DateTimeAsMicroseconds,
+    ) -> Option<CandleData> {
+        let mut result: Option<CandleData> = None;
+
+        for tick in self.iter_in_range(from, to) {
+            let price = tick.get_price(bid_or_ask);
+
+            match result.as_mut() {
+                Some(candle) => candle.update_from_price(price, 0.0),
+                None => result = Some(CandleData::new_from_price(price, 0.0)),
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use super::{BufferedTick, TickBuffer};
+
+    #[test]
+    fn test_horizon() {
+        let mut buffer = TickBuffer::new(10);
+
+        for time in [5, 0, 12, 20] {
+            buffer.push(BufferedTick {
+                bid: time as f64,
+                ask: time as f64,
+                volume: 0.0,
+                time_stamp: DateTimeAsMicroseconds::new(time),
+            });
+        }
+
+        assert_eq!(2, buffer.len());
+        assert_eq!(6, buffer.get_covered_from().unwrap().unix_microseconds);
+        assert_eq!(Some(0), buffer.find(DateTimeAsMicroseconds::new(12)));
+        assert_eq!(None, buffer.find(DateTimeAsMicroseconds::new(5)));
+    }
+}
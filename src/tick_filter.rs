@@ -0,0 +1,342 @@
+use std::collections::VecDeque;
+
+use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+use crate::{InstrumentId, InstrumentsMap};
+
+#[derive(Debug, Clone, Copy)]
+pub enum DeviationReference {
+    /// Mid price of the last accepted tick.
+    LastPrice,
+    /// Median of the mid prices of the last accepted ticks.
+    RollingMedian { window: usize },
+}
+
+impl DeviationReference {
+    fn get_window(&self) -> usize {
+        match self {
+            Self::LastPrice => 1,
+            Self::RollingMedian { window } => (*window).max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TickFilterSettings {
+    /// Ticks with the mid price deviating from the reference by more than the percent are suspicious.
+    pub max_deviation_percent: Option<f64>,
+    pub deviation_reference: DeviationReference,
+    pub reject_bid_above_ask: bool,
+    /// Suspicious ticks are held in quarantine instead of being rejected right away.
+    /// If this amount of suspicious ticks close to each other arrives in a row, the market is considered moved
+    /// and the quarantined ticks are accepted. Otherwise they are rejected by the next normal tick.
+    pub quarantine_confirm_ticks: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TickRejectReason {
+    NonFinite,
+    BidAboveAsk,
+    Deviation {
+        reference: f64,
+        deviation_percent: f64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct RejectedTick {
+    pub instrument_id: InstrumentId,
+    pub bid: f64,
+    pub ask: f64,
+    pub time_stamp: DateTimeAsMicroseconds,
+    pub reason: TickRejectReason,
+}
+
+/// Quote as (bid, ask, volume, time stamp).
+pub type TickToFilter = (f64, f64, f64, DateTimeAsMicroseconds);
+
+#[derive(Default)]
+pub struct TickFilterResult {
+    pub accepted: Vec<TickToFilter>,
+    pub rejected: Vec<RejectedTick>,
+}
+
+struct TickFilterState {
+    references: VecDeque<f64>,
+    quarantine: Vec<(TickToFilter, TickRejectReason)>,
+}
+
+impl TickFilterState {
+    fn new() -> Self {
+        Self {
+            references: VecDeque::new(),
+            quarantine: Vec::new(),
+        }
+    }
+
+    fn get_reference(&self) -> Option<f64> {
+        if self.references.is_empty() {
+            return None;
+        }
+
+        let mut sorted: Vec<f64> = self.references.iter().copied().collect();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let middle = sorted.len() / 2;
+
+        if sorted.len() % 2 == 0 {
+            Some((sorted[middle - 1] + sorted[middle]) / 2.0)
+        } else {
+            Some(sorted[middle])
+        }
+    }
+
+    fn accept(&mut self, tick: TickToFilter, window: usize) {
+        self.references.push_back(get_mid(&tick));
+
+        while self.references.len() > window {
+            self.references.pop_front();
+        }
+    }
+}
+
+fn get_mid(tick: &TickToFilter) -> f64 {
+    (tick.0 + tick.1) / 2.0
+}
+
+fn get_deviation_percent(price: f64, reference: f64) -> f64 {
+    if reference == 0.0 {
+        return if price == 0.0 { 0.0 } else { f64::INFINITY };
+    }
+
+    ((price - reference) / reference).abs() * 100.0
+}
+
+/// Checks ticks before they reach the candles. Instruments without settings are not filtered.
+pub struct TickFilter {
+    settings: InstrumentsMap<TickFilterSettings>,
+    states: InstrumentsMap<TickFilterState>,
+}
+
+impl TickFilter {
+    pub fn new() -> Self {
+        Self {
+            settings: InstrumentsMap::new(),
+            states: InstrumentsMap::new(),
+        }
+    }
+
+    /// Changing the settings resets the state of the instrument.
+    pub fn set_settings(
+        &mut self,
+        instrument_id: InstrumentId,
+        settings: Option<TickFilterSettings>,
+    ) {
+        self.states.remove(instrument_id);
+
+        match settings {
+            Some(settings) => {
+                self.settings.insert(instrument_id, settings);
+            }
+            None => {
+                self.settings.remove(instrument_id);
+            }
+        }
+    }
+
+    pub fn get_settings(&self, instrument_id: InstrumentId) -> Option<TickFilterSettings> {
+        self.settings.get(instrument_id).copied()
+    }
+
+    pub fn is_enabled(&self, instrument_id: InstrumentId) -> bool {
+        self.settings.contains(instrument_id)
+    }
+
+    pub fn get_quarantined(&self, instrument_id: InstrumentId) -> Vec<TickToFilter> {
+        match self.states.get(instrument_id) {
+            Some(state) => state.quarantine.iter().map(|(tick, _)| *tick).collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns ticks to handle in the order they must be handled. Released quarantined ticks come first.
+    pub fn check(&mut self, instrument_id: InstrumentId, tick: TickToFilter) -> TickFilterResult {
+        let mut result = TickFilterResult::default();
+
+        let settings = match self.settings.get(instrument_id) {
+            Some(settings) => *settings,
+            None => {
+                result.accepted.push(tick);
+                return result;
+            }
+        };
+
+        let rejected = |tick: TickToFilter, reason| RejectedTick {
+            instrument_id,
+            bid: tick.0,
+            ask: tick.1,
+            time_stamp: tick.3,
+            reason,
+        };
+
+        let (bid, ask, volume, _) = tick;
+
+        if !bid.is_finite() || !ask.is_finite() || !volume.is_finite() {
+            result
+                .rejected
+                .push(rejected(tick, TickRejectReason::NonFinite));
+            return result;
+        }
+
+        if settings.reject_bid_above_ask && bid > ask {
+            result
+                .rejected
+                .push(rejected(tick, TickRejectReason::BidAboveAsk));
+            return result;
+        }
+
+        let window = settings.deviation_reference.get_window();
+        let state = self
+            .states
+            .get_or_insert_with(instrument_id, TickFilterState::new);
+
+        let max_deviation_percent = match settings.max_deviation_percent {
+            Some(max_deviation_percent) => max_deviation_percent,
+            None => {
+                state.accept(tick, window);
+                result.accepted.push(tick);
+                return result;
+            }
+        };
+
+        let reference = match state.get_reference() {
+            Some(reference) => reference,
+            None => {
+                state.accept(tick, window);
+                result.accepted.push(tick);
+                return result;
+            }
+        };
+
+        let deviation_percent = get_deviation_percent(get_mid(&tick), reference);
+
+        if deviation_percent <= max_deviation_percent {
+            for (tick, reason) in state.quarantine.drain(..) {
+                result.rejected.push(rejected(tick, reason));
+            }
+
+            state.accept(tick, window);
+            result.accepted.push(tick);
+            return result;
+        }
+
+        let reason = TickRejectReason::Deviation {
+            reference,
+            deviation_percent,
+        };
+
+        let confirm_ticks = match settings.quarantine_confirm_ticks {
+            Some(confirm_ticks) => confirm_ticks.max(1),
+            None => {
+                result.rejected.push(rejected(tick, reason));
+                return result;
+            }
+        };
+
+        // Quarantined ticks confirm each other only if they are close to each other
+        if let Some((first, _)) = state.quarantine.first() {
+            if get_deviation_percent(get_mid(&tick), get_mid(first)) > max_deviation_percent {
+                for (tick, reason) in state.quarantine.drain(..) {
+                    result.rejected.push(rejected(tick, reason));
+                }
+            }
+        }
+
+        state.quarantine.push((tick, reason));
+
+        if state.quarantine.len() >= confirm_ticks {
+            state.references.clear();
+
+            for (tick, _) in std::mem::take(&mut state.quarantine) {
+                state.accept(tick, window);
+                result.accepted.push(tick);
+            }
+        }
+
+        result
+    }
+}
+
+impl Default for TickFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::InstrumentsInterner;
+
+    use super::{DeviationReference, TickFilter, TickFilterSettings, TickRejectReason};
+
+    #[test]
+    fn test_tick_filter() {
+        let mut interner = InstrumentsInterner::new();
+        let eurusd = interner.get_or_add("EURUSD");
+
+        let mut filter = TickFilter::new();
+        filter.set_settings(
+            eurusd,
+            Some(TickFilterSettings {
+                max_deviation_percent: Some(1.0),
+                deviation_reference: DeviationReference::RollingMedian { window: 3 },
+                reject_bid_above_ask: true,
+                quarantine_confirm_ticks: Some(2),
+            }),
+        );
+
+        let now = DateTimeAsMicroseconds::new(0);
+
+        assert_eq!(1, filter.check(eurusd, (1.0, 1.0, 0.0, now)).accepted.len());
+        assert_eq!(
+            1,
+            filter
+                .check(eurusd, (1.005, 1.005, 0.0, now))
+                .accepted
+                .len()
+        );
+
+        let result = filter.check(eurusd, (f64::NAN, 1.0, 0.0, now));
+        assert_eq!(TickRejectReason::NonFinite, result.rejected[0].reason);
+
+        let result = filter.check(eurusd, (1.01, 1.0, 0.0, now));
+        assert_eq!(TickRejectReason::BidAboveAsk, result.rejected[0].reason);
+
+        // Spike is quarantined and rejected by the next normal tick
+        let result = filter.check(eurusd, (2.0, 2.0, 0.0, now));
+        assert!(result.accepted.is_empty() && result.rejected.is_empty());
+        assert_eq!(1, filter.get_quarantined(eurusd).len());
+
+        let result = filter.check(eurusd, (1.001, 1.001, 0.0, now));
+        assert_eq!(1, result.accepted.len());
+        assert_eq!(2.0, result.rejected[0].bid);
+
+        // Two ticks confirm the move
+        assert!(filter
+            .check(eurusd, (1.5, 1.5, 0.0, now))
+            .accepted
+            .is_empty());
+        let result = filter.check(eurusd, (1.501, 1.501, 0.0, now));
+        assert_eq!(2, result.accepted.len());
+
+        assert_eq!(
+            1,
+            filter
+                .check(eurusd, (1.502, 1.502, 0.0, now))
+                .accepted
+                .len()
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use std::collections::BTreeSet;
+
+use rust_extensions::date_time::{
+    DateTimeAsMicroseconds, MICRO_SECONDS_IN_ONE_DAY, MICRO_SECONDS_IN_ONE_HOUR,
+    MICRO_SECONDS_IN_ONE_MINUTE,
+};
+
+use crate::{CandleDateKey, CandleType, GetCandleDateKey};
+
+const MINUTES_IN_ONE_WEEK: i64 = 7 * 24 * 60;
+const MICRO_SECONDS_IN_ONE_WEEK: i64 = 7 * MICRO_SECONDS_IN_ONE_DAY;
+
+/// 1970-01-01 was Thursday.
+const FIRST_DAY_OF_WEEK: i64 = 3;
+
+/// Time within a week in UTC. `day_of_week` is 0 for Monday and 6 for Sunday.
+#[derive(Debug, Clone, Copy)]
+pub struct WeekTime {
+    pub day_of_week: u8,
+    pub hour: u8,
+    pub minute: u8,
+}
+
+impl WeekTime {
+    pub fn new(day_of_week: u8, hour: u8, minute: u8) -> Self {
+        Self {
+            day_of_week,
+            hour,
+            minute,
+        }
+    }
+
+    fn get_minute_of_week(&self) -> i64 {
+        (self.day_of_week as i64 % 7) * 24 * 60 + self.hour as i64 * 60 + self.minute as i64
+    }
+}
+
+/// Weekly session. If `close` is not after `open`, the session spans the end of the week,
+/// e.g. FX from Sunday 22:00 to Friday 22:00.
+#[derive(Debug, Clone, Copy)]
+pub struct WeeklySession {
+    pub open: WeekTime,
+    pub close: WeekTime,
+}
+
+/// Trading hours of an instrument: weekly sessions (daily breaks are gaps between sessions) and holidays.
+/// A calendar without sessions is open all week except holidays.
+#[derive(Debug, Clone, Default)]
+pub struct TradingCalendar {
+    pub sessions: Vec<WeeklySession>,
+    holidays: BTreeSet<i64>,
+}
+
+impl TradingCalendar {
+    pub fn new(sessions: Vec<WeeklySession>) -> Self {
+        Self {
+            sessions,
+            holidays: BTreeSet::new(),
+        }
+    }
+
+    /// The whole UTC day of the key is closed.
+    pub fn add_holiday(&mut self, day: CandleDateKey) {
+        let day: DateTimeAsMicroseconds = day.into();
+        self.holidays
+            .insert(day.unix_microseconds.div_euclid(MICRO_SECONDS_IN_ONE_DAY));
+    }
+
+    pub fn remove_holiday(&mut self, day: CandleDateKey) -> bool {
+        let day: DateTimeAsMicroseconds = day.into();
+        self.holidays
+            .remove(&day.unix_microseconds.div_euclid(MICRO_SECONDS_IN_ONE_DAY))
+    }
+
+    /// Disjoint ordered intervals of microseconds within `from..to` when the market is open.
+    pub fn get_open_intervals(&self, from: i64, to: i64) -> Vec<(i64, i64)> {
+        let mut result = Vec::new();
+
+        if from >= to {
+            return result;
+        }
+
+        if self.sessions.is_empty() {
+            result.push((from, to));
+        } else {
+            let days = from.div_euclid(MICRO_SECONDS_IN_ONE_DAY);
+            let monday = days - (days + FIRST_DAY_OF_WEEK).rem_euclid(7);
+
+            // Sessions spanning the end of the week start in the week before
+            let mut week_start = (monday - 7) * MICRO_SECONDS_IN_ONE_DAY;
+
+            while week_start < to {
+                for session in &self.sessions {
+                    let open = session.open.get_minute_of_week();
+                    let mut close = session.close.get_minute_of_week();
+
+                    if close <= open {
+                        close += MINUTES_IN_ONE_WEEK;
+                    }
+
+                    let open = (week_start + open * MICRO_SECONDS_IN_ONE_MINUTE).max(from);
+                    let close = (week_start + close * MICRO_SECONDS_IN_ONE_MINUTE).min(to);
+
+                    if open < close {
+                        result.push((open, close));
+                    }
+                }
+
+                week_start += MICRO_SECONDS_IN_ONE_WEEK;
+            }
+
+            result.sort_by_key(|(open, _)| *open);
+            result = merge_intervals(result);
+        }
+
+        if self.holidays.is_empty() {
+            return result;
+        }
+
+        let first_day = from.div_euclid(MICRO_SECONDS_IN_ONE_DAY);
+        let last_day = (to - 1).div_euclid(MICRO_SECONDS_IN_ONE_DAY);
+
+        for holiday in self.holidays.range(first_day..=last_day) {
+            let holiday_from = holiday * MICRO_SECONDS_IN_ONE_DAY;
+            let holiday_to = holiday_from + MICRO_SECONDS_IN_ONE_DAY;
+
+            let mut without_holiday = Vec::with_capacity(result.len() + 1);
+
+            for (open, close) in result {
+                if close <= holiday_from || open >= holiday_to {
+                    without_holiday.push((open, close));
+                    continue;
+                }
+
+                if open < holiday_from {
+                    without_holiday.push((open, holiday_from));
+                }
+
+                if close > holiday_to {
+                    without_holiday.push((holiday_to, close));
+                }
+            }
+
+            result = without_holiday;
+        }
+
+        result
+    }
+
+    pub fn is_open(&self, time: DateTimeAsMicroseconds) -> bool {
+        !self
+            .get_open_intervals(time.unix_microseconds, time.unix_microseconds + 1)
+            .is_empty()
+    }
+
+    /// The period of the candle has at least one open moment.
+    pub fn is_in_session(&self, candle_type: CandleType, date_key: CandleDateKey) -> bool {
+        let (from, to) = get_period(candle_type, date_key);
+        !self.get_open_intervals(from, to).is_empty()
+    }
+
+    /// Keys of the in-session periods within `from..to`.
+    pub fn get_in_session_periods(
+        &self,
+        from: CandleDateKey,
+        to: CandleDateKey,
+        candle_type: CandleType,
+    ) -> Vec<CandleDateKey> {
+        let mut result = Vec::new();
+
+        match get_period_length(candle_type) {
+            Some(period_length) => {
+                let (from, _) = get_period(candle_type, from);
+                let (to, _) = get_period(candle_type, to);
+
+                let mut last_index = None;
+
+                for (open, close) in self.get_open_intervals(from, to) {
+                    let first = open.div_euclid(period_length);
+                    let last = (close - 1).div_euclid(period_length);
+
+                    for index in first..=last {
+                        if last_index.map_or(false, |last_index| index <= last_index) {
+                            continue;
+                        }
+
+                        result.push(
+                            DateTimeAsMicroseconds::new(index * period_length)
+                                .into_candle_date_key(candle_type),
+                        );
+                        last_index = Some(index);
+                    }
+                }
+            }
+            None => {
+                for date_key in from.iter_to(to, candle_type) {
+                    if self.is_in_session(candle_type, date_key) {
+                        result.push(date_key);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Amount of the in-session periods within `from..to`.
+    pub fn get_in_session_candles_amount(
+        &self,
+        from: CandleDateKey,
+        to: CandleDateKey,
+        candle_type: CandleType,
+    ) -> i64 {
+        let period_length = match get_period_length(candle_type) {
+            Some(period_length) => period_length,
+            None => return self.get_in_session_periods(from, to, candle_type).len() as i64,
+        };
+
+        let (from, _) = get_period(candle_type, from);
+        let (to, _) = get_period(candle_type, to);
+
+        let mut result = 0;
+        let mut last_index: Option<i64> = None;
+
+        for (open, close) in self.get_open_intervals(from, to) {
+            let mut first = open.div_euclid(period_length);
+            let last = (close - 1).div_euclid(period_length);
+
+            if let Some(last_index) = last_index {
+                first = first.max(last_index + 1);
+            }
+
+            if first <= last {
+                result += last - first + 1;
+                last_index = Some(last);
+            }
+        }
+
+        result
+    }
+}
+
+fn merge_intervals(intervals: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    let mut result: Vec<(i64, i64)> = Vec::with_capacity(intervals.len());
+
+    for (open, close) in intervals {
+        match result.last_mut() {
+            Some(last) if open <= last.1 => last.1 = last.1.max(close),
+            _ => result.push((open, close)),
+        }
+    }
+
+    result
+}
+
+fn get_period_length(candle_type: CandleType) -> Option<i64> {
+    match candle_type {
+        CandleType::Minute => Some(MICRO_SECONDS_IN_ONE_MINUTE),
+        CandleType::Hour => Some(MICRO_SECONDS_IN_ONE_HOUR),
+        CandleType::Day => Some(MICRO_SECONDS_IN_ONE_DAY),
+        CandleType::Month => None,
+    }
+}
+
+/// Period of the candle in microseconds.
+fn get_period(candle_type: CandleType, date_key: CandleDateKey) -> (i64, i64) {
+    let from: DateTimeAsMicroseconds = date_key.into();
+    let to: DateTimeAsMicroseconds = date_key.get_next_period_date_key(candle_type).into();
+    (from.unix_microseconds, to.unix_microseconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_extensions::date_time::DateTimeAsMicroseconds;
+
+    use crate::{CandleDateKey, CandleType};
+
+    use super::{TradingCalendar, WeekTime, WeeklySession};
+
+    fn fx_calendar() -> TradingCalendar {
+        // Sunday 22:00 - Friday 22:00 with a daily break 21:00 - 21:05 from Monday to Thursday
+        let mut sessions = Vec::new();
+
+        for day in 0..4 {
+            sessions.push(WeeklySession {
+                open: if day == 0 {
+                    WeekTime::new(6, 22, 0)
+                } else {
+                    WeekTime::new(day - 1, 21, 5)
+                },
+                close: WeekTime::new(day, 21, 0),
+            });
+        }
+
+        sessions.push(WeeklySession {
+            open: WeekTime::new(3, 21, 5),
+            close: WeekTime::new(4, 22, 0),
+        });
+
+        TradingCalendar::new(sessions)
+    }
+
+    #[test]
+    fn test_is_open() {
+        let mut calendar = fx_calendar();
+        calendar.add_holiday(CandleDateKey::new(202101010000));
+
+        let is_open = |calendar: &TradingCalendar, value: &str| {
+            calendar.is_open(DateTimeAsMicroseconds::from_str(value).unwrap())
+        };
+
+        // 2021-01-04 is Monday
+        assert!(is_open(&calendar, "2021-01-04T10:00:00"));
+        assert!(!is_open(&calendar, "2021-01-04T21:02:00"));
+        assert!(!is_open(&calendar, "2021-01-02T10:00:00"));
+        assert!(is_open(&calendar, "2021-01-03T22:30:00"));
+        assert!(!is_open(&calendar, "2021-01-03T21:30:00"));
+        assert!(!is_open(&calendar, "2021-01-01T10:00:00"));
+        assert!(is_open(&calendar, "2020-12-31T10:00:00"));
+    }
+
+    #[test]
+    fn test_in_session_periods() {
+        let calendar = fx_calendar();
+
+        let from = CandleDateKey::new(202101010000);
+        let to = CandleDateKey::new(202101080000);
+
+        let days = calendar.get_in_session_periods(from, to, CandleType::Day);
+        let days: Vec<u64> = days.iter().map(|itm| itm.get_value()).collect();
+
+        assert_eq!(
+            vec![
+                202101010000,
+                202101030000,
+                202101040000,
+                202101050000,
+                202101060000,
+                202101070000
+            ],
+            days
+        );
+
+        assert_eq!(
+            6,
+            calendar.get_in_session_candles_amount(from, to, CandleType::Day)
+        );
+
+        // Sunday 22:00 till Monday 21:00 and Monday 21:05 till 24:00
+        let monday_minutes = calendar.get_in_session_candles_amount(
+            CandleDateKey::new(202101030000),
+            CandleDateKey::new(202101050000),
+            CandleType::Minute,
+        );
+        assert_eq!(120 + 21 * 60 + 175, monday_minutes);
+
+        let hours = calendar.get_in_session_candles_amount(
+            CandleDateKey::new(202101040000),
+            CandleDateKey::new(202101050000),
+            CandleType::Hour,
+        );
+        assert_eq!(24, hours);
+
+        let months = calendar.get_in_session_periods(
+            CandleDateKey::new(202012000000),
+            CandleDateKey::new(202102000000),
+            CandleType::Month,
+        );
+        assert_eq!(2, months.len());
+    }
+}
@@ -1,43 +1,19 @@
-use rust_extensions::date_time::{
-    DateTimeAsMicroseconds, MICRO_SECONDS_IN_ONE_DAY, MICRO_SECONDS_IN_ONE_HOUR,
-    MICRO_SECONDS_IN_ONE_MINUTE,
-};
-
-use crate::{CandleDateKey, CandleType};
+use crate::{count_periods, CandleDateKey, CandleType, TradingCalendar};
 
+/// Signed amount of whole periods from `from` to `to`. See [`crate::count_periods`] for partial periods,
+/// custom timeframes and trading sessions.
 pub fn get_candles_amount(from: CandleDateKey, to: CandleDateKey, candle_type: CandleType) -> i64 {
-    let from = from.to_date_time_struct();
-    let to = to.to_date_time_struct();
-    match candle_type {
-        CandleType::Minute => {
-            let from: DateTimeAsMicroseconds = from.try_into().unwrap();
-            let to: DateTimeAsMicroseconds = to.try_into().unwrap();
-            return (to.unix_microseconds - from.unix_microseconds) / MICRO_SECONDS_IN_ONE_MINUTE;
-        }
-        CandleType::Hour => {
-            let from: DateTimeAsMicroseconds = from.try_into().unwrap();
-            let to: DateTimeAsMicroseconds = to.try_into().unwrap();
-            return (to.unix_microseconds - from.unix_microseconds) / MICRO_SECONDS_IN_ONE_HOUR;
-        }
-        CandleType::Day => {
-            let from: DateTimeAsMicroseconds = from.try_into().unwrap();
-            let to: DateTimeAsMicroseconds = to.try_into().unwrap();
-            return (to.unix_microseconds - from.unix_microseconds) / MICRO_SECONDS_IN_ONE_DAY;
-        }
-        CandleType::Month => {
-            if from.year == to.year {
-                return (to.month - from.month) as i64;
-            }
-
-            let result = if from.year < to.year {
-                (to.year - from.year) * 12 + to.month as i32 - from.month as i32
-            } else {
-                0
-            };
-
-            result as i64
-        }
-    }
+    count_periods(from, to, candle_type.into(), None).periods
+}
+
+/// Same as [`get_candles_amount`] counting only the periods the calendar is open.
+pub fn get_trading_candles_amount(
+    from: CandleDateKey,
+    to: CandleDateKey,
+    candle_type: CandleType,
+    calendar: Option<&TradingCalendar>,
+) -> i64 {
+    count_periods(from, to, candle_type.into(), calendar).periods
 }
 
 #[cfg(test)]
@@ -99,4 +75,38 @@ mod tests {
 
         assert_eq!(amount, 30);
     }
+
+    #[test]
+    fn test_trading_candles_amount() {
+        use crate::{TradingCalendar, WeekTime, WeeklySession};
+
+        let calendar = TradingCalendar::new(vec![WeeklySession {
+            open: WeekTime::new(0, 0, 0),
+            close: WeekTime::new(5, 0, 0),
+        }]);
+
+        let from = crate::CandleDateKey::new(202101010000);
+
+        let to = crate::CandleDateKey::new(202101310000);
+
+        let amount =
+            super::get_trading_candles_amount(from, to, crate::CandleType::Day, Some(&calendar));
+
+        assert_eq!(amount, 21);
+
+        let amount = super::get_trading_candles_amount(from, to, crate::CandleType::Day, None);
+
+        assert_eq!(amount, 30);
+    }
+
+    #[test]
+    fn test_reversed_months_calculation() {
+        let from = crate::CandleDateKey::new(202103000000);
+
+        let to = crate::CandleDateKey::new(202012000000);
+
+        let amount = super::get_candles_amount(from, to, crate::CandleType::Month);
+
+        assert_eq!(amount, -3);
+    }
 }